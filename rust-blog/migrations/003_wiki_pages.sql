@@ -0,0 +1,17 @@
+-- Wiki Pages Migration
+
+-- Hierarchical wiki pages, addressed by their slash-separated path
+CREATE TABLE wiki_pages (
+    id UUID PRIMARY KEY,
+    parent_id UUID REFERENCES wiki_pages(id) ON DELETE SET NULL,
+    path VARCHAR(500) NOT NULL UNIQUE,
+    title VARCHAR(255) NOT NULL,
+    content TEXT NOT NULL,
+    source_path VARCHAR(500),
+    nav_order INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_wiki_pages_parent_id ON wiki_pages(parent_id);
+CREATE INDEX idx_wiki_pages_nav_order ON wiki_pages(parent_id, nav_order);
@@ -0,0 +1,23 @@
+-- Wiki Revisions Migration
+
+-- Current revision number, used for optimistic concurrency on edits
+ALTER TABLE wiki_pages ADD COLUMN revision INTEGER NOT NULL DEFAULT 1;
+
+-- Full edit history, one row per saved revision
+CREATE TABLE wiki_page_revisions (
+    id UUID PRIMARY KEY,
+    page_id UUID NOT NULL REFERENCES wiki_pages(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    content TEXT NOT NULL,
+    edited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    edit_summary TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE(page_id, revision)
+);
+
+CREATE INDEX idx_wiki_page_revisions_page_id ON wiki_page_revisions(page_id, revision DESC);
+
+-- Seed the history of pages created before revisions existed
+INSERT INTO wiki_page_revisions (id, page_id, revision, title, content, edit_summary, created_at)
+SELECT gen_random_uuid(), id, 1, title, content, 'Initial import', updated_at FROM wiki_pages;
@@ -0,0 +1,21 @@
+-- Multilingual Content Migration
+
+-- Every post and wiki page is written in one language. Translations of the
+-- same content share a translation_group.
+ALTER TABLE posts ADD COLUMN lang VARCHAR(10) NOT NULL DEFAULT 'en';
+ALTER TABLE posts ADD COLUMN translation_group UUID;
+UPDATE posts SET translation_group = id;
+ALTER TABLE posts ALTER COLUMN translation_group SET NOT NULL;
+
+ALTER TABLE wiki_pages ADD COLUMN lang VARCHAR(10) NOT NULL DEFAULT 'en';
+ALTER TABLE wiki_pages ADD COLUMN translation_group UUID;
+UPDATE wiki_pages SET translation_group = id;
+ALTER TABLE wiki_pages ALTER COLUMN translation_group SET NOT NULL;
+
+-- The same path can now exist once per language
+ALTER TABLE wiki_pages DROP CONSTRAINT wiki_pages_path_key;
+ALTER TABLE wiki_pages ADD CONSTRAINT wiki_pages_lang_path_key UNIQUE (lang, path);
+
+CREATE INDEX idx_posts_lang ON posts(lang);
+CREATE INDEX idx_posts_translation_group ON posts(translation_group);
+CREATE INDEX idx_wiki_pages_translation_group ON wiki_pages(translation_group);
@@ -0,0 +1,16 @@
+-- Page Discussions Migration
+
+-- Links a post or wiki page to the forum topic where it is discussed
+CREATE TABLE discussion_links (
+    id UUID PRIMARY KEY,
+    content_type VARCHAR(20) NOT NULL CHECK (content_type IN ('post', 'wiki')),
+    content_id UUID NOT NULL,
+    topic_id UUID NOT NULL UNIQUE REFERENCES topics(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE(content_type, content_id)
+);
+
+-- Discussion topics are created in their own category
+INSERT INTO categories (id, name, description, color, icon, sort_order) VALUES
+(gen_random_uuid(), 'Page Discussions', 'Questions and comments about blog posts and wiki pages', '#0EA5E9', '📖', 6)
+ON CONFLICT (name) DO NOTHING;
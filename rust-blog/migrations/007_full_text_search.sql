@@ -0,0 +1,47 @@
+-- Full-Text Search Migration
+
+-- array_to_string is only STABLE, so generated columns cannot call it directly
+CREATE OR REPLACE FUNCTION tags_to_text(tags TEXT[])
+RETURNS TEXT AS $$
+    SELECT coalesce(array_to_string(tags, ' '), '');
+$$ LANGUAGE sql IMMUTABLE;
+
+-- Weighted search vectors: titles rank above summaries and tags, which rank
+-- above body text. English content is stemmed; other languages are indexed
+-- word by word.
+ALTER TABLE posts ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    CASE WHEN lang = 'en' THEN
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(summary, '')), 'B') ||
+        setweight(to_tsvector('english', tags_to_text(tags)), 'B') ||
+        setweight(to_tsvector('english', coalesce(content, '')), 'C')
+    ELSE
+        setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(summary, '')), 'B') ||
+        setweight(to_tsvector('simple', tags_to_text(tags)), 'B') ||
+        setweight(to_tsvector('simple', coalesce(content, '')), 'C')
+    END
+) STORED;
+
+ALTER TABLE topics ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A')
+) STORED;
+
+ALTER TABLE replies ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(content, '')), 'C')
+) STORED;
+
+ALTER TABLE wiki_pages ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    CASE WHEN lang = 'en' THEN
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(content, '')), 'C')
+    ELSE
+        setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(content, '')), 'C')
+    END
+) STORED;
+
+CREATE INDEX idx_posts_search_vector ON posts USING GIN (search_vector);
+CREATE INDEX idx_topics_search_vector ON topics USING GIN (search_vector);
+CREATE INDEX idx_replies_search_vector ON replies USING GIN (search_vector);
+CREATE INDEX idx_wiki_pages_search_vector ON wiki_pages USING GIN (search_vector);
@@ -0,0 +1,62 @@
+-- CJK Search Migration
+
+-- Postgres' parsers treat a run of Chinese characters as a single token, so
+-- the server segments CJK text into words and stores them, space-separated,
+-- in these columns. They are empty for text without CJK characters.
+ALTER TABLE posts ADD COLUMN title_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE posts ADD COLUMN summary_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE posts ADD COLUMN content_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE topics ADD COLUMN title_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE replies ADD COLUMN content_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE wiki_pages ADD COLUMN title_words TEXT NOT NULL DEFAULT '';
+ALTER TABLE wiki_pages ADD COLUMN content_words TEXT NOT NULL DEFAULT '';
+
+-- Rebuild the search vectors to include the segmented words, weighted like
+-- the text they come from so English and Chinese matches rank alike
+ALTER TABLE posts DROP COLUMN search_vector;
+ALTER TABLE posts ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    CASE WHEN lang = 'en' THEN
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(summary, '')), 'B') ||
+        setweight(to_tsvector('english', tags_to_text(tags)), 'B') ||
+        setweight(to_tsvector('english', coalesce(content, '')), 'C')
+    ELSE
+        setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(summary, '')), 'B') ||
+        setweight(to_tsvector('simple', tags_to_text(tags)), 'B') ||
+        setweight(to_tsvector('simple', coalesce(content, '')), 'C')
+    END ||
+    setweight(to_tsvector('simple', title_words), 'A') ||
+    setweight(to_tsvector('simple', summary_words), 'B') ||
+    setweight(to_tsvector('simple', content_words), 'C')
+) STORED;
+
+ALTER TABLE topics DROP COLUMN search_vector;
+ALTER TABLE topics ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('simple', title_words), 'A')
+) STORED;
+
+ALTER TABLE replies DROP COLUMN search_vector;
+ALTER TABLE replies ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(content, '')), 'C') ||
+    setweight(to_tsvector('simple', content_words), 'C')
+) STORED;
+
+ALTER TABLE wiki_pages DROP COLUMN search_vector;
+ALTER TABLE wiki_pages ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (
+    CASE WHEN lang = 'en' THEN
+        setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(content, '')), 'C')
+    ELSE
+        setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+        setweight(to_tsvector('simple', coalesce(content, '')), 'C')
+    END ||
+    setweight(to_tsvector('simple', title_words), 'A') ||
+    setweight(to_tsvector('simple', content_words), 'C')
+) STORED;
+
+CREATE INDEX idx_posts_search_vector ON posts USING GIN (search_vector);
+CREATE INDEX idx_topics_search_vector ON topics USING GIN (search_vector);
+CREATE INDEX idx_replies_search_vector ON replies USING GIN (search_vector);
+CREATE INDEX idx_wiki_pages_search_vector ON wiki_pages USING GIN (search_vector);
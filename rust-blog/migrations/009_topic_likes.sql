@@ -0,0 +1,63 @@
+-- Topic Likes Migration
+
+ALTER TABLE topics ADD COLUMN likes_count BIGINT NOT NULL DEFAULT 0;
+
+-- Likes on a topic itself, separate from likes on its replies
+CREATE TABLE topic_likes (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    topic_id UUID NOT NULL REFERENCES topics(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE(user_id, topic_id)
+);
+
+CREATE INDEX idx_topic_likes_topic_id ON topic_likes(topic_id);
+CREATE INDEX idx_topic_likes_user_id ON topic_likes(user_id);
+
+-- Function to update topic like counters, mirroring update_like_counters
+CREATE OR REPLACE FUNCTION update_topic_like_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE topics
+        SET likes_count = likes_count + 1
+        WHERE id = NEW.topic_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received + 1,
+            reputation = reputation + 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM topics WHERE id = NEW.topic_id);
+
+        RETURN NEW;
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE topics
+        SET likes_count = likes_count - 1
+        WHERE id = OLD.topic_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received - 1,
+            reputation = reputation - 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM topics WHERE id = OLD.topic_id);
+
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_update_topic_like_counters
+    AFTER INSERT OR DELETE ON topic_likes
+    FOR EACH ROW
+    EXECUTE FUNCTION update_topic_like_counters();
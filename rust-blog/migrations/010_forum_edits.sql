@@ -0,0 +1,17 @@
+-- Forum Edits Migration
+
+-- When and by whom a topic title or reply was last edited; updated_at also
+-- moves on new replies, so it can't tell edits apart
+ALTER TABLE topics
+    ADD COLUMN edited_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN edited_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE replies
+    ADD COLUMN edited_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN edited_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- Deleting a reply keeps the replies that answered it
+ALTER TABLE replies
+    DROP CONSTRAINT replies_reply_to_id_fkey,
+    ADD CONSTRAINT replies_reply_to_id_fkey
+        FOREIGN KEY (reply_to_id) REFERENCES replies(id) ON DELETE SET NULL;
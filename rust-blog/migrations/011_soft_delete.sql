@@ -0,0 +1,143 @@
+-- Soft Delete Migration
+
+-- Deleted posts, topics and replies go to the trash, from which moderators
+-- can restore them until they are purged
+ALTER TABLE posts
+    ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN deleted_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE topics
+    ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN deleted_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE replies
+    ADD COLUMN deleted_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN deleted_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_posts_deleted_at ON posts(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_topics_deleted_at ON topics(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX idx_replies_deleted_at ON replies(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Counters only include live rows: soft deleting a row counts as removing
+-- it, restoring it as adding it back, and purging a row that is already in
+-- the trash changes nothing
+
+CREATE OR REPLACE FUNCTION update_topic_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        -- Update topic reply count and last reply info
+        UPDATE topics
+        SET replies_count = replies_count + 1,
+            last_reply_at = NEW.created_at,
+            last_reply_user_id = NEW.user_id,
+            updated_at = NOW()
+        WHERE id = NEW.topic_id;
+
+        -- Update category post count
+        UPDATE categories
+        SET posts_count = posts_count + 1,
+            last_post_at = NEW.created_at
+        WHERE id = (SELECT category_id FROM topics WHERE id = NEW.topic_id);
+
+        -- Update user profile
+        UPDATE user_profiles
+        SET replies_count = replies_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE' AND OLD.deleted_at IS NULL)
+       OR (TG_OP = 'UPDATE' AND OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+        UPDATE topics
+        SET replies_count = replies_count - 1
+        WHERE id = OLD.topic_id;
+
+        UPDATE categories
+        SET posts_count = posts_count - 1
+        WHERE id = (SELECT category_id FROM topics WHERE id = OLD.topic_id);
+
+        UPDATE user_profiles
+        SET replies_count = replies_count - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        RETURN OLD;
+    ELSIF TG_OP = 'UPDATE' AND OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+        UPDATE topics
+        SET replies_count = replies_count + 1
+        WHERE id = NEW.topic_id;
+
+        UPDATE categories
+        SET posts_count = posts_count + 1
+        WHERE id = (SELECT category_id FROM topics WHERE id = NEW.topic_id);
+
+        UPDATE user_profiles
+        SET replies_count = replies_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- A topic's replies stay counted in its category while the topic is live,
+-- so they leave and come back with it
+CREATE OR REPLACE FUNCTION update_category_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE categories
+        SET topics_count = topics_count + 1
+        WHERE id = NEW.category_id;
+
+        -- Update user profile
+        UPDATE user_profiles
+        SET topics_count = topics_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE' AND OLD.deleted_at IS NULL)
+       OR (TG_OP = 'UPDATE' AND OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+        UPDATE categories
+        SET topics_count = topics_count - 1,
+            posts_count = posts_count - OLD.replies_count
+        WHERE id = OLD.category_id;
+
+        UPDATE user_profiles
+        SET topics_count = topics_count - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        RETURN OLD;
+    ELSIF TG_OP = 'UPDATE' AND OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+        UPDATE categories
+        SET topics_count = topics_count + 1,
+            posts_count = posts_count + NEW.replies_count
+        WHERE id = NEW.category_id;
+
+        UPDATE user_profiles
+        SET topics_count = topics_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER trigger_update_topic_counters ON replies;
+CREATE TRIGGER trigger_update_topic_counters
+    AFTER INSERT OR DELETE OR UPDATE OF deleted_at ON replies
+    FOR EACH ROW
+    EXECUTE FUNCTION update_topic_counters();
+
+DROP TRIGGER trigger_update_category_counters ON topics;
+CREATE TRIGGER trigger_update_category_counters
+    AFTER INSERT OR DELETE OR UPDATE OF deleted_at ON topics
+    FOR EACH ROW
+    EXECUTE FUNCTION update_category_counters();
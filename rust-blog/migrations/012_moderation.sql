@@ -0,0 +1,106 @@
+-- Moderation Migration
+
+-- is_pinned keeps a topic at the top of its category; a global pin also
+-- keeps it at the top of the list of all topics
+ALTER TABLE topics
+    ADD COLUMN is_pinned_globally BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN lock_reason TEXT;
+
+CREATE INDEX idx_topics_pinned_globally ON topics(is_pinned_globally DESC, last_reply_at DESC);
+
+-- Moving a live topic to another category takes its replies' posts along;
+-- the soft delete branches are as in 011_soft_delete.sql
+CREATE OR REPLACE FUNCTION update_category_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE categories
+        SET topics_count = topics_count + 1
+        WHERE id = NEW.category_id;
+
+        -- Update user profile
+        UPDATE user_profiles
+        SET topics_count = topics_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    ELSIF (TG_OP = 'DELETE' AND OLD.deleted_at IS NULL)
+       OR (TG_OP = 'UPDATE' AND OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+        UPDATE categories
+        SET topics_count = topics_count - 1,
+            posts_count = posts_count - OLD.replies_count
+        WHERE id = OLD.category_id;
+
+        UPDATE user_profiles
+        SET topics_count = topics_count - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        RETURN OLD;
+    ELSIF TG_OP = 'UPDATE' AND OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+        UPDATE categories
+        SET topics_count = topics_count + 1,
+            posts_count = posts_count + NEW.replies_count
+        WHERE id = NEW.category_id;
+
+        UPDATE user_profiles
+        SET topics_count = topics_count + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        RETURN NEW;
+    ELSIF TG_OP = 'UPDATE' AND NEW.deleted_at IS NULL AND OLD.category_id <> NEW.category_id THEN
+        UPDATE categories
+        SET topics_count = topics_count - 1,
+            posts_count = posts_count - OLD.replies_count
+        WHERE id = OLD.category_id;
+
+        UPDATE categories
+        SET topics_count = topics_count + 1,
+            posts_count = posts_count + NEW.replies_count
+        WHERE id = NEW.category_id;
+
+        RETURN NEW;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Moving a live reply to another topic (merging or splitting topics) moves
+-- its count between the topics and, when they differ, their categories.
+-- Replies of a topic in the trash aren't counted in its category.
+CREATE OR REPLACE FUNCTION move_reply_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.deleted_at IS NULL AND OLD.topic_id <> NEW.topic_id THEN
+        UPDATE topics
+        SET replies_count = replies_count - 1
+        WHERE id = OLD.topic_id;
+
+        UPDATE topics
+        SET replies_count = replies_count + 1
+        WHERE id = NEW.topic_id;
+
+        UPDATE categories
+        SET posts_count = posts_count - 1
+        WHERE id = (SELECT category_id FROM topics WHERE id = OLD.topic_id AND deleted_at IS NULL);
+
+        UPDATE categories
+        SET posts_count = posts_count + 1
+        WHERE id = (SELECT category_id FROM topics WHERE id = NEW.topic_id AND deleted_at IS NULL);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER trigger_update_category_counters ON topics;
+CREATE TRIGGER trigger_update_category_counters
+    AFTER INSERT OR DELETE OR UPDATE OF deleted_at, category_id ON topics
+    FOR EACH ROW
+    EXECUTE FUNCTION update_category_counters();
+
+CREATE TRIGGER trigger_move_reply_counters
+    AFTER UPDATE OF topic_id ON replies
+    FOR EACH ROW
+    EXECUTE FUNCTION move_reply_counters();
@@ -0,0 +1,39 @@
+-- Reports Migration
+
+-- Members flag posts, topics, replies and users for moderators to review.
+-- A member has at most one open report per target.
+CREATE TABLE reports (
+    id UUID PRIMARY KEY,
+    reporter_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content_type VARCHAR(20) NOT NULL CHECK (content_type IN ('post', 'topic', 'reply', 'user')),
+    content_id UUID NOT NULL,
+    reason VARCHAR(20) NOT NULL CHECK (reason IN ('spam', 'abuse', 'off_topic', 'other')),
+    details TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'dismissed', 'actioned')),
+    -- What the moderator did: dismiss, hide, warn or suspend
+    resolution VARCHAR(20),
+    resolved_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_reports_open_reporter ON reports(reporter_id, content_type, content_id) WHERE status = 'open';
+CREATE INDEX idx_reports_target ON reports(content_type, content_id);
+CREATE INDEX idx_reports_open ON reports(created_at) WHERE status = 'open';
+
+-- Warnings moderators have given members
+CREATE TABLE user_warnings (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    moderator_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    report_id UUID REFERENCES reports(id) ON DELETE SET NULL,
+    message TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_user_warnings_user ON user_warnings(user_id, created_at DESC);
+
+-- Suspended members can't sign in or post until suspended_until
+ALTER TABLE users
+    ADD COLUMN suspended_until TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN suspension_reason TEXT;
@@ -0,0 +1,25 @@
+-- Restrictions Migration
+
+-- Suspended members can't sign in; silenced members can read but not post.
+-- Both end on their own and record who issued them.
+ALTER TABLE users
+    ADD COLUMN suspended_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    ADD COLUMN silenced_until TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN silence_reason TEXT,
+    ADD COLUMN silenced_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- Bans on an IP address or range (`ip`, as understood by inet) or on an
+-- email domain and its subdomains (`email_domain`, lowercase). Without
+-- expires_at a ban is permanent.
+CREATE TABLE bans (
+    id UUID PRIMARY KEY,
+    ban_type VARCHAR(20) NOT NULL CHECK (ban_type IN ('ip', 'email_domain')),
+    value VARCHAR(255) NOT NULL,
+    reason TEXT NOT NULL,
+    banned_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (ban_type, value)
+);
+
+CREATE INDEX idx_bans_type ON bans(ban_type);
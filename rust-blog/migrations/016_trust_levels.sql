@@ -0,0 +1,35 @@
+-- Trust Levels Migration
+
+-- trust_level is earned from activity and recomputed by a background job;
+-- an admin's trust_level_override takes its place while set. A day counts
+-- as visited the first time the member is seen on it.
+ALTER TABLE user_profiles
+    ADD COLUMN trust_level SMALLINT NOT NULL DEFAULT 0 CHECK (trust_level BETWEEN 0 AND 4),
+    ADD COLUMN trust_level_override SMALLINT CHECK (trust_level_override BETWEEN 0 AND 4),
+    ADD COLUMN days_visited INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN last_visited_on DATE;
+
+-- The trust level needed to start topics in a category
+ALTER TABLE categories
+    ADD COLUMN min_trust_level SMALLINT NOT NULL DEFAULT 0 CHECK (min_trust_level BETWEEN 0 AND 4);
+
+-- Profiles were only created for the users present in 002_forum_tables.sql,
+-- so the counters and trust levels of everyone since had nowhere to go
+INSERT INTO user_profiles (user_id, display_name, created_at, updated_at)
+SELECT id, username, created_at, created_at FROM users
+ON CONFLICT (user_id) DO NOTHING;
+
+CREATE OR REPLACE FUNCTION create_user_profile()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO user_profiles (user_id, display_name, created_at, updated_at)
+    VALUES (NEW.id, NEW.username, NEW.created_at, NEW.created_at)
+    ON CONFLICT (user_id) DO NOTHING;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_create_user_profile
+    AFTER INSERT ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION create_user_profile();
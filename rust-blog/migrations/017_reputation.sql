@@ -0,0 +1,185 @@
+-- Reputation Ledger Migration
+
+-- Every change to a member's reputation, with what caused it. An event is
+-- removed again when its cause is undone (an unlike, a solution unaccepted,
+-- a topic unfeatured), so reputation is always the sum of the events.
+CREATE TABLE reputation_events (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_type VARCHAR(20) NOT NULL CHECK (event_type IN ('like_received', 'solution_accepted', 'topic_featured', 'penalty')),
+    points INTEGER NOT NULL,
+    source_type VARCHAR(20) CHECK (source_type IN ('reply', 'topic')),
+    source_id UUID,
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    reason TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_reputation_events_user ON reputation_events(user_id, created_at DESC);
+CREATE INDEX idx_reputation_events_source ON reputation_events(source_type, source_id);
+CREATE UNIQUE INDEX idx_reputation_events_like ON reputation_events(source_type, source_id, actor_id)
+    WHERE event_type = 'like_received';
+CREATE UNIQUE INDEX idx_reputation_events_solution ON reputation_events(source_id)
+    WHERE event_type = 'solution_accepted';
+CREATE UNIQUE INDEX idx_reputation_events_featured ON reputation_events(source_id)
+    WHERE event_type = 'topic_featured';
+
+-- user_profiles.reputation is kept as the sum of the ledger
+CREATE OR REPLACE FUNCTION update_reputation()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE user_profiles
+        SET reputation = reputation + NEW.points,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+        RETURN NEW;
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE user_profiles
+        SET reputation = reputation - OLD.points,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_update_reputation
+    AFTER INSERT OR DELETE ON reputation_events
+    FOR EACH ROW
+    EXECUTE FUNCTION update_reputation();
+
+-- Likes record their reputation in the ledger rather than bumping it; the
+-- counters are as in 002_forum_tables.sql and 009_topic_likes.sql
+CREATE OR REPLACE FUNCTION update_like_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE replies
+        SET likes_count = likes_count + 1
+        WHERE id = NEW.reply_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received + 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM replies WHERE id = NEW.reply_id);
+
+        INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, actor_id, created_at)
+        SELECT gen_random_uuid(), r.user_id, 'like_received', 1, 'reply', r.id, NEW.user_id, NEW.created_at
+        FROM replies r WHERE r.id = NEW.reply_id
+        ON CONFLICT DO NOTHING;
+
+        RETURN NEW;
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE replies
+        SET likes_count = likes_count - 1
+        WHERE id = OLD.reply_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received - 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM replies WHERE id = OLD.reply_id);
+
+        DELETE FROM reputation_events
+        WHERE event_type = 'like_received' AND source_type = 'reply'
+          AND source_id = OLD.reply_id AND actor_id = OLD.user_id;
+
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION update_topic_like_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        UPDATE topics
+        SET likes_count = likes_count + 1
+        WHERE id = NEW.topic_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given + 1,
+            updated_at = NOW()
+        WHERE user_id = NEW.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received + 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM topics WHERE id = NEW.topic_id);
+
+        INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, actor_id, created_at)
+        SELECT gen_random_uuid(), t.user_id, 'like_received', 1, 'topic', t.id, NEW.user_id, NEW.created_at
+        FROM topics t WHERE t.id = NEW.topic_id
+        ON CONFLICT DO NOTHING;
+
+        RETURN NEW;
+    ELSIF TG_OP = 'DELETE' THEN
+        UPDATE topics
+        SET likes_count = likes_count - 1
+        WHERE id = OLD.topic_id;
+
+        UPDATE user_profiles
+        SET likes_given = likes_given - 1,
+            updated_at = NOW()
+        WHERE user_id = OLD.user_id;
+
+        UPDATE user_profiles
+        SET likes_received = likes_received - 1,
+            updated_at = NOW()
+        WHERE user_id = (SELECT user_id FROM topics WHERE id = OLD.topic_id);
+
+        DELETE FROM reputation_events
+        WHERE event_type = 'like_received' AND source_type = 'topic'
+          AND source_id = OLD.topic_id AND actor_id = OLD.user_id;
+
+        RETURN OLD;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Fill the ledger from the likes and solutions so far. Likes on your own
+-- posts and answers to your own topics have never been possible or earned
+-- anything, so they are left out.
+INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, actor_id, created_at)
+SELECT gen_random_uuid(), r.user_id, 'like_received', 1, 'reply', r.id, l.user_id, l.created_at
+FROM likes l JOIN replies r ON r.id = l.reply_id
+WHERE r.user_id <> l.user_id;
+
+INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, actor_id, created_at)
+SELECT gen_random_uuid(), t.user_id, 'like_received', 1, 'topic', t.id, l.user_id, l.created_at
+FROM topic_likes l JOIN topics t ON t.id = l.topic_id
+WHERE t.user_id <> l.user_id;
+
+INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, created_at)
+SELECT gen_random_uuid(), r.user_id, 'solution_accepted', 15, 'reply', r.id, r.updated_at
+FROM replies r JOIN topics t ON t.id = r.topic_id
+WHERE r.is_solution AND r.user_id <> t.user_id;
+
+-- The backfill went through the trigger on top of the old counts, so start
+-- the sums over
+UPDATE user_profiles p
+SET reputation = COALESCE((SELECT SUM(points) FROM reputation_events e WHERE e.user_id = p.user_id), 0);
+
+-- Badges a member has been awarded; the badges and their rules are defined
+-- in src/badges.rs
+CREATE TABLE user_badges (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    badge VARCHAR(50) NOT NULL,
+    awarded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, badge)
+);
+
+CREATE INDEX idx_user_badges_badge ON user_badges(badge, awarded_at DESC);
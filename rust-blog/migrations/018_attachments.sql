@@ -0,0 +1,31 @@
+-- Attachments Migration
+
+-- Uploaded content, stored once per SHA-256 in the blob store (see
+-- src/blob_store.rs). last_uploaded_at keeps a blob that was just uploaded
+-- again from being collected while its attachment is being recorded.
+CREATE TABLE blobs (
+    sha256 VARCHAR(64) PRIMARY KEY,
+    size BIGINT NOT NULL,
+    mime_type VARCHAR(100) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_uploaded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+-- An upload of a blob under a file name, linked to the post or reply it is
+-- attached to. Uploads are unlinked while the reply they are for is still
+-- being written, and are removed if they stay that way.
+CREATE TABLE attachments (
+    id UUID PRIMARY KEY,
+    sha256 VARCHAR(64) NOT NULL REFERENCES blobs(sha256),
+    filename VARCHAR(255) NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    post_id UUID REFERENCES posts(id) ON DELETE CASCADE,
+    reply_id UUID REFERENCES replies(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CHECK (post_id IS NULL OR reply_id IS NULL)
+);
+
+CREATE INDEX idx_attachments_post ON attachments(post_id) WHERE post_id IS NOT NULL;
+CREATE INDEX idx_attachments_reply ON attachments(reply_id) WHERE reply_id IS NOT NULL;
+CREATE INDEX idx_attachments_sha256 ON attachments(sha256);
+CREATE INDEX idx_attachments_unlinked ON attachments(created_at) WHERE post_id IS NULL AND reply_id IS NULL;
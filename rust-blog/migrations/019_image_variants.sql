@@ -0,0 +1,17 @@
+-- Image Variants Migration
+
+-- The size of image blobs
+ALTER TABLE blobs ADD COLUMN width INTEGER;
+ALTER TABLE blobs ADD COLUMN height INTEGER;
+
+-- Smaller versions of large images, stored in the blob store next to the
+-- image they were made from (see src/images.rs)
+CREATE TABLE blob_variants (
+    sha256 VARCHAR(64) NOT NULL REFERENCES blobs(sha256) ON DELETE CASCADE,
+    variant VARCHAR(20) NOT NULL,
+    mime_type VARCHAR(100) NOT NULL,
+    size BIGINT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    PRIMARY KEY (sha256, variant)
+);
@@ -0,0 +1,14 @@
+-- Challenge Files Migration
+
+-- What was uploaded, for showing challenge files: the MD5 next to the
+-- SHA-256, a file(1)-style description and, for zip and tar archives, the
+-- files inside (see src/magic.rs and src/archives.rs)
+ALTER TABLE blobs ADD COLUMN md5 VARCHAR(32);
+ALTER TABLE blobs ADD COLUMN description TEXT;
+ALTER TABLE blobs ADD COLUMN archive JSONB;
+
+-- Challenge files are kept byte for byte and always downloaded
+ALTER TABLE attachments
+    ADD COLUMN kind VARCHAR(20) NOT NULL DEFAULT 'file' CHECK (kind IN ('file', 'challenge'));
+
+CREATE INDEX idx_attachments_challenge ON attachments(post_id) WHERE kind = 'challenge';
@@ -0,0 +1,19 @@
+-- Notifications Migration
+
+-- Something that happened for a member to see, such as being mentioned.
+-- actor_id is who did it; topic_id and reply_id are where.
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    notification_type VARCHAR(30) NOT NULL CHECK (notification_type IN ('mention')),
+    actor_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    topic_id UUID REFERENCES topics(id) ON DELETE CASCADE,
+    reply_id UUID REFERENCES replies(id) ON DELETE CASCADE,
+    read_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_notifications_user ON notifications(user_id, created_at DESC);
+-- For limiting how many people a member mentions an hour
+CREATE INDEX idx_notifications_mentions_by ON notifications(actor_id, created_at)
+    WHERE notification_type = 'mention';
@@ -0,0 +1,26 @@
+-- Notification Center Migration
+
+-- Besides mentions: replies to a member's topics, replies to their posts,
+-- likes, accepted solutions and moderator actions
+ALTER TABLE notifications DROP CONSTRAINT notifications_notification_type_check;
+ALTER TABLE notifications ADD CONSTRAINT notifications_notification_type_check
+    CHECK (notification_type IN ('mention', 'reply', 'quote', 'like', 'solution', 'moderation'));
+
+-- count is how many likes were batched into one notification; message says
+-- what a moderator did
+ALTER TABLE notifications ADD COLUMN count INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE notifications ADD COLUMN message TEXT;
+
+CREATE INDEX idx_notifications_unread ON notifications(user_id) WHERE read_at IS NULL;
+-- Likes on the same topic or reply add to its unread like notification
+CREATE UNIQUE INDEX idx_notifications_likes ON notifications(user_id, (COALESCE(reply_id, topic_id)))
+    WHERE notification_type = 'like' AND read_at IS NULL;
+
+-- The kinds of notification a member turned off or back on; without a row
+-- a kind is on
+CREATE TABLE notification_preferences (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    notification_type VARCHAR(30) NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    PRIMARY KEY (user_id, notification_type)
+);
@@ -0,0 +1,8 @@
+-- Hidden By Reports Migration
+
+-- Content hidden automatically because enough members reported it. It waits
+-- in the trash for a moderator and isn't purged until one hides it for good;
+-- deleted_by can't tell it apart, as it is also cleared when a user is deleted
+ALTER TABLE posts ADD COLUMN hidden_by_reports BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE topics ADD COLUMN hidden_by_reports BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE replies ADD COLUMN hidden_by_reports BOOLEAN NOT NULL DEFAULT false;
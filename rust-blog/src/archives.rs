@@ -0,0 +1,173 @@
+//! Listings of the files in uploaded zip and tar archives, read from their
+//! headers without writing anything out, so challenge files show what is
+//! inside before they are downloaded.
+
+use crate::models::{ArchiveEntry, ArchiveListing};
+use std::io::{Cursor, Read};
+
+/// Listings stop here and are marked truncated.
+const MAX_ENTRIES: usize = 1000;
+/// How much of a compressed tarball is decompressed to read its headers,
+/// so a small upload can't make the server unpack gigabytes.
+const MAX_UNPACKED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// The files in a zip, tar or gzipped tar archive; `None` for anything else
+/// or an archive that can't be read.
+pub fn list(bytes: &[u8]) -> Option<ArchiveListing> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        list_zip(bytes)
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        let unpacked = flate2::read::GzDecoder::new(bytes).take(MAX_UNPACKED_BYTES);
+        list_tar(unpacked)
+    } else if bytes.get(257..262) == Some(b"ustar") {
+        list_tar(bytes)
+    } else {
+        None
+    }
+}
+
+// Only the central directory is read; nothing is decompressed
+fn list_zip(bytes: &[u8]) -> Option<ArchiveListing> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).ok()?;
+    let mut listing = ArchiveListing { entries: Vec::new(), truncated: archive.len() > MAX_ENTRIES };
+    for i in 0..archive.len().min(MAX_ENTRIES) {
+        let Ok(file) = archive.by_index_raw(i) else {
+            listing.truncated = true;
+            break;
+        };
+        listing.entries.push(ArchiveEntry {
+            path: file.name().to_string(),
+            size: file.size() as i64,
+            is_dir: file.is_dir(),
+            encrypted: file.encrypted(),
+        });
+    }
+    Some(listing)
+}
+
+fn list_tar(reader: impl Read) -> Option<ArchiveListing> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().ok()?;
+    let mut listing = ArchiveListing { entries: Vec::new(), truncated: false };
+    for entry in entries {
+        // A gzipped file that isn't a tarball fails on its first header
+        let Ok(entry) = entry else {
+            if listing.entries.is_empty() {
+                return None;
+            }
+            listing.truncated = true;
+            break;
+        };
+        if listing.entries.len() == MAX_ENTRIES {
+            listing.truncated = true;
+            break;
+        }
+        let Ok(path) = entry.path() else {
+            continue;
+        };
+        listing.entries.push(ArchiveEntry {
+            path: path.to_string_lossy().into_owned(),
+            size: entry.header().size().unwrap_or(0) as i64,
+            is_dir: entry.header().entry_type().is_dir(),
+            encrypted: false,
+        });
+    }
+    Some(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list, MAX_ENTRIES};
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    fn zip(build: impl FnOnce(&mut ZipWriter<std::io::Cursor<Vec<u8>>>, SimpleFileOptions)) -> Vec<u8> {
+        let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        build(&mut writer, SimpleFileOptions::default().compression_method(CompressionMethod::Stored));
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_header(path: &str, size: u64, entry_type: tar::EntryType) -> tar::Header {
+        let mut header = tar::Header::new_ustar();
+        header.set_path(path).unwrap();
+        header.set_size(size);
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_cksum();
+        header
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn zip_listings_include_nested_entries() {
+        let bytes = zip(|writer, options| {
+            writer.add_directory("src/bin/", options).unwrap();
+            writer.start_file("src/bin/solve.py", options).unwrap();
+            writer.write_all(b"print('flag')\n").unwrap();
+            writer.start_file("README", options).unwrap();
+        });
+
+        let listing = list(&bytes).unwrap();
+        assert!(!listing.truncated);
+        let entries: Vec<(&str, i64, bool)> =
+            listing.entries.iter().map(|e| (e.path.as_str(), e.size, e.is_dir)).collect();
+        assert_eq!(entries, [("src/bin/", 0, true), ("src/bin/solve.py", 14, false), ("README", 0, false)]);
+    }
+
+    #[test]
+    fn zip_listings_stop_at_the_entry_limit() {
+        let bytes = zip(|writer, options| {
+            for i in 0..=MAX_ENTRIES {
+                writer.start_file(format!("{}.txt", i), options).unwrap();
+            }
+        });
+
+        let listing = list(&bytes).unwrap();
+        assert!(listing.truncated);
+        assert_eq!(listing.entries.len(), MAX_ENTRIES);
+    }
+
+    #[test]
+    fn tar_gz_listings_include_nested_entries() {
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&tar_header("firmware/", 0, tar::EntryType::Directory), std::io::empty()).unwrap();
+        let content = b"\x7fELF";
+        builder
+            .append(&tar_header("firmware/bin/busybox", content.len() as u64, tar::EntryType::Regular), &content[..])
+            .unwrap();
+        let bytes = gzip(&builder.into_inner().unwrap());
+
+        let listing = list(&bytes).unwrap();
+        assert!(!listing.truncated);
+        let entries: Vec<(&str, i64, bool)> =
+            listing.entries.iter().map(|e| (e.path.as_str(), e.size, e.is_dir)).collect();
+        assert_eq!(entries, [("firmware/", 0, true), ("firmware/bin/busybox", 4, false)]);
+    }
+
+    // Only the header is read, so an entry larger than the upload is listed
+    // with its size, and what follows it can't be
+    #[test]
+    fn tar_gz_listings_report_oversized_entries_without_reading_them() {
+        let header = tar_header("disk.img", 5 << 30, tar::EntryType::Regular);
+        let bytes = gzip(header.as_bytes());
+
+        let listing = list(&bytes).unwrap();
+        assert_eq!(listing.entries.len(), 1);
+        assert_eq!(listing.entries[0].path, "disk.img");
+        assert_eq!(listing.entries[0].size, 5 << 30);
+        assert!(listing.truncated);
+    }
+
+    #[test]
+    fn other_content_is_not_listed() {
+        assert!(list(b"").is_none());
+        assert!(list(b"PK\x03\x04 not really a zip").is_none());
+        assert!(list(&gzip(b"not a tarball, just some gzipped text")).is_none());
+    }
+}
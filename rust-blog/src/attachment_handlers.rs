@@ -0,0 +1,466 @@
+use crate::{
+    attachments::{self, UploadError},
+    audit::{self, AuditEvent, ClientIp},
+    auth::is_moderator,
+    blob_store, images,
+    models::{AttachTo, AttachmentKind, Claims},
+    restrictions,
+    trust::{self, Capability},
+    AppState,
+};
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use uuid::Uuid;
+
+/// Checks that the user may attach files to `target`: editors to posts,
+/// and authors to their own replies.
+async fn check_target(state: &AppState, claims: &Claims, user_id: Uuid, target: AttachTo) -> Result<(), Response> {
+    match (target.post_id, target.reply_id) {
+        (Some(_), Some(_)) => {
+            Err((StatusCode::BAD_REQUEST, "Attach to a post or a reply, not both").into_response())
+        }
+        (Some(post_id), None) => {
+            if !is_moderator(&claims.role) {
+                return Err((StatusCode::FORBIDDEN, "Editor access required").into_response());
+            }
+            match state.db.get_post(post_id).await {
+                Ok(Some(_)) => Ok(()),
+                Ok(None) => Err((StatusCode::NOT_FOUND, "Post not found").into_response()),
+                Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+            }
+        }
+        (None, Some(reply_id)) => match state.db.get_reply(reply_id).await {
+            Ok(Some(reply)) if reply.user_id == user_id || is_moderator(&claims.role) => Ok(()),
+            Ok(Some(_)) => Err((StatusCode::FORBIDDEN, "You can only attach files to your own replies").into_response()),
+            Ok(None) => Err((StatusCode::NOT_FOUND, "Reply not found").into_response()),
+            Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+        },
+        (None, None) => Ok(()),
+    }
+}
+
+fn parse_id(value: &str) -> Result<Option<Uuid>, (StatusCode, &'static str)> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid post_id or reply_id"))
+}
+
+// Attachments API
+// Uploaded as the multipart field "file", with optional "post_id" or
+// "reply_id" fields; without either, the upload can be attached later,
+// once the reply it is for has been posted. A "kind" field of "challenge"
+// uploads a challenge file
+
+pub async fn api_upload_attachment(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(response) = trust::require(&state, &claims, Capability::AttachFiles).await {
+        return response;
+    }
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    let mut file = None;
+    let mut kind = AttachmentKind::default();
+    let mut target = AttachTo::default();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(_) => return (StatusCode::BAD_REQUEST, "Invalid multipart body").into_response(),
+        };
+        let name = field.name().map(str::to_string);
+        match name.as_deref() {
+            Some("file") => {
+                let filename = field.file_name().unwrap_or("file").to_string();
+                match field.bytes().await {
+                    Ok(bytes) => file = Some((filename, bytes.to_vec())),
+                    Err(_) => {
+                        let message = UploadError::TooLarge(attachments::max_attachment_bytes()).to_string();
+                        return (StatusCode::PAYLOAD_TOO_LARGE, message).into_response();
+                    }
+                }
+            }
+            Some("kind") => {
+                let parsed = field.text().await.ok().and_then(|value| AttachmentKind::parse(value.trim()));
+                match parsed {
+                    Some(parsed) => kind = parsed,
+                    None => return (StatusCode::BAD_REQUEST, "kind must be file or challenge").into_response(),
+                }
+            }
+            Some(name @ ("post_id" | "reply_id")) => {
+                let is_post = name == "post_id";
+                let id = match field.text().await {
+                    Ok(value) => match parse_id(&value) {
+                        Ok(id) => id,
+                        Err(error) => return error.into_response(),
+                    },
+                    Err(_) => return (StatusCode::BAD_REQUEST, "Invalid multipart body").into_response(),
+                };
+                if is_post {
+                    target.post_id = id;
+                } else {
+                    target.reply_id = id;
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some((filename, bytes)) = file else {
+        return (StatusCode::BAD_REQUEST, "No file field in the upload").into_response();
+    };
+
+    if let Err(response) = check_target(&state, &claims, user_id, target).await {
+        return response;
+    }
+
+    let attachment = attachments::store(&state.db, state.blobs.as_ref(), user_id, &filename, bytes, kind, target).await;
+    match attachment {
+        Ok(attachment) => match attachments::with_variants(&state.db, vec![attachment]).await {
+            Ok(mut found) => (StatusCode::CREATED, Json(found.remove(0))).into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Err(e) => {
+            let status = match &e {
+                UploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                UploadError::Empty => StatusCode::BAD_REQUEST,
+                UploadError::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                UploadError::InvalidImage => StatusCode::UNPROCESSABLE_ENTITY,
+                UploadError::Storage(error) => {
+                    tracing::warn!("Failed to store attachment: {}", error);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            (status, e.to_string()).into_response()
+        }
+    }
+}
+
+pub async fn api_list_attachments(
+    State(state): State<AppState>,
+    Query(target): Query<AttachTo>,
+) -> impl IntoResponse {
+    if target.post_id.is_some() == target.reply_id.is_some() {
+        return (StatusCode::BAD_REQUEST, "Give either post_id or reply_id").into_response();
+    }
+
+    let attachments = match state.db.list_attachments(target).await {
+        Ok(attachments) => attachments,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    match attachments::with_variants(&state.db, attachments).await {
+        Ok(attachments) => Json(attachments).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+/// Links one of your unlinked uploads to a post or reply.
+pub async fn api_attach(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(attachment_id): Path<Uuid>,
+    Json(target): Json<AttachTo>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if target.post_id.is_none() && target.reply_id.is_none() {
+        return (StatusCode::BAD_REQUEST, "Give either post_id or reply_id").into_response();
+    }
+
+    match state.db.get_attachment(attachment_id).await {
+        Ok(Some(attachment)) if attachment.user_id == Some(user_id) => {}
+        Ok(Some(_)) => return (StatusCode::FORBIDDEN, "You can only attach your own uploads").into_response(),
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+    if let Err(response) = check_target(&state, &claims, user_id, target).await {
+        return response;
+    }
+
+    let attachment = match state.db.attach(attachment_id, target).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::CONFLICT, "This upload is already attached").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to attach upload").into_response(),
+    };
+    match attachments::with_variants(&state.db, vec![attachment]).await {
+        Ok(mut found) => Json(found.remove(0)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_delete_attachment(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(attachment_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let attachment = match state.db.get_attachment(attachment_id).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let own = attachment.user_id == Some(user_id);
+    if !own && !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "You can only remove your own attachments").into_response();
+    }
+
+    // The blob itself is removed by the collector once nothing uses it
+    match state.db.delete_attachment(attachment_id).await {
+        Ok(Some(deleted)) => {
+            if !own {
+                audit::record(&state, &claims, &ip, AuditEvent::new("attachment.delete", "attachment", attachment_id).before(&deleted)).await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove attachment").into_response(),
+    }
+}
+
+// Downloads
+
+/// `Content-Disposition` with an ASCII fallback name and the real name
+/// percent-encoded, per RFC 6266.
+fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", disposition, fallback, encoded)
+}
+
+/// The byte range asked for by a `Range` header, as inclusive offsets into
+/// content of `len` bytes: `None` to send the whole content, which is also
+/// the answer to several ranges, and `Err` if the range is past the end.
+fn requested_range(range: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = range.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // The last `end` bytes
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 {
+            return Some(Err(()));
+        }
+        (len.saturating_sub(suffix), len.checked_sub(1)?)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let last = len.saturating_sub(1);
+        let end = if end.is_empty() { last } else { end.parse::<u64>().ok()?.min(last) };
+        if start > end || start >= len {
+            return Some(Err(()));
+        }
+        (start, end)
+    };
+    Some(Ok(range))
+}
+
+// The blob under `key` with headers for caching forever; what is stored
+// under a key never changes. Single byte ranges are served, so interrupted
+// downloads of large challenge files can be resumed
+async fn blob_response(
+    state: &AppState,
+    headers: &HeaderMap,
+    key: &str,
+    etag: String,
+    mime_type: &str,
+    disposition: String,
+) -> Response {
+    if headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) == Some(etag.as_str()) {
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+
+    let bytes = match state.blobs.get(key).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => {
+            tracing::warn!("Blob {} is missing", key);
+            return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read blob {}: {}", key, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read attachment").into_response();
+        }
+    };
+
+    // A range applies only while the content is the one it was asked of
+    let len = bytes.len() as u64;
+    let if_range_matches = match headers.get(header::IF_RANGE) {
+        Some(value) => value.to_str().ok() == Some(etag.as_str()),
+        None => true,
+    };
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches)
+        .and_then(|range| requested_range(range, len));
+
+    let mut response = match range {
+        None => Body::from(bytes).into_response(),
+        Some(Ok((start, end))) => {
+            let part = bytes[start as usize..=end as usize].to_vec();
+            let content_range = format!("bytes {}-{}/{}", start, end, len);
+            (StatusCode::PARTIAL_CONTENT, [(header::CONTENT_RANGE, content_range)], part).into_response()
+        }
+        Some(Err(())) => {
+            let content_range = format!("bytes */{}", len);
+            return (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, content_range)]).into_response();
+        }
+    };
+    let response_headers = response.headers_mut();
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    let values = [
+        (header::CONTENT_TYPE, mime_type.to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+        (header::ETAG, etag),
+        (header::CACHE_CONTROL, "public, max-age=31536000, immutable".to_string()),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response_headers.insert(name, value);
+        }
+    }
+    response_headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    response
+}
+
+pub async fn serve_attachment(
+    State(state): State<AppState>,
+    Path(attachment_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let attachment = match state.db.get_attachment(attachment_id).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    // Content never changes under an id, so the hash is a strong ETag
+    let etag = format!("\"{}\"", attachment.sha256);
+    let disposition = if attachments::is_inline(&attachment) { "inline" } else { "attachment" };
+    let key = blob_store::key_for(&attachment.sha256);
+    let disposition = content_disposition(disposition, &attachment.filename);
+    blob_response(&state, &headers, &key, etag, &attachment.mime_type, disposition).await
+}
+
+/// A smaller version of an image attachment, such as its thumbnail.
+pub async fn serve_attachment_variant(
+    State(state): State<AppState>,
+    Path((attachment_id, variant)): Path<(Uuid, String)>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let attachment = match state.db.get_attachment(attachment_id).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let found = match state.db.list_attachment_variants(&[attachment_id]).await {
+        Ok(variants) => variants.into_iter().find(|found| found.variant == variant),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let Some(found) = found else {
+        return (StatusCode::NOT_FOUND, "Variant not found").into_response();
+    };
+
+    let etag = format!("\"{}.{}\"", attachment.sha256, found.variant);
+    let key = blob_store::variant_key(&attachment.sha256, &found.variant);
+    let filename = match images::extension(&found.mime_type) {
+        Some(extension) => format!("{}-{}.{}", attachments::file_stem(&attachment.filename), found.variant, extension),
+        None => attachment.filename.clone(),
+    };
+    let disposition = content_disposition("inline", &filename);
+    blob_response(&state, &headers, &key, etag, &found.mime_type, disposition).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_disposition, requested_range};
+
+    #[test]
+    fn single_ranges_are_served() {
+        assert_eq!(requested_range("bytes=0-99", 1000), Some(Ok((0, 99))));
+        assert_eq!(requested_range("bytes=500-", 1000), Some(Ok((500, 999))));
+        assert_eq!(requested_range(" bytes=10-10 ", 1000), Some(Ok((10, 10))));
+    }
+
+    #[test]
+    fn suffix_ranges_count_from_the_end() {
+        assert_eq!(requested_range("bytes=-100", 1000), Some(Ok((900, 999))));
+        assert_eq!(requested_range("bytes=-5000", 1000), Some(Ok((0, 999))));
+        assert_eq!(requested_range("bytes=-0", 1000), Some(Err(())));
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_cut_or_refused() {
+        assert_eq!(requested_range("bytes=900-5000", 1000), Some(Ok((900, 999))));
+        assert_eq!(requested_range("bytes=1000-", 1000), Some(Err(())));
+        assert_eq!(requested_range("bytes=5-3", 1000), Some(Err(())));
+    }
+
+    #[test]
+    fn empty_content_has_no_ranges() {
+        assert_eq!(requested_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(requested_range("bytes=-10", 0), None);
+    }
+
+    #[test]
+    fn several_or_unreadable_ranges_send_everything() {
+        assert_eq!(requested_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(requested_range("items=0-1", 1000), None);
+        assert_eq!(requested_range("bytes=abc", 1000), None);
+        assert_eq!(requested_range("bytes=a-b", 1000), None);
+    }
+
+    #[test]
+    fn ascii_filenames_are_kept() {
+        assert_eq!(
+            content_disposition("attachment", "solve v2.py"),
+            "attachment; filename=\"solve v2.py\"; filename*=UTF-8''solve%20v2.py"
+        );
+    }
+
+    #[test]
+    fn non_ascii_filenames_get_a_fallback() {
+        assert_eq!(
+            content_disposition("inline", "résumé.pdf"),
+            "inline; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+        assert_eq!(
+            content_disposition("attachment", "a\"b\\c\n.txt"),
+            "attachment; filename=\"a_b_c_.txt\"; filename*=UTF-8''a%22b%5Cc%0A.txt"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! File attachments on posts and replies. An upload is checked against the
+//! size limit and the allowed types, which are detected from the content
+//! rather than trusted from the client; images are stripped of metadata and
+//! get smaller variants (see `images.rs`). The result is stored in the
+//! `BlobStore` by its SHA-256 and recorded with the file name it was
+//! uploaded under, its MD5 and a description of what it is. Challenge files
+//! skip the type check and are stored exactly as uploaded.
+
+use crate::archives;
+use crate::blob_store::{self, BlobStore};
+use crate::database::Database;
+use crate::images::{self, EncodedImage, ProcessedImage};
+use crate::magic;
+use crate::markdown::ResponsiveImage;
+use crate::models::{
+    ArchiveListing, AttachTo, Attachment, AttachmentKind, AttachmentWithVariants, NewBlob, NewBlobVariant,
+};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 20 * 1024 * 1024;
+/// Uploads not linked to a post or reply within this many hours are removed.
+const UNLINKED_TTL_HOURS: i32 = 24;
+/// Unused blobs are kept this long after their last upload, so a blob being
+/// uploaded again isn't collected before its attachment is recorded.
+const ORPHAN_GRACE_HOURS: i32 = 1;
+const COLLECT_INTERVAL: Duration = Duration::from_secs(3600);
+const MAX_FILENAME_CHARS: usize = 255;
+
+/// Types that can be attached. Content that isn't recognized is stored as
+/// `application/octet-stream`, which is always served as a download.
+pub const ALLOWED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+    "application/zip",
+    "application/gzip",
+    "application/x-tar",
+    "application/x-xz",
+    "application/x-bzip2",
+    "application/x-7z-compressed",
+    "application/x-executable",
+    "application/vnd.microsoft.portable-executable",
+    "application/vnd.tcpdump.pcap",
+    "application/octet-stream",
+];
+
+/// The largest upload, from `ATTACHMENT_MAX_BYTES`.
+pub fn max_attachment_bytes() -> usize {
+    std::env::var("ATTACHMENT_MAX_BYTES")
+        .ok()
+        .and_then(|bytes| bytes.parse().ok())
+        .filter(|bytes| *bytes > 0)
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_BYTES)
+}
+
+/// The type of `bytes` from their magic numbers; UTF-8 text without NULs
+/// is `text/plain`.
+pub fn detect_mime_type(bytes: &[u8]) -> &'static str {
+    if let Some(kind) = infer::get(bytes) {
+        return kind.mime_type();
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Images are shown in the page; everything else, challenge files
+/// included, is downloaded.
+pub fn is_inline(attachment: &Attachment) -> bool {
+    attachment.mime_type.starts_with("image/") && attachment.kind == AttachmentKind::File.as_str()
+}
+
+/// The uploaded file name without any directories or control characters.
+pub fn clean_filename(filename: &str) -> String {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        "file".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Why an upload was refused.
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge(usize),
+    Empty,
+    UnsupportedType(&'static str),
+    InvalidImage,
+    Storage(anyhow::Error),
+}
+
+impl std::fmt::Display for UploadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadError::TooLarge(max) => write!(f, "Attachments can be at most {} MB", max / (1024 * 1024)),
+            UploadError::Empty => write!(f, "The file is empty"),
+            UploadError::UnsupportedType(mime_type) => write!(f, "Files of type {} can't be attached", mime_type),
+            UploadError::InvalidImage => {
+                write!(f, "Couldn't read the image; it may be larger than {0}x{0}", images::MAX_DIMENSION)
+            }
+            UploadError::Storage(_) => write!(f, "Failed to store the file"),
+        }
+    }
+}
+
+/// The file name without its extension.
+pub fn file_stem(filename: &str) -> &str {
+    match filename.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => filename,
+    }
+}
+
+// The file name with the extension of the type the image was saved as
+fn with_extension(filename: &str, mime_type: &str) -> String {
+    match images::extension(mime_type) {
+        Some(extension) => format!("{}.{}", file_stem(filename), extension),
+        None => filename.to_string(),
+    }
+}
+
+fn variant_record(name: &str, image: &EncodedImage) -> NewBlobVariant {
+    NewBlobVariant {
+        variant: name.to_string(),
+        mime_type: image.mime_type.to_string(),
+        size: image.bytes.len() as i64,
+        width: image.width as i32,
+        height: image.height as i32,
+    }
+}
+
+// What is recorded about content besides its type
+struct Fingerprint {
+    sha256: String,
+    md5: String,
+    description: String,
+    archive: Option<ArchiveListing>,
+}
+
+fn fingerprint(bytes: &[u8]) -> Fingerprint {
+    Fingerprint {
+        sha256: format!("{:x}", Sha256::digest(bytes)),
+        md5: format!("{:x}", Md5::digest(bytes)),
+        description: magic::describe(bytes),
+        archive: archives::list(bytes),
+    }
+}
+
+/// Checks and stores an upload, returning its record.
+pub async fn store(
+    db: &Database,
+    blobs: &dyn BlobStore,
+    user_id: Uuid,
+    filename: &str,
+    bytes: Vec<u8>,
+    kind: AttachmentKind,
+    target: AttachTo,
+) -> Result<Attachment, UploadError> {
+    let max = max_attachment_bytes();
+    if bytes.len() > max {
+        return Err(UploadError::TooLarge(max));
+    }
+    if bytes.is_empty() {
+        return Err(UploadError::Empty);
+    }
+    let mime_type = detect_mime_type(&bytes);
+    let is_challenge = kind == AttachmentKind::Challenge;
+    if !is_challenge && !ALLOWED_TYPES.contains(&mime_type) {
+        return Err(UploadError::UnsupportedType(mime_type));
+    }
+
+    let mut filename = clean_filename(filename);
+    let (original, variants) = if images::is_processable(mime_type) && !is_challenge {
+        // Decoding and encoding is CPU-bound
+        let processed = tokio::task::spawn_blocking(move || images::process(bytes))
+            .await
+            .map_err(|e| UploadError::Storage(e.into()))?
+            .map_err(|_| UploadError::InvalidImage)?;
+        let ProcessedImage { original, variants } = processed;
+        if original.mime_type != mime_type {
+            filename = with_extension(&filename, original.mime_type);
+        }
+        (original, variants)
+    } else {
+        (EncodedImage { bytes, mime_type, width: 0, height: 0 }, Vec::new())
+    };
+
+    // Hashing and listing archives is CPU-bound too
+    let (original, fingerprint) = tokio::task::spawn_blocking(move || {
+        let fingerprint = fingerprint(&original.bytes);
+        (original, fingerprint)
+    })
+    .await
+    .map_err(|e| UploadError::Storage(e.into()))?;
+    let sha256 = fingerprint.sha256;
+    let is_image = images::is_processable(original.mime_type) && !is_challenge;
+    let blob = NewBlob {
+        sha256: sha256.clone(),
+        md5: fingerprint.md5,
+        size: original.bytes.len() as i64,
+        mime_type: original.mime_type.to_string(),
+        description: fingerprint.description,
+        width: is_image.then_some(original.width as i32),
+        height: is_image.then_some(original.height as i32),
+        archive: fingerprint.archive,
+        variants: variants.iter().map(|(name, image)| variant_record(name, image)).collect(),
+    };
+
+    let key = blob_store::key_for(&sha256);
+    if !blobs.exists(&key).await.map_err(UploadError::Storage)? {
+        for (name, image) in variants {
+            blobs
+                .put(&blob_store::variant_key(&sha256, name), image.bytes, image.mime_type)
+                .await
+                .map_err(UploadError::Storage)?;
+        }
+        // Last, so a stored blob always has its variants
+        blobs.put(&key, original.bytes, original.mime_type).await.map_err(UploadError::Storage)?;
+    }
+
+    db.create_attachment(&blob, &filename, kind, user_id, target)
+        .await
+        .map_err(UploadError::Storage)
+}
+
+/// Adds the variants of their images to attachments.
+pub async fn with_variants(db: &Database, attachments: Vec<Attachment>) -> anyhow::Result<Vec<AttachmentWithVariants>> {
+    let ids: Vec<Uuid> = attachments.iter().map(|attachment| attachment.id).collect();
+    let mut variants: HashMap<Uuid, Vec<_>> = HashMap::new();
+    for variant in db.list_attachment_variants(&ids).await? {
+        variants.entry(variant.attachment_id).or_default().push(variant);
+    }
+
+    Ok(attachments
+        .into_iter()
+        .map(|attachment| AttachmentWithVariants {
+            variants: variants.remove(&attachment.id).unwrap_or_default(),
+            attachment,
+        })
+        .collect())
+}
+
+/// The images among the attachments that have variants, for rendering
+/// Markdown with `srcset`.
+pub async fn responsive_images(db: &Database, ids: &[Uuid]) -> anyhow::Result<HashMap<Uuid, ResponsiveImage>> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let attachments = with_variants(db, db.get_attachments(ids).await?).await?;
+
+    Ok(attachments
+        .into_iter()
+        .filter(|found| !found.variants.is_empty())
+        .filter_map(|found| {
+            let (width, height) = (found.attachment.width?, found.attachment.height?);
+            let mut sources: Vec<(String, i32)> =
+                found.variants.into_iter().map(|variant| (variant.url, variant.width)).collect();
+            sources.push((found.attachment.url, width));
+            Some((found.attachment.id, ResponsiveImage { width, height, sources }))
+        })
+        .collect())
+}
+
+/// Removes abandoned uploads and then the blobs nothing uses any more.
+pub async fn collect_garbage(db: &Database, blobs: &dyn BlobStore) -> anyhow::Result<usize> {
+    db.delete_unlinked_attachments(UNLINKED_TTL_HOURS).await?;
+    let orphans = db.delete_orphaned_blobs(ORPHAN_GRACE_HOURS).await?;
+    for sha256 in &orphans {
+        blobs.delete(&blob_store::key_for(sha256)).await?;
+        for (name, _) in images::VARIANTS {
+            blobs.delete(&blob_store::variant_key(sha256, name)).await?;
+        }
+    }
+    Ok(orphans.len())
+}
+
+/// Collects garbage now and then every hour.
+pub fn spawn_collector(db: Arc<Database>, blobs: Arc<dyn BlobStore>) {
+    tokio::spawn(async move {
+        loop {
+            match collect_garbage(&db, blobs.as_ref()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Removed {} unused blobs", count),
+                Err(e) => tracing::warn!("Failed to remove unused blobs: {}", e),
+            }
+            tokio::time::sleep(COLLECT_INTERVAL).await;
+        }
+    });
+}
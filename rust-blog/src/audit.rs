@@ -0,0 +1,146 @@
+//! The audit log: every privileged change is recorded with who made it,
+//! from which address, and the target before and after.
+
+use crate::{
+    models::{AuditEntry, Claims, NewAuditEntry},
+    AppState,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The client address, put in the request extensions by
+/// `restrictions::enforce`.
+#[derive(Debug, Clone)]
+pub struct ClientIp(pub String);
+
+/// A change to record, such as `topic.lock` on a topic.
+pub struct AuditEvent {
+    action: &'static str,
+    target_type: &'static str,
+    target_id: String,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str, target_type: &'static str, target_id: impl ToString) -> Self {
+        Self {
+            action,
+            target_type,
+            target_id: target_id.to_string(),
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+}
+
+/// Records a change that has been made. A failure is logged rather than
+/// undoing the change.
+pub async fn record(state: &AppState, claims: &Claims, ip: &ClientIp, event: AuditEvent) {
+    let entry = NewAuditEntry {
+        actor_id: claims.sub.parse::<Uuid>().ok(),
+        actor_username: claims.username.clone(),
+        action: event.action.to_string(),
+        target_type: event.target_type.to_string(),
+        target_id: event.target_id,
+        before: event.before,
+        after: event.after,
+        ip_address: Some(ip.0.clone()),
+    };
+    let action = entry.action.clone();
+    if let Err(e) = state.db.insert_audit_entry(entry).await {
+        tracing::error!("Failed to record {} in the audit log: {}", action, e);
+    }
+}
+
+/// The entries as CSV, with before and after as JSON.
+pub fn to_csv(entries: &[AuditEntry]) -> String {
+    let mut csv = String::from("created_at,actor_id,actor_username,action,target_type,target_id,before,after,ip_address\n");
+    for entry in entries {
+        let json = |value: &Option<serde_json::Value>| value.as_ref().map(|v| v.to_string()).unwrap_or_default();
+        let fields = [
+            entry.created_at.to_rfc3339(),
+            entry.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.actor_username.clone(),
+            entry.action.clone(),
+            entry.target_type.clone(),
+            entry.target_id.clone(),
+            json(&entry.before),
+            json(&entry.after),
+            entry.ip_address.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+// Quotes a field if it needs it; a leading =, +, -, @, tab or carriage
+// return is escaped so spreadsheets don't run it as a formula
+fn csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::csv_field;
+
+    #[test]
+    fn plain_fields_are_left_alone() {
+        assert_eq!(csv_field("topic.delete"), "topic.delete");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn fields_with_separators_are_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("line one\nline two"), "\"line one\nline two\"");
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+    }
+
+    #[test]
+    fn embedded_quotes_are_doubled() {
+        assert_eq!(csv_field(r#"{"title":"Hi"}"#), r#""{""title"":""Hi""}""#);
+    }
+
+    #[test]
+    fn formula_prefixes_are_escaped() {
+        assert_eq!(csv_field("=SUM(A1)"), "'=SUM(A1)");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-1"), "'-1");
+        assert_eq!(csv_field("@cmd"), "'@cmd");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+    }
+
+    #[test]
+    fn escaped_formulas_are_still_quoted() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\",\"y\")"), "\"'=HYPERLINK(\"\"x\"\",\"\"y\"\")\"");
+    }
+
+    #[test]
+    fn prefixes_later_in_the_field_are_left_alone() {
+        assert_eq!(csv_field("a=b"), "a=b");
+        assert_eq!(csv_field("a-b"), "a-b");
+    }
+}
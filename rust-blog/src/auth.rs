@@ -2,7 +2,7 @@ use crate::models::{User, Claims, UserRole};
 use anyhow::Result;
 use axum::{
     extract::{Request, State},
-    http::{header::AUTHORIZATION, StatusCode},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
     middleware::Next,
     response::Response,
 };
@@ -46,6 +46,17 @@ pub fn verify_jwt(token: &str) -> Result<Claims> {
     Ok(token_data.claims)
 }
 
+/// The claims of a valid bearer token, if the request has one. For public
+/// routes that show more to signed-in users.
+pub fn optional_claims(headers: &HeaderMap) -> Option<Claims> {
+    let token = headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?;
+    verify_jwt(token).ok()
+}
+
 pub async fn auth_middleware(
     State(state): State<crate::AppState>,
     mut request: Request,
@@ -75,6 +86,11 @@ pub async fn auth_middleware(
     }
 }
 
+/// Editors and admins moderate the forum.
+pub fn is_moderator(role: &UserRole) -> bool {
+    matches!(role, UserRole::Admin | UserRole::Editor)
+}
+
 pub fn require_role(required_role: UserRole) -> impl Fn(Claims) -> Result<(), StatusCode> {
     move |claims: Claims| {
         match (&claims.role, &required_role) {
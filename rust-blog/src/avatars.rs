@@ -0,0 +1,92 @@
+//! Avatars. An upload is turned upright, cropped to a square and resized to
+//! each of `AVATAR_SIZES`, re-encoded as PNG (which also drops any metadata)
+//! and kept in the `BlobStore` under `avatars/`, served at `/avatars`.
+//! Members without one get `static/default-avatar.png`.
+
+use crate::blob_store::BlobStore;
+use crate::images::{self, MAX_DIMENSION};
+use image::{imageops::FilterType, ImageFormat};
+use std::io::Cursor;
+use uuid::Uuid;
+
+pub const DEFAULT_AVATAR: &str = "/static/default-avatar.png";
+/// The sizes, in pixels, every avatar is saved in; `avatar_url` points at
+/// the largest.
+pub const AVATAR_SIZES: [u32; 3] = [64, 128, 256];
+pub const MAX_AVATAR_BYTES: usize = 5 * 1024 * 1024;
+const ALLOWED_FORMATS: [ImageFormat; 4] = [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif, ImageFormat::WebP];
+
+fn largest_size() -> u32 {
+    AVATAR_SIZES[AVATAR_SIZES.len() - 1]
+}
+
+/// The URL of an avatar at `size`, or the default avatar.
+pub fn sized_url(avatar_url: Option<&str>, size: u32) -> String {
+    let largest = format!("-{}.png", largest_size());
+    match avatar_url {
+        Some(url) if url.ends_with(&largest) => {
+            format!("{}-{}.png", url.trim_end_matches(&largest), size)
+        }
+        Some(url) => url.to_string(),
+        None => DEFAULT_AVATAR.to_string(),
+    }
+}
+
+/// The blob key of an avatar file, named `{version}-{size}.png`, or `None`
+/// if the name isn't one.
+pub fn key_for(user_id: Uuid, file: &str) -> Option<String> {
+    let (version, size) = file.strip_suffix(".png")?.split_once('-')?;
+    let valid = version.len() == 32
+        && version.bytes().all(|b| b.is_ascii_hexdigit())
+        && size.parse().is_ok_and(|size: u32| AVATAR_SIZES.contains(&size));
+    valid.then(|| format!("avatars/{}/{}", user_id, file))
+}
+
+/// Decodes an uploaded image and returns it as a PNG in each of
+/// `AVATAR_SIZES`. Errors are messages for the uploader.
+pub fn process(bytes: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, String> {
+    if !image::guess_format(bytes).is_ok_and(|format| ALLOWED_FORMATS.contains(&format)) {
+        return Err("Avatars must be PNG, JPEG, GIF or WebP images".to_string());
+    }
+    let (image, _) = images::decode(bytes)
+        .map_err(|_| format!("Couldn't read the image; it may be larger than {0}x{0}", MAX_DIMENSION))?;
+
+    AVATAR_SIZES
+        .iter()
+        .map(|&size| {
+            let mut png = Vec::new();
+            image
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(|_| "Couldn't resize the image".to_string())?;
+            Ok((size, png))
+        })
+        .collect()
+}
+
+/// Saves the sizes of a new avatar, returning the URL of the largest.
+pub async fn save(blobs: &dyn BlobStore, user_id: Uuid, images: Vec<(u32, Vec<u8>)>) -> anyhow::Result<String> {
+    // A new name each time, so browsers don't show a cached old avatar
+    let version = Uuid::new_v4().simple().to_string();
+    for (size, png) in images {
+        let key = format!("avatars/{}/{}-{}.png", user_id, version, size);
+        blobs.put(&key, png, "image/png").await?;
+    }
+
+    Ok(format!("/avatars/{}/{}-{}.png", user_id, version, largest_size()))
+}
+
+/// Removes every size of the avatar at `avatar_url`.
+pub async fn remove(blobs: &dyn BlobStore, user_id: Uuid, avatar_url: &str) -> anyhow::Result<()> {
+    let prefix = format!("/avatars/{}/", user_id);
+    let Some(file) = avatar_url.strip_prefix(&prefix) else {
+        return Ok(());
+    };
+    for size in AVATAR_SIZES {
+        let sized = sized_url(Some(file), size);
+        if let Some(key) = key_for(user_id, &sized) {
+            blobs.delete(&key).await?;
+        }
+    }
+    Ok(())
+}
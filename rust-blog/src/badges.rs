@@ -0,0 +1,183 @@
+//! Badges. Each badge has a rule over a member's activity, and a background
+//! job awards it to everyone who meets the rule. Badges are never taken
+//! back. To add a badge, add it to `BADGES`.
+
+use crate::{database::Database, models::UserBadge};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often rules are checked for new holders.
+const AWARD_INTERVAL: Duration = Duration::from_secs(900);
+
+/// What a member needs to earn a badge: at least this many of something.
+#[derive(Debug, Clone, Copy)]
+pub enum BadgeRule {
+    SolutionsAccepted(i64),
+    LikesReceived(i64),
+    Replies(i64),
+    Topics(i64),
+    Reputation(i64),
+    DaysVisited(i64),
+    TrustLevel(i64),
+}
+
+impl BadgeRule {
+    /// The metric `Database::award_badge` compares, and the threshold.
+    pub fn metric(self) -> (&'static str, i64) {
+        match self {
+            BadgeRule::SolutionsAccepted(n) => ("solutions_accepted", n),
+            BadgeRule::LikesReceived(n) => ("likes_received", n),
+            BadgeRule::Replies(n) => ("replies", n),
+            BadgeRule::Topics(n) => ("topics", n),
+            BadgeRule::Reputation(n) => ("reputation", n),
+            BadgeRule::DaysVisited(n) => ("days_visited", n),
+            BadgeRule::TrustLevel(n) => ("trust_level", n),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Badge {
+    pub slug: &'static str,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub icon: &'static str,
+    #[serde(skip)]
+    pub rule: BadgeRule,
+}
+
+pub const BADGES: &[Badge] = &[
+    Badge {
+        slug: "first-reply",
+        name: "First Reply",
+        description: "Replied to a topic",
+        icon: "💬",
+        rule: BadgeRule::Replies(1),
+    },
+    Badge {
+        slug: "conversation-starter",
+        name: "Conversation Starter",
+        description: "Started 10 topics",
+        icon: "🗣️",
+        rule: BadgeRule::Topics(10),
+    },
+    Badge {
+        slug: "first-solution",
+        name: "First Solution",
+        description: "Had a reply accepted as a solution",
+        icon: "✅",
+        rule: BadgeRule::SolutionsAccepted(1),
+    },
+    Badge {
+        slug: "problem-solver",
+        name: "Problem Solver",
+        description: "Had 25 replies accepted as solutions",
+        icon: "🧩",
+        rule: BadgeRule::SolutionsAccepted(25),
+    },
+    Badge {
+        slug: "10-likes",
+        name: "10 Likes",
+        description: "Received 10 likes",
+        icon: "👍",
+        rule: BadgeRule::LikesReceived(10),
+    },
+    Badge {
+        slug: "100-likes",
+        name: "100 Likes",
+        description: "Received 100 likes",
+        icon: "❤️",
+        rule: BadgeRule::LikesReceived(100),
+    },
+    Badge {
+        slug: "respected",
+        name: "Respected",
+        description: "Reached 500 reputation",
+        icon: "⭐",
+        rule: BadgeRule::Reputation(500),
+    },
+    Badge {
+        slug: "dedicated",
+        name: "Dedicated",
+        description: "Visited on 100 different days",
+        icon: "📅",
+        rule: BadgeRule::DaysVisited(100),
+    },
+    Badge {
+        slug: "regular",
+        name: "Regular",
+        description: "Reached trust level 3",
+        icon: "🛡️",
+        rule: BadgeRule::TrustLevel(3),
+    },
+];
+
+/// A badge and how many members hold it.
+#[derive(Debug, Serialize)]
+pub struct BadgeSummary {
+    #[serde(flatten)]
+    pub badge: &'static Badge,
+    pub holders: i64,
+}
+
+/// A badge a member holds.
+#[derive(Debug, Serialize)]
+pub struct AwardedBadge {
+    #[serde(flatten)]
+    pub badge: &'static Badge,
+    pub awarded_at: DateTime<Utc>,
+}
+
+pub fn find(slug: &str) -> Option<&'static Badge> {
+    BADGES.iter().find(|badge| badge.slug == slug)
+}
+
+/// The badges a member holds, skipping any since removed from `BADGES`.
+pub fn awarded(badges: Vec<UserBadge>) -> Vec<AwardedBadge> {
+    badges
+        .into_iter()
+        .filter_map(|awarded| {
+            find(&awarded.badge).map(|badge| AwardedBadge { badge, awarded_at: awarded.awarded_at })
+        })
+        .collect()
+}
+
+/// Badges grouped by holder, for user cards.
+pub fn by_user(badges: Vec<UserBadge>) -> HashMap<Uuid, Vec<&'static Badge>> {
+    let mut by_user: HashMap<Uuid, Vec<&'static Badge>> = HashMap::new();
+    for awarded in badges {
+        if let Some(badge) = find(&awarded.badge) {
+            by_user.entry(awarded.user_id).or_default().push(badge);
+        }
+    }
+    by_user
+}
+
+/// Awards every badge to the members who have newly earned it, returning
+/// how many were awarded.
+pub async fn award_all(db: &Database) -> anyhow::Result<u64> {
+    let mut awarded = 0;
+    for badge in BADGES {
+        let (metric, threshold) = badge.rule.metric();
+        awarded += db.award_badge(badge.slug, metric, threshold).await?;
+    }
+    Ok(awarded)
+}
+
+/// Awards badges now and then every 15 minutes.
+pub fn spawn_awarder(db: Arc<Database>) {
+    tokio::spawn(async move {
+        loop {
+            match award_all(&db).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Awarded {} badges", count),
+                Err(e) => tracing::warn!("Failed to award badges: {}", e),
+            }
+            tokio::time::sleep(AWARD_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,171 @@
+//! Where uploaded files are kept. `STORAGE_BACKEND` picks the local disk
+//! (`local`, the default, under `STORAGE_DIR`) or an S3-compatible bucket
+//! (`s3`, such as AWS S3 or MinIO). Blobs are keyed by the SHA-256 of their
+//! content, so a file uploaded twice is stored once; what they are and who
+//! uploaded them is recorded in the database.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// A store of immutable blobs.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    /// Stores `bytes` under `key`, replacing anything already there.
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<()>;
+    /// `None` if nothing is stored under `key`.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    async fn exists(&self, key: &str) -> Result<bool>;
+    /// Removing a missing blob is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// The key of a blob with the given SHA-256, fanned out over two levels of
+/// directories so no directory grows too large.
+pub fn key_for(sha256: &str) -> String {
+    format!("{}/{}/{}", &sha256[..2], &sha256[2..4], sha256)
+}
+
+/// The key of a variant made of the blob with the given SHA-256, such as a
+/// smaller version of an image.
+pub fn variant_key(sha256: &str, variant: &str) -> String {
+    format!("{}.{}", key_for(sha256), variant)
+}
+
+/// Opens the store configured by `STORAGE_BACKEND`.
+pub fn from_env() -> Result<Arc<dyn BlobStore>> {
+    match std::env::var("STORAGE_BACKEND").as_deref() {
+        Ok("local") | Err(_) => {
+            let dir = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "uploads".to_string());
+            Ok(Arc::new(LocalBlobStore::new(dir)))
+        }
+        Ok("s3") => Ok(Arc::new(S3BlobStore::from_env()?)),
+        Ok(other) => anyhow::bail!("Unknown STORAGE_BACKEND '{}', expected local or s3", other),
+    }
+}
+
+/// Blobs as files under a directory.
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    // Keys are relative paths that must stay under the root
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            anyhow::bail!("Invalid blob key '{}'", key);
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed, so a blob is never seen half-written
+        let partial = path.with_extension(format!("{}.partial", uuid::Uuid::new_v4().simple()));
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(tokio::fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Blobs as objects in an S3-compatible bucket, configured by `S3_BUCKET`,
+/// `S3_REGION`, `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY`. Setting
+/// `S3_ENDPOINT` points it at another S3-compatible service, such as MinIO,
+/// addressed with path-style URLs.
+pub struct S3BlobStore {
+    bucket: Box<Bucket>,
+}
+
+impl S3BlobStore {
+    pub fn from_env() -> Result<Self> {
+        let name = std::env::var("S3_BUCKET").context("STORAGE_BACKEND=s3 needs S3_BUCKET")?;
+        let region_name = std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = std::env::var("S3_ENDPOINT").ok();
+        let region = match &endpoint {
+            Some(endpoint) => Region::Custom { region: region_name, endpoint: endpoint.clone() },
+            None => region_name.parse()?,
+        };
+        let credentials = Credentials::new(
+            std::env::var("S3_ACCESS_KEY_ID").ok().as_deref(),
+            std::env::var("S3_SECRET_ACCESS_KEY").ok().as_deref(),
+            None,
+            None,
+            None,
+        )?;
+
+        let bucket = Bucket::new(&name, region, credentials)?;
+        let bucket = if endpoint.is_some() { bucket.with_path_style() } else { bucket };
+        Ok(Self { bucket })
+    }
+}
+
+fn is_not_found(error: &S3Error) -> bool {
+    matches!(error, S3Error::HttpFailWithBody(404, _))
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> Result<()> {
+        self.bucket.put_object_with_content_type(key, &bytes, content_type).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.bucket.get_object(key).await {
+            Ok(response) => Ok(Some(response.bytes().to_vec())),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool> {
+        match self.bucket.head_object(key).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.bucket.delete_object(key).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_not_found(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -3,22 +3,54 @@ use crate::models::{
     Category, CreateCategory, UpdateCategory,
     Topic, CreateTopic, UpdateTopic, TopicWithDetails,
     Reply, CreateReply, UpdateReply, ReplyWithDetails,
-    UserProfile, UpdateUserProfile, Like, TopicView,
-    CategoryWithStats, UserStats, ForumStats, UserInfo
+    UserProfile, UpdateUserProfile, UserTopic, UserReply, Like, TopicView, LikeStatus, Liker, MergeOutcome, SplitOutcome,
+    CategoryWithStats, UserStats, ForumStats, UserInfo,
+    WikiPage, UpsertWikiPage, UpdateWikiPage, WikiRevision, WikiRevisionSummary,
+    TranslationRow, Discussion, SearchHit, SearchDocument, Suggestion, VocabularyWord, TrashItem,
+    Report, CreateReport, ReportQueueItem, UserWarning, Ban, AuditEntry, AuditQuery, NewAuditEntry,
+    ReputationEvent, UserBadge, BadgeHolder, DailyCount, LeaderboardEntry, Attachment, AttachTo,
+    ArchiveListing, AttachmentKind, AttachmentVariant, ChallengeFile, NewBlob, Notification, NotificationType,
 };
+use crate::i18n::DEFAULT_LANG;
+use crate::search::cjk_words;
+use crate::search_index::{IndexQuery, SearchIndex};
+use crate::trust::TrustRequirement;
 use anyhow::Result;
-use chrono::Utc;
-use sqlx::{PgPool, Row};
+use chrono::{DateTime, Utc};
+use sqlx::{types::Json, PgPool, Row};
+use std::sync::Arc;
+use tokio::sync::Notify;
 use uuid::Uuid;
 
+/// Reputation earned when a reply is accepted as a topic's solution.
+pub const SOLUTION_REPUTATION: i32 = 15;
+/// Reputation earned when a moderator features a topic.
+pub const FEATURED_TOPIC_REPUTATION: i32 = 10;
+
 pub struct Database {
     pool: PgPool,
+    search_index: Option<Arc<dyn SearchIndex>>,
+    changes: Arc<Notify>,
 }
 
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
         let pool = PgPool::connect(database_url).await?;
-        Ok(Self { pool })
+        Ok(Self { pool, search_index: None, changes: Arc::new(Notify::new()) })
+    }
+
+    /// Keeps `index` updated from every write from now on.
+    pub fn set_search_index(&mut self, index: Arc<dyn SearchIndex>) {
+        self.search_index = Some(index);
+    }
+
+    pub fn search_index(&self) -> Option<Arc<dyn SearchIndex>> {
+        self.search_index.clone()
+    }
+
+    /// Notified whenever a post, topic, reply, wiki page or user is written.
+    pub fn changes(&self) -> Arc<Notify> {
+        self.changes.clone()
     }
 
     pub async fn migrate(&self) -> Result<()> {
@@ -32,13 +64,18 @@ impl Database {
         let now = Utc::now();
         let published = post.published.unwrap_or(false);
         let tags = post.tags.unwrap_or_default();
+        let lang = post.lang.unwrap_or_else(|| DEFAULT_LANG.to_string());
 
+        // A translation joins the group of the post it translates
         let post = sqlx::query_as!(
             Post,
             r#"
-            INSERT INTO posts (id, title, content, summary, author, published, created_at, updated_at, tags)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-            RETURNING id, title, content, summary, author, published, created_at, updated_at, tags
+            INSERT INTO posts (id, title, content, summary, author, published, created_at, updated_at, tags,
+                               lang, translation_group, title_words, summary_words, content_words)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+                    COALESCE((SELECT translation_group FROM posts WHERE id = $11), $1), $12, $13, $14)
+            RETURNING id, title, content, summary, author, published, created_at, updated_at, tags,
+                      lang, translation_group
             "#,
             id,
             post.title,
@@ -48,18 +85,25 @@ impl Database {
             published,
             now,
             now,
-            &tags
+            &tags,
+            lang,
+            post.translation_of,
+            cjk_words(&post.title),
+            cjk_words(post.summary.as_deref().unwrap_or_default()),
+            cjk_words(&post.content)
         )
         .fetch_one(&self.pool)
         .await?;
 
+        self.content_changed(post.id).await;
         Ok(post)
     }
 
     pub async fn get_post(&self, id: Uuid) -> Result<Option<Post>> {
         let post = sqlx::query_as!(
             Post,
-            "SELECT id, title, content, summary, author, published, created_at, updated_at, tags FROM posts WHERE id = $1",
+            "SELECT id, title, content, summary, author, published, created_at, updated_at, tags, lang, translation_group
+             FROM posts WHERE id = $1 AND deleted_at IS NULL",
             id
         )
         .fetch_optional(&self.pool)
@@ -68,28 +112,31 @@ impl Database {
         Ok(post)
     }
 
-    pub async fn list_posts(&self, published_only: bool, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<Post>> {
+    pub async fn list_posts(&self, published_only: bool, lang: Option<&str>, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<Post>> {
         let limit = limit.unwrap_or(10);
         let offset = offset.unwrap_or(0);
 
         let posts = if published_only {
             sqlx::query_as!(
                 Post,
-                "SELECT id, title, content, summary, author, published, created_at, updated_at, tags
-                 FROM posts WHERE published = true
+                "SELECT id, title, content, summary, author, published, created_at, updated_at, tags, lang, translation_group
+                 FROM posts WHERE published = true AND deleted_at IS NULL AND ($3::text IS NULL OR lang = $3::text)
                  ORDER BY created_at DESC LIMIT $1 OFFSET $2",
                 limit,
-                offset
+                offset,
+                lang
             )
             .fetch_all(&self.pool)
             .await?
         } else {
             sqlx::query_as!(
                 Post,
-                "SELECT id, title, content, summary, author, published, created_at, updated_at, tags
-                 FROM posts ORDER BY created_at DESC LIMIT $1 OFFSET $2",
+                "SELECT id, title, content, summary, author, published, created_at, updated_at, tags, lang, translation_group
+                 FROM posts WHERE deleted_at IS NULL AND ($3::text IS NULL OR lang = $3::text)
+                 ORDER BY created_at DESC LIMIT $1 OFFSET $2",
                 limit,
-                offset
+                offset,
+                lang
             )
             .fetch_all(&self.pool)
             .await?
@@ -98,6 +145,21 @@ impl Database {
         Ok(posts)
     }
 
+    /// Every language version of a post, including the post itself.
+    pub async fn list_post_translations(&self, translation_group: Uuid) -> Result<Vec<Post>> {
+        let posts = sqlx::query_as!(
+            Post,
+            "SELECT id, title, content, summary, author, published, created_at, updated_at, tags, lang, translation_group
+             FROM posts WHERE translation_group = $1 AND deleted_at IS NULL
+             ORDER BY lang",
+            translation_group
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(posts)
+    }
+
     pub async fn update_post(&self, id: Uuid, update: UpdatePost) -> Result<Option<Post>> {
         let existing_post = self.get_post(id).await?;
         let Some(mut post) = existing_post else {
@@ -125,9 +187,11 @@ impl Database {
             Post,
             r#"
             UPDATE posts
-            SET title = $1, content = $2, summary = $3, published = $4, updated_at = $5, tags = $6
+            SET title = $1, content = $2, summary = $3, published = $4, updated_at = $5, tags = $6,
+                title_words = $8, summary_words = $9, content_words = $10
             WHERE id = $7
-            RETURNING id, title, content, summary, author, published, created_at, updated_at, tags
+            RETURNING id, title, content, summary, author, published, created_at, updated_at, tags,
+                      lang, translation_group
             "#,
             post.title,
             post.content,
@@ -135,19 +199,41 @@ impl Database {
             post.published,
             post.updated_at,
             &post.tags,
-            id
+            id,
+            cjk_words(&post.title),
+            cjk_words(post.summary.as_deref().unwrap_or_default()),
+            cjk_words(&post.content)
         )
         .fetch_one(&self.pool)
         .await?;
 
+        self.content_changed(id).await;
         Ok(Some(updated_post))
     }
 
-    pub async fn delete_post(&self, id: Uuid) -> Result<bool> {
-        let result = sqlx::query!("DELETE FROM posts WHERE id = $1", id)
-            .execute(&self.pool)
-            .await?;
+    /// Moves a post to the trash.
+    pub async fn delete_post(&self, id: Uuid, deleted_by: Option<Uuid>) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE posts SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1 AND deleted_at IS NULL",
+            id,
+            deleted_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.content_changed(id).await;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn restore_post(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE posts SET deleted_at = NULL, deleted_by = NULL, hidden_by_reports = false WHERE id = $1 AND deleted_at IS NOT NULL",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
 
+        self.content_changed(id).await;
         Ok(result.rows_affected() > 0)
     }
 
@@ -163,7 +249,9 @@ impl Database {
             r#"
             INSERT INTO users (id, username, email, password_hash, role, created_at)
             VALUES ($1, $2, $3, $4, $5, $6)
-            RETURNING id, username, email, password_hash, role as "role: UserRole", created_at
+            RETURNING id, username, email, password_hash, role as "role: UserRole", created_at,
+                      suspended_until, suspension_reason, suspended_by,
+                      silenced_until, silence_reason, silenced_by
             "#,
             id,
             user.username,
@@ -175,13 +263,16 @@ impl Database {
         .fetch_one(&self.pool)
         .await?;
 
+        self.content_changed(user.id).await;
         Ok(user)
     }
 
     pub async fn get_user_by_username(&self, username: &str) -> Result<Option<User>> {
         let user = sqlx::query_as!(
             User,
-            r#"SELECT id, username, email, password_hash, role as "role: UserRole", created_at
+            r#"SELECT id, username, email, password_hash, role as "role: UserRole", created_at,
+                      suspended_until, suspension_reason, suspended_by,
+                      silenced_until, silence_reason, silenced_by
                FROM users WHERE username = $1"#,
             username
         )
@@ -194,7 +285,9 @@ impl Database {
     pub async fn get_user_by_id(&self, id: Uuid) -> Result<Option<User>> {
         let user = sqlx::query_as!(
             User,
-            r#"SELECT id, username, email, password_hash, role as "role: UserRole", created_at
+            r#"SELECT id, username, email, password_hash, role as "role: UserRole", created_at,
+                      suspended_until, suspension_reason, suspended_by,
+                      silenced_until, silence_reason, silenced_by
                FROM users WHERE id = $1"#,
             id
         )
@@ -214,15 +307,16 @@ impl Database {
         let category = sqlx::query_as!(
             Category,
             r#"
-            INSERT INTO categories (id, name, description, color, icon, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            RETURNING id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, created_at
+            INSERT INTO categories (id, name, description, color, icon, min_trust_level, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, min_trust_level, created_at
             "#,
             id,
             category.name,
             category.description,
             category.color,
             category.icon,
+            category.min_trust_level,
             now
         )
         .fetch_one(&self.pool)
@@ -234,7 +328,7 @@ impl Database {
     pub async fn list_categories(&self) -> Result<Vec<CategoryWithStats>> {
         let categories = sqlx::query_as!(
             Category,
-            "SELECT id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, created_at
+            "SELECT id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, min_trust_level, created_at
              FROM categories ORDER BY sort_order, name"
         )
         .fetch_all(&self.pool)
@@ -257,7 +351,7 @@ impl Database {
     pub async fn get_category(&self, id: Uuid) -> Result<Option<Category>> {
         let category = sqlx::query_as!(
             Category,
-            "SELECT id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, created_at
+            "SELECT id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, min_trust_level, created_at
              FROM categories WHERE id = $1",
             id
         )
@@ -270,18 +364,19 @@ impl Database {
     async fn get_latest_topic_for_category(&self, category_id: Uuid) -> Result<Option<TopicWithDetails>> {
         let topic = sqlx::query!(
             r#"
-            SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count,
+            SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
                    t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
-                   t.created_at, t.updated_at,
+                   t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason,
                    c.name as category_name, c.description as category_description,
                    c.color as category_color, c.icon as category_icon, c.sort_order as category_sort_order,
                    c.topics_count as category_topics_count, c.posts_count as category_posts_count,
-                   c.last_post_at as category_last_post_at, c.created_at as category_created_at,
+                   c.last_post_at as category_last_post_at, c.min_trust_level as category_min_trust_level,
+                   c.created_at as category_created_at,
                    u.username, u.email, u.role as "role: UserRole"
             FROM topics t
             JOIN categories c ON t.category_id = c.id
             JOIN users u ON t.user_id = u.id
-            WHERE t.category_id = $1
+            WHERE t.category_id = $1 AND t.deleted_at IS NULL
             ORDER BY t.created_at DESC
             LIMIT 1
             "#,
@@ -299,11 +394,16 @@ impl Database {
                 user_id: row.user_id,
                 views: row.views,
                 replies_count: row.replies_count,
+                likes_count: row.likes_count,
                 is_pinned: row.is_pinned,
                 is_locked: row.is_locked,
                 is_solved: row.is_solved,
                 last_reply_at: row.last_reply_at,
                 last_reply_user_id: row.last_reply_user_id,
+                is_pinned_globally: row.is_pinned_globally,
+                lock_reason: row.lock_reason,
+                edited_at: row.edited_at,
+                edited_by: row.edited_by,
                 created_at: row.created_at,
                 updated_at: row.updated_at,
             };
@@ -318,6 +418,7 @@ impl Database {
                 topics_count: row.category_topics_count,
                 posts_count: row.category_posts_count,
                 last_post_at: row.category_last_post_at,
+                min_trust_level: row.category_min_trust_level,
                 created_at: row.category_created_at,
             };
 
@@ -341,21 +442,34 @@ impl Database {
 
     // Topic operations
     pub async fn create_topic(&self, topic: CreateTopic, user_id: Uuid) -> Result<Topic> {
-        let id = Uuid::new_v4();
-        let now = Utc::now();
         let slug = self.generate_slug(&topic.title).await?;
 
         // Start transaction to create topic and first reply
         let mut tx = self.pool.begin().await?;
+        let created = Self::insert_topic(&mut tx, &topic, &slug, user_id).await?;
+        tx.commit().await?;
 
-        let topic = sqlx::query_as!(
+        self.content_changed(created.id).await;
+        Ok(created)
+    }
+
+    async fn insert_topic(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        topic: &CreateTopic,
+        slug: &str,
+        user_id: Uuid,
+    ) -> Result<Topic> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let created = sqlx::query_as!(
             Topic,
             r#"
-            INSERT INTO topics (id, category_id, title, slug, user_id, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id, category_id, title, slug, user_id, views, replies_count,
+            INSERT INTO topics (id, category_id, title, slug, user_id, created_at, updated_at, title_words)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, category_id, title, slug, user_id, views, replies_count, likes_count,
                       is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
-                      created_at, updated_at
+                      created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
             "#,
             id,
             topic.category_id,
@@ -363,78 +477,86 @@ impl Database {
             slug,
             user_id,
             now,
-            now
+            now,
+            cjk_words(&topic.title)
         )
-        .fetch_one(&mut *tx)
+        .fetch_one(&mut **tx)
         .await?;
 
         // Create the initial post as the first reply
         let reply_id = Uuid::new_v4();
         sqlx::query!(
-            "INSERT INTO replies (id, topic_id, user_id, content, created_at, updated_at)
-             VALUES ($1, $2, $3, $4, $5, $6)",
+            "INSERT INTO replies (id, topic_id, user_id, content, created_at, updated_at, content_words)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
             reply_id,
-            topic.id,
+            created.id,
             user_id,
             topic.content,
             now,
-            now
+            now,
+            cjk_words(&topic.content)
         )
-        .execute(&mut *tx)
+        .execute(&mut **tx)
         .await?;
 
-        tx.commit().await?;
-
-        Ok(topic)
+        Ok(created)
     }
 
-    pub async fn list_topics(&self, category_id: Option<Uuid>, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<TopicWithDetails>> {
+    /// Topics, pinned first, then by latest activity. `solved` keeps only
+    /// solved or unsolved topics.
+    pub async fn list_topics(&self, category_id: Option<Uuid>, solved: Option<bool>, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<TopicWithDetails>> {
         let limit = limit.unwrap_or(20);
         let offset = offset.unwrap_or(0);
 
         let topics = if let Some(cat_id) = category_id {
             sqlx::query!(
                 r#"
-                SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count,
+                SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
                        t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
-                       t.created_at, t.updated_at,
+                       t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason,
                        c.name as category_name, c.description as category_description,
                        c.color as category_color, c.icon as category_icon, c.sort_order as category_sort_order,
                        c.topics_count as category_topics_count, c.posts_count as category_posts_count,
-                       c.last_post_at as category_last_post_at, c.created_at as category_created_at,
+                       c.last_post_at as category_last_post_at, c.min_trust_level as category_min_trust_level,
+                       c.created_at as category_created_at,
                        u.username, u.email, u.role as "role: UserRole"
                 FROM topics t
                 JOIN categories c ON t.category_id = c.id
                 JOIN users u ON t.user_id = u.id
-                WHERE t.category_id = $1
+                WHERE t.category_id = $1 AND t.deleted_at IS NULL
+                  AND ($4::bool IS NULL OR t.is_solved = $4)
                 ORDER BY t.is_pinned DESC, t.last_reply_at DESC NULLS LAST, t.created_at DESC
                 LIMIT $2 OFFSET $3
                 "#,
                 cat_id,
                 limit,
-                offset
+                offset,
+                solved
             )
             .fetch_all(&self.pool)
             .await?
         } else {
             sqlx::query!(
                 r#"
-                SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count,
+                SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
                        t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
-                       t.created_at, t.updated_at,
+                       t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason,
                        c.name as category_name, c.description as category_description,
                        c.color as category_color, c.icon as category_icon, c.sort_order as category_sort_order,
                        c.topics_count as category_topics_count, c.posts_count as category_posts_count,
-                       c.last_post_at as category_last_post_at, c.created_at as category_created_at,
+                       c.last_post_at as category_last_post_at, c.min_trust_level as category_min_trust_level,
+                       c.created_at as category_created_at,
                        u.username, u.email, u.role as "role: UserRole"
                 FROM topics t
                 JOIN categories c ON t.category_id = c.id
                 JOIN users u ON t.user_id = u.id
-                ORDER BY t.is_pinned DESC, t.last_reply_at DESC NULLS LAST, t.created_at DESC
+                WHERE t.deleted_at IS NULL AND ($3::bool IS NULL OR t.is_solved = $3)
+                ORDER BY t.is_pinned_globally DESC, t.last_reply_at DESC NULLS LAST, t.created_at DESC
                 LIMIT $1 OFFSET $2
                 "#,
                 limit,
-                offset
+                offset,
+                solved
             )
             .fetch_all(&self.pool)
             .await?
@@ -450,11 +572,16 @@ impl Database {
                 user_id: row.user_id,
                 views: row.views,
                 replies_count: row.replies_count,
+                likes_count: row.likes_count,
                 is_pinned: row.is_pinned,
                 is_locked: row.is_locked,
                 is_solved: row.is_solved,
                 last_reply_at: row.last_reply_at,
                 last_reply_user_id: row.last_reply_user_id,
+                is_pinned_globally: row.is_pinned_globally,
+                lock_reason: row.lock_reason,
+                edited_at: row.edited_at,
+                edited_by: row.edited_by,
                 created_at: row.created_at,
                 updated_at: row.updated_at,
             };
@@ -469,6 +596,7 @@ impl Database {
                 topics_count: row.category_topics_count,
                 posts_count: row.category_posts_count,
                 last_post_at: row.category_last_post_at,
+                min_trust_level: row.category_min_trust_level,
                 created_at: row.category_created_at,
             };
 
@@ -493,18 +621,19 @@ impl Database {
     pub async fn get_topic_by_slug(&self, slug: &str) -> Result<Option<TopicWithDetails>> {
         let row = sqlx::query!(
             r#"
-            SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count,
+            SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
                    t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
-                   t.created_at, t.updated_at,
+                   t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason,
                    c.name as category_name, c.description as category_description,
                    c.color as category_color, c.icon as category_icon, c.sort_order as category_sort_order,
                    c.topics_count as category_topics_count, c.posts_count as category_posts_count,
-                   c.last_post_at as category_last_post_at, c.created_at as category_created_at,
+                   c.last_post_at as category_last_post_at, c.min_trust_level as category_min_trust_level,
+                   c.created_at as category_created_at,
                    u.username, u.email, u.role as "role: UserRole"
             FROM topics t
             JOIN categories c ON t.category_id = c.id
             JOIN users u ON t.user_id = u.id
-            WHERE t.slug = $1
+            WHERE t.slug = $1 AND t.deleted_at IS NULL
             "#,
             slug
         )
@@ -520,11 +649,16 @@ impl Database {
                 user_id: row.user_id,
                 views: row.views,
                 replies_count: row.replies_count,
+                likes_count: row.likes_count,
                 is_pinned: row.is_pinned,
                 is_locked: row.is_locked,
                 is_solved: row.is_solved,
                 last_reply_at: row.last_reply_at,
                 last_reply_user_id: row.last_reply_user_id,
+                is_pinned_globally: row.is_pinned_globally,
+                lock_reason: row.lock_reason,
+                edited_at: row.edited_at,
+                edited_by: row.edited_by,
                 created_at: row.created_at,
                 updated_at: row.updated_at,
             };
@@ -539,6 +673,7 @@ impl Database {
                 topics_count: row.category_topics_count,
                 posts_count: row.category_posts_count,
                 last_post_at: row.category_last_post_at,
+                min_trust_level: row.category_min_trust_level,
                 created_at: row.category_created_at,
             };
 
@@ -594,9 +729,9 @@ impl Database {
         let reply = sqlx::query_as!(
             Reply,
             r#"
-            INSERT INTO replies (id, topic_id, user_id, content, reply_to_id, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
-            RETURNING id, topic_id, user_id, content, is_solution, likes_count, reply_to_id, created_at, updated_at
+            INSERT INTO replies (id, topic_id, user_id, content, reply_to_id, created_at, updated_at, content_words)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, topic_id, user_id, content, is_solution, likes_count, reply_to_id, created_at, updated_at, edited_at, edited_by
             "#,
             id,
             topic_id,
@@ -604,32 +739,38 @@ impl Database {
             reply.content,
             reply.reply_to_id,
             now,
-            now
+            now,
+            cjk_words(&reply.content)
         )
         .fetch_one(&self.pool)
         .await?;
 
+        self.content_changed(reply.id).await;
         Ok(reply)
     }
 
-    pub async fn list_replies(&self, topic_id: Uuid, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<ReplyWithDetails>> {
+    /// Replies to a topic, oldest first. `viewer` is the signed-in user, if
+    /// any, for the `liked_by_me` flags.
+    pub async fn list_replies(&self, topic_id: Uuid, viewer: Option<Uuid>, limit: Option<i64>, offset: Option<i64>) -> Result<Vec<ReplyWithDetails>> {
         let limit = limit.unwrap_or(20);
         let offset = offset.unwrap_or(0);
 
         let replies = sqlx::query!(
             r#"
             SELECT r.id, r.topic_id, r.user_id, r.content, r.is_solution, r.likes_count,
-                   r.reply_to_id, r.created_at, r.updated_at,
-                   u.username, u.email, u.role as "role: UserRole"
+                   r.reply_to_id, r.created_at, r.updated_at, r.edited_at, r.edited_by,
+                   u.username, u.email, u.role as "role: UserRole",
+                   EXISTS(SELECT 1 FROM likes l WHERE l.reply_id = r.id AND l.user_id = $4) as "liked_by_me!"
             FROM replies r
             JOIN users u ON r.user_id = u.id
-            WHERE r.topic_id = $1
+            WHERE r.topic_id = $1 AND r.deleted_at IS NULL
             ORDER BY r.created_at ASC
             LIMIT $2 OFFSET $3
             "#,
             topic_id,
             limit,
-            offset
+            offset,
+            viewer as Option<Uuid>
         )
         .fetch_all(&self.pool)
         .await?;
@@ -644,6 +785,8 @@ impl Database {
                 is_solution: row.is_solution,
                 likes_count: row.likes_count,
                 reply_to_id: row.reply_to_id,
+                edited_at: row.edited_at,
+                edited_by: row.edited_by,
                 created_at: row.created_at,
                 updated_at: row.updated_at,
             };
@@ -659,26 +802,3068 @@ impl Database {
                 reply,
                 user,
                 reply_to_user: None, // TODO: Fetch reply-to user if needed
+                liked_by_me: row.liked_by_me,
             });
         }
 
         Ok(result)
     }
 
-    // User profile operations
-    pub async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfile>> {
-        let profile = sqlx::query_as!(
-            UserProfile,
-            "SELECT user_id, display_name, bio, avatar_url, location, website, reputation,
-                    topics_count, replies_count, likes_given, likes_received, last_seen_at,
-                    created_at, updated_at
-             FROM user_profiles WHERE user_id = $1",
+    pub async fn get_reply(&self, id: Uuid) -> Result<Option<Reply>> {
+        let reply = sqlx::query_as!(
+            Reply,
+            "SELECT id, topic_id, user_id, content, is_solution, likes_count, reply_to_id, created_at, updated_at, edited_at, edited_by
+             FROM replies WHERE id = $1 AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(reply)
+    }
+
+    pub async fn get_topic(&self, id: Uuid) -> Result<Option<Topic>> {
+        let topic = sqlx::query_as!(
+            Topic,
+            "SELECT id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                    is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                    created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+             FROM topics WHERE id = $1 AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(topic)
+    }
+
+    /// The opening post of a topic, which is stored as its first reply.
+    pub async fn get_first_reply_id(&self, topic_id: Uuid) -> Result<Option<Uuid>> {
+        let id = sqlx::query_scalar!(
+            "SELECT id FROM replies WHERE topic_id = $1 ORDER BY created_at ASC LIMIT 1",
+            topic_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Whether anyone but `user_id` has a reply in the topic, outside the
+    /// trash.
+    pub async fn has_replies_from_others(&self, topic_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(
+                   SELECT 1 FROM replies WHERE topic_id = $1 AND user_id <> $2 AND deleted_at IS NULL
+               ) as "exists!""#,
+            topic_id,
             user_id
         )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    // Edit operations
+    // Only a changed title or content marks a topic or reply as edited
+
+    pub async fn update_topic(&self, id: Uuid, update: UpdateTopic, edited_by: Uuid) -> Result<Option<Topic>> {
+        let now = Utc::now();
+
+        let topic = sqlx::query_as!(
+            Topic,
+            r#"
+            UPDATE topics
+            SET title = COALESCE($2, title), title_words = COALESCE($3, title_words),
+                is_pinned = COALESCE($4, is_pinned), is_pinned_globally = is_pinned_globally AND COALESCE($4, true),
+                is_locked = COALESCE($5, is_locked), lock_reason = CASE WHEN COALESCE($5, true) THEN lock_reason END,
+                edited_at = CASE WHEN $2 <> title THEN $6 ELSE edited_at END,
+                edited_by = CASE WHEN $2 <> title THEN $7 ELSE edited_by END,
+                updated_at = $6
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                      is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                      created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+            "#,
+            id,
+            update.title,
+            update.title.as_deref().map(cjk_words),
+            update.is_pinned,
+            update.is_locked,
+            now,
+            edited_by
+        )
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(profile)
+        if topic.is_some() {
+            self.content_changed(id).await;
+        }
+        Ok(topic)
+    }
+
+    pub async fn update_reply(&self, id: Uuid, update: UpdateReply, edited_by: Uuid) -> Result<Option<Reply>> {
+        let now = Utc::now();
+
+        let reply = sqlx::query_as!(
+            Reply,
+            r#"
+            UPDATE replies
+            SET content = $2, content_words = $3,
+                edited_at = CASE WHEN $2 <> content THEN $4 ELSE edited_at END,
+                edited_by = CASE WHEN $2 <> content THEN $5 ELSE edited_by END,
+                updated_at = $4
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, topic_id, user_id, content, is_solution, likes_count, reply_to_id, created_at, updated_at, edited_at, edited_by
+            "#,
+            id,
+            update.content,
+            cjk_words(&update.content),
+            now,
+            edited_by
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(reply) = reply else {
+            return Ok(None);
+        };
+
+        // The opening post is also the body of the topic's search document
+        if self.get_first_reply_id(reply.topic_id).await? == Some(reply.id) {
+            self.content_changed(reply.topic_id).await;
+        } else {
+            self.content_changed(reply.id).await;
+        }
+        Ok(Some(reply))
+    }
+
+    // Trash operations
+    // Deleting only moves a row to the trash; the counter triggers treat
+    // that as removing it and a restore as adding it back
+
+    /// Moves a topic, with its replies, to the trash.
+    pub async fn delete_topic(&self, id: Uuid, deleted_by: Option<Uuid>) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE topics SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1 AND deleted_at IS NULL",
+            id,
+            deleted_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.content_changed(id).await;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn restore_topic(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE topics SET deleted_at = NULL, deleted_by = NULL, hidden_by_reports = false WHERE id = $1 AND deleted_at IS NOT NULL",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        self.content_changed(id).await;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Moves a reply other than the opening post to the trash. Its topic
+    /// goes back to unsolved if it was the solution, and the topic's last
+    /// reply becomes the one before it.
+    pub async fn delete_reply(&self, id: Uuid, deleted_by: Option<Uuid>) -> Result<bool> {
+        let Some(reply) = self.get_reply(id).await? else {
+            return Ok(false);
+        };
+        let mut tx = self.pool.begin().await?;
+
+        if reply.is_solution {
+            Self::update_solution(&mut tx, reply.topic_id, None).await?;
+        }
+
+        let result = sqlx::query!(
+            "UPDATE replies SET deleted_at = NOW(), deleted_by = $2 WHERE id = $1 AND deleted_at IS NULL",
+            id,
+            deleted_by
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Self::refresh_last_reply(&mut tx, reply.topic_id).await?;
+        tx.commit().await?;
+
+        self.content_changed(id).await;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Restores a reply, unless its topic is in the trash too. It comes back
+    /// as an ordinary reply even if it was the solution.
+    pub async fn restore_reply(&self, id: Uuid) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let topic_id = sqlx::query_scalar!(
+            r#"
+            UPDATE replies r SET deleted_at = NULL, deleted_by = NULL, hidden_by_reports = false
+            FROM topics t
+            WHERE r.id = $1 AND r.deleted_at IS NOT NULL AND t.id = r.topic_id AND t.deleted_at IS NULL
+            RETURNING r.topic_id
+            "#,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(topic_id) = topic_id else {
+            return Ok(false);
+        };
+
+        Self::refresh_last_reply(&mut tx, topic_id).await?;
+        tx.commit().await?;
+
+        self.content_changed(id).await;
+        Ok(true)
+    }
+
+    async fn refresh_last_reply(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, topic_id: Uuid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE topics
+            SET (last_reply_at, last_reply_user_id) = (
+                SELECT created_at, user_id FROM replies
+                WHERE topic_id = $1 AND deleted_at IS NULL
+                ORDER BY created_at DESC LIMIT 1
+            )
+            WHERE id = $1
+            "#,
+            topic_id
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deleted posts, topics and replies, most recently deleted first.
+    /// `content_type` keeps one of `post`, `topic` or `reply`.
+    pub async fn list_trash(
+        &self,
+        content_type: Option<&str>,
+        retention_days: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<TrashItem>> {
+        let items = sqlx::query_as!(
+            TrashItem,
+            r#"
+            SELECT content_type as "content_type!", id as "id!", topic_id, title as "title!",
+                   excerpt as "excerpt!", author as "author!", deleted_at as "deleted_at!",
+                   deleted_by, deleted_by_username, hidden_by_reports as "hidden_by_reports!",
+                   deleted_at + make_interval(days => $1) as "purge_at!"
+            FROM (
+                SELECT 'post' as content_type, p.id, NULL::uuid as topic_id, p.title::text as title,
+                       left(p.content, 200) as excerpt, p.author::text as author, p.deleted_at, p.deleted_by,
+                       p.hidden_by_reports
+                FROM posts p
+                WHERE p.deleted_at IS NOT NULL
+                UNION ALL
+                SELECT 'topic', t.id, t.id, t.title, left(coalesce(op.content, ''), 200), a.username,
+                       t.deleted_at, t.deleted_by, t.hidden_by_reports
+                FROM topics t
+                JOIN users a ON a.id = t.user_id
+                LEFT JOIN LATERAL (
+                    SELECT content FROM replies WHERE topic_id = t.id ORDER BY created_at LIMIT 1
+                ) op ON true
+                WHERE t.deleted_at IS NOT NULL
+                UNION ALL
+                SELECT 'reply', r.id, r.topic_id, t.title, left(r.content, 200), a.username,
+                       r.deleted_at, r.deleted_by, r.hidden_by_reports
+                FROM replies r
+                JOIN topics t ON t.id = r.topic_id
+                JOIN users a ON a.id = r.user_id
+                WHERE r.deleted_at IS NOT NULL
+            ) trash
+            LEFT JOIN LATERAL (SELECT username as deleted_by_username FROM users WHERE id = trash.deleted_by) u ON true
+            WHERE $2::text IS NULL OR content_type = $2
+            ORDER BY deleted_at DESC
+            LIMIT $3 OFFSET $4
+            "#,
+            retention_days,
+            content_type,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    /// Permanently deletes posts, topics and replies that have been in the
+    /// trash for more than `retention_days`, except content hidden by reports
+    /// that no moderator has looked at. Returns how many were purged.
+    pub async fn purge_trash(&self, retention_days: i32) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        // Replies first, so those purged with their topic are counted once
+        let replies = sqlx::query!(
+            "DELETE FROM replies WHERE deleted_at < NOW() - make_interval(days => $1) AND NOT hidden_by_reports",
+            retention_days
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let topics = sqlx::query!(
+            "DELETE FROM topics WHERE deleted_at < NOW() - make_interval(days => $1) AND NOT hidden_by_reports",
+            retention_days
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let posts = sqlx::query!(
+            "DELETE FROM posts WHERE deleted_at < NOW() - make_interval(days => $1) AND NOT hidden_by_reports",
+            retention_days
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(replies.rows_affected() + topics.rows_affected() + posts.rows_affected())
+    }
+
+    // Moderation operations
+
+    /// Pins a topic to the top of its category and, with `globally`, of the
+    /// list of all topics; or unpins it.
+    pub async fn set_topic_pinned(&self, id: Uuid, pinned: bool, globally: bool) -> Result<Option<Topic>> {
+        let topic = sqlx::query_as!(
+            Topic,
+            r#"
+            UPDATE topics SET is_pinned = $2, is_pinned_globally = $2 AND $3
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                      is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                      created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+            "#,
+            id,
+            pinned,
+            globally
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(topic)
+    }
+
+    /// Locks a topic against new replies, with an optional reason for
+    /// readers, or unlocks it.
+    pub async fn set_topic_locked(&self, id: Uuid, locked: bool, reason: Option<&str>) -> Result<Option<Topic>> {
+        let topic = sqlx::query_as!(
+            Topic,
+            r#"
+            UPDATE topics SET is_locked = $2, lock_reason = CASE WHEN $2 THEN $3 END
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                      is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                      created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+            "#,
+            id,
+            locked,
+            reason
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(topic)
+    }
+
+    /// Moves a topic to another category; the counter trigger moves its
+    /// counts along.
+    pub async fn move_topic(&self, id: Uuid, category_id: Uuid) -> Result<Option<Topic>> {
+        let topic = sqlx::query_as!(
+            Topic,
+            r#"
+            UPDATE topics SET category_id = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            RETURNING id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                      is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                      created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+            "#,
+            id,
+            category_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if topic.is_some() {
+            self.content_changed(id).await;
+        }
+        Ok(topic)
+    }
+
+    /// Moves every reply of `source_id`, its opening post included, into
+    /// `target_id` and removes the emptied source topic. When the source
+    /// started before the target, its replies are moved later, keeping their
+    /// spacing, so the target's opening post stays first. The source's
+    /// solution is unaccepted and its likes become likes on its opening
+    /// post; its views and page discussion carry over. Two page discussions
+    /// can't be merged, as neither page may lose its topic.
+    pub async fn merge_topics(&self, source_id: Uuid, target_id: Uuid) -> Result<MergeOutcome> {
+        let mut tx = self.pool.begin().await?;
+
+        // Lock both topics, in a fixed order so concurrent merges can't deadlock
+        sqlx::query!(
+            "SELECT id FROM topics WHERE id = ANY($1) ORDER BY id FOR UPDATE",
+            &[source_id, target_id][..]
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let discussions = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM discussion_links WHERE topic_id = ANY($1)"#,
+            &[source_id, target_id][..]
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if discussions > 1 {
+            return Ok(MergeOutcome::BothDiscussions);
+        }
+
+        Self::update_solution(&mut tx, source_id, None).await?;
+
+        // Moved while the source still exists, so the like triggers find its
+        // author and likes_received stays right
+        sqlx::query!(
+            r#"
+            INSERT INTO likes (id, user_id, reply_id, created_at)
+            SELECT gen_random_uuid(), l.user_id, r.id, l.created_at
+            FROM topic_likes l
+            JOIN LATERAL (
+                SELECT id FROM replies WHERE topic_id = $1 ORDER BY created_at LIMIT 1
+            ) r ON true
+            WHERE l.topic_id = $1
+            ON CONFLICT (user_id, reply_id) DO NOTHING
+            "#,
+            source_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM topic_likes WHERE topic_id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE replies
+            SET topic_id = $2,
+                created_at = created_at + GREATEST(interval '0', (
+                    (SELECT MIN(created_at) FROM replies WHERE topic_id = $2) + interval '1 millisecond'
+                    - (SELECT MIN(created_at) FROM replies WHERE topic_id = $1)
+                ))
+            WHERE topic_id = $1
+            "#,
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE topics SET views = views + (SELECT views FROM topics WHERE id = $1) WHERE id = $2",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE discussion_links SET topic_id = $2 WHERE topic_id = $1",
+            source_id,
+            target_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!("DELETE FROM topics WHERE id = $1", source_id)
+            .execute(&mut *tx)
+            .await?;
+
+        Self::refresh_last_reply(&mut tx, target_id).await?;
+        tx.commit().await?;
+
+        self.content_changed(source_id).await;
+        self.content_changed(target_id).await;
+        Ok(MergeOutcome::Merged)
+    }
+
+    /// Moves `reply_ids` out of `source_id` into a new topic in
+    /// `category_id`. The earliest of them becomes its opening post and that
+    /// reply's author the topic's author. Only live replies other than the
+    /// source's opening post move.
+    pub async fn split_topic(
+        &self,
+        source_id: Uuid,
+        reply_ids: &[Uuid],
+        title: &str,
+        category_id: Uuid,
+    ) -> Result<SplitOutcome> {
+        let opening_post = self.get_first_reply_id(source_id).await?;
+        let slug = self.generate_slug(title).await?;
+        let mut tx = self.pool.begin().await?;
+
+        // Kept from being deleted until the topic is in it
+        let category = sqlx::query_scalar!("SELECT id FROM categories WHERE id = $1 FOR KEY SHARE", category_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if category.is_none() {
+            return Ok(SplitOutcome::UnknownCategory);
+        }
+
+        let first = sqlx::query!(
+            "SELECT user_id FROM replies
+             WHERE topic_id = $1 AND id = ANY($2) AND id IS DISTINCT FROM $3 AND deleted_at IS NULL
+             ORDER BY created_at LIMIT 1",
+            source_id,
+            reply_ids,
+            opening_post
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(first) = first else {
+            return Ok(SplitOutcome::NothingToMove);
+        };
+
+        let moves_solution = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM replies WHERE topic_id = $1 AND id = ANY($2) AND is_solution) as "exists!""#,
+            source_id,
+            reply_ids
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if moves_solution {
+            Self::update_solution(&mut tx, source_id, None).await?;
+        }
+
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        sqlx::query!(
+            "INSERT INTO topics (id, category_id, title, slug, user_id, created_at, updated_at, title_words)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            id,
+            category_id,
+            title,
+            slug,
+            first.user_id,
+            now,
+            now,
+            cjk_words(title)
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "UPDATE replies SET topic_id = $2
+             WHERE topic_id = $1 AND id = ANY($3) AND id IS DISTINCT FROM $4 AND deleted_at IS NULL",
+            source_id,
+            id,
+            reply_ids,
+            opening_post
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Self::refresh_last_reply(&mut tx, source_id).await?;
+        Self::refresh_last_reply(&mut tx, id).await?;
+
+        let topic = sqlx::query_as!(
+            Topic,
+            "SELECT id, category_id, title, slug, user_id, views, replies_count, likes_count,
+                    is_pinned, is_locked, is_solved, last_reply_at, last_reply_user_id,
+                    created_at, updated_at, edited_at, edited_by, is_pinned_globally, lock_reason
+             FROM topics WHERE id = $1",
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.content_changed(source_id).await;
+        self.content_changed(id).await;
+        Ok(SplitOutcome::Split(Box::new(topic)))
+    }
+
+    // Report operations
+    // A target is a `post`, `topic`, `reply` or `user`; reports about it
+    // stay open until a moderator resolves them together
+
+    /// Files a report, or returns `None` if the reporter already has an open
+    /// report about the same target.
+    pub async fn create_report(&self, reporter_id: Uuid, report: CreateReport) -> Result<Option<Report>> {
+        let report = sqlx::query_as!(
+            Report,
+            r#"
+            INSERT INTO reports (id, reporter_id, content_type, content_id, reason, details)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (reporter_id, content_type, content_id) WHERE status = 'open' DO NOTHING
+            RETURNING id, reporter_id, content_type, content_id, reason, details, status,
+                      resolution, resolved_by, resolved_at, created_at
+            "#,
+            Uuid::new_v4(),
+            reporter_id,
+            report.content_type.as_str(),
+            report.content_id,
+            report.reason.as_str(),
+            report.details
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(report)
+    }
+
+    pub async fn get_report(&self, id: Uuid) -> Result<Option<Report>> {
+        let report = sqlx::query_as!(
+            Report,
+            "SELECT id, reporter_id, content_type, content_id, reason, details, status,
+                    resolution, resolved_by, resolved_at, created_at
+             FROM reports WHERE id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(report)
+    }
+
+    /// How many members have open reports about a target.
+    pub async fn count_open_reporters(&self, content_type: &str, content_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(DISTINCT reporter_id) as "count!" FROM reports
+               WHERE content_type = $1 AND content_id = $2 AND status = 'open'"#,
+            content_type,
+            content_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Reported targets with open reports, most reported first.
+    pub async fn list_report_queue(&self, limit: i64, offset: i64) -> Result<Vec<ReportQueueItem>> {
+        let items = sqlx::query_as!(
+            ReportQueueItem,
+            r#"
+            SELECT r.content_type as "content_type!", r.content_id as "content_id!",
+                   array_agg(r.id ORDER BY r.created_at) as "report_ids!",
+                   COUNT(*) as "report_count!",
+                   array_agg(DISTINCT r.reason) as "reasons!",
+                   coalesce(array_agg(r.details ORDER BY r.created_at) FILTER (WHERE r.details IS NOT NULL), '{}') as "details!",
+                   target.title, target.excerpt, target.user_id, target.author, target.url,
+                   coalesce(target.hidden, false) as "hidden!",
+                   MIN(r.created_at) as "first_reported_at!", MAX(r.created_at) as "last_reported_at!"
+            FROM reports r
+            LEFT JOIN LATERAL (
+                SELECT p.title::text as title, left(p.content, 200) as excerpt, NULL::uuid as user_id,
+                       p.author::text as author, '/' || p.lang || '/posts/' || p.id::text as url,
+                       p.deleted_at IS NOT NULL as hidden
+                FROM posts p
+                WHERE r.content_type = 'post' AND p.id = r.content_id
+                UNION ALL
+                SELECT t.title, left(coalesce(op.content, ''), 200), t.user_id, a.username,
+                       '/forum/t/' || t.slug, t.deleted_at IS NOT NULL
+                FROM topics t
+                JOIN users a ON a.id = t.user_id
+                LEFT JOIN LATERAL (
+                    SELECT content FROM replies WHERE topic_id = t.id ORDER BY created_at LIMIT 1
+                ) op ON true
+                WHERE r.content_type = 'topic' AND t.id = r.content_id
+                UNION ALL
+                SELECT t.title, left(rp.content, 200), rp.user_id, a.username,
+                       '/forum/t/' || t.slug || '#reply-' || rp.id::text,
+                       rp.deleted_at IS NOT NULL OR t.deleted_at IS NOT NULL
+                FROM replies rp
+                JOIN topics t ON t.id = rp.topic_id
+                JOIN users a ON a.id = rp.user_id
+                WHERE r.content_type = 'reply' AND rp.id = r.content_id
+                UNION ALL
+                SELECT u.username::text, up.bio, u.id, u.username::text, NULL::text, false
+                FROM users u
+                LEFT JOIN user_profiles up ON up.user_id = u.id
+                WHERE r.content_type = 'user' AND u.id = r.content_id
+            ) target ON true
+            WHERE r.status = 'open'
+            GROUP BY r.content_type, r.content_id, target.title, target.excerpt, target.user_id,
+                     target.author, target.url, target.hidden
+            ORDER BY COUNT(*) DESC, MIN(r.created_at)
+            LIMIT $1 OFFSET $2
+            "#,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    /// Closes every open report about a target as `dismissed` or
+    /// `actioned`. Returns how many were closed.
+    pub async fn resolve_reports(
+        &self,
+        content_type: &str,
+        content_id: Uuid,
+        status: &str,
+        resolution: &str,
+        resolved_by: Uuid,
+    ) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE reports
+            SET status = $3, resolution = $4, resolved_by = $5, resolved_at = NOW()
+            WHERE content_type = $1 AND content_id = $2 AND status = 'open'
+            "#,
+            content_type,
+            content_id,
+            status,
+            resolution,
+            resolved_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// The member answerable for a target, even if it is in the trash: the
+    /// author of a topic or reply, or the user. Posts have none.
+    pub async fn reported_user_id(&self, content_type: &str, content_id: Uuid) -> Result<Option<Uuid>> {
+        let user_id = sqlx::query_scalar!(
+            r#"
+            SELECT user_id as "user_id!" FROM topics WHERE $1 = 'topic' AND id = $2
+            UNION ALL
+            SELECT user_id FROM replies WHERE $1 = 'reply' AND id = $2
+            UNION ALL
+            SELECT id FROM users WHERE $1 = 'user' AND id = $2
+            "#,
+            content_type,
+            content_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    /// Moves reported content to the trash, deleted by `hidden_by`, or marked
+    /// as hidden by reports when hidden automatically. A moderator hiding
+    /// content that reports already hid takes it over, so it is purged like
+    /// anything else in the trash. Hiding a topic's opening post hides the
+    /// topic. Users can't be hidden.
+    pub async fn hide_reported_content(
+        &self,
+        content_type: &str,
+        content_id: Uuid,
+        hidden_by: Option<Uuid>,
+    ) -> Result<bool> {
+        let (content_type, id) = match content_type {
+            "post" | "topic" => (content_type, content_id),
+            "reply" => {
+                let Some(reply) = self.get_reply(content_id).await? else {
+                    return Ok(false);
+                };
+                if self.get_first_reply_id(reply.topic_id).await? == Some(reply.id) {
+                    ("topic", reply.topic_id)
+                } else {
+                    ("reply", reply.id)
+                }
+            }
+            _ => return Ok(false),
+        };
+
+        let hidden = match content_type {
+            "post" => self.delete_post(id, hidden_by).await?,
+            "topic" => self.delete_topic(id, hidden_by).await?,
+            _ => self.delete_reply(id, hidden_by).await?,
+        };
+
+        match hidden_by {
+            None if hidden => self.mark_hidden_by_reports(content_type, id).await.map(|_| true),
+            Some(moderator_id) if !hidden => self.take_over_hidden(content_type, id, moderator_id).await,
+            _ => Ok(hidden),
+        }
+    }
+
+    async fn mark_hidden_by_reports(&self, content_type: &str, id: Uuid) -> Result<()> {
+        let query = match content_type {
+            "post" => sqlx::query!("UPDATE posts SET hidden_by_reports = true WHERE id = $1", id),
+            "topic" => sqlx::query!("UPDATE topics SET hidden_by_reports = true WHERE id = $1", id),
+            _ => sqlx::query!("UPDATE replies SET hidden_by_reports = true WHERE id = $1", id),
+        };
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn take_over_hidden(&self, content_type: &str, id: Uuid, moderator_id: Uuid) -> Result<bool> {
+        let query = match content_type {
+            "post" => sqlx::query!(
+                "UPDATE posts SET deleted_by = $2, hidden_by_reports = false WHERE id = $1 AND hidden_by_reports",
+                id,
+                moderator_id
+            ),
+            "topic" => sqlx::query!(
+                "UPDATE topics SET deleted_by = $2, hidden_by_reports = false WHERE id = $1 AND hidden_by_reports",
+                id,
+                moderator_id
+            ),
+            _ => sqlx::query!(
+                "UPDATE replies SET deleted_by = $2, hidden_by_reports = false WHERE id = $1 AND hidden_by_reports",
+                id,
+                moderator_id
+            ),
+        };
+        let result = query.execute(&self.pool).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Restores content hidden automatically by reports. Content a
+    /// moderator moved to the trash stays there.
+    pub async fn unhide_reported_content(&self, content_type: &str, content_id: Uuid) -> Result<bool> {
+        let hidden = sqlx::query!(
+            r#"
+            SELECT 'post' as "content_type!", id as "id!" FROM posts
+            WHERE $1 = 'post' AND id = $2 AND hidden_by_reports
+            UNION ALL
+            SELECT 'topic', id FROM topics
+            WHERE $1 = 'topic' AND id = $2 AND hidden_by_reports
+            UNION ALL
+            SELECT 'reply', r.id FROM replies r
+            WHERE $1 = 'reply' AND r.id = $2 AND r.hidden_by_reports
+            UNION ALL
+            SELECT 'topic', t.id FROM replies r
+            JOIN topics t ON t.id = r.topic_id
+            WHERE $1 = 'reply' AND r.id = $2 AND r.deleted_at IS NULL AND t.hidden_by_reports
+            "#,
+            content_type,
+            content_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match hidden {
+            Some(row) if row.content_type == "post" => self.restore_post(row.id).await,
+            Some(row) if row.content_type == "topic" => self.restore_topic(row.id).await,
+            Some(row) => self.restore_reply(row.id).await,
+            None => Ok(false),
+        }
+    }
+
+    // Warning and suspension operations
+
+    pub async fn warn_user(
+        &self,
+        user_id: Uuid,
+        moderator_id: Uuid,
+        report_id: Option<Uuid>,
+        message: &str,
+    ) -> Result<UserWarning> {
+        let warning = sqlx::query_as!(
+            UserWarning,
+            r#"
+            INSERT INTO user_warnings (id, user_id, moderator_id, report_id, message)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, moderator_id, report_id, message, created_at
+            "#,
+            Uuid::new_v4(),
+            user_id,
+            moderator_id,
+            report_id,
+            message
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(warning)
+    }
+
+    /// A member's warnings, newest first.
+    pub async fn list_warnings(&self, user_id: Uuid) -> Result<Vec<UserWarning>> {
+        let warnings = sqlx::query_as!(
+            UserWarning,
+            "SELECT id, user_id, moderator_id, report_id, message, created_at
+             FROM user_warnings WHERE user_id = $1
+             ORDER BY created_at DESC",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(warnings)
+    }
+
+    pub async fn suspend_user(&self, user_id: Uuid, until: DateTime<Utc>, reason: &str, suspended_by: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET suspended_until = $2, suspension_reason = $3, suspended_by = $4 WHERE id = $1",
+            user_id,
+            until,
+            reason,
+            suspended_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn lift_suspension(&self, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET suspended_until = NULL, suspension_reason = NULL, suspended_by = NULL
+             WHERE id = $1 AND suspended_until IS NOT NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn silence_user(&self, user_id: Uuid, until: DateTime<Utc>, reason: &str, silenced_by: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET silenced_until = $2, silence_reason = $3, silenced_by = $4 WHERE id = $1",
+            user_id,
+            until,
+            reason,
+            silenced_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn lift_silence(&self, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE users SET silenced_until = NULL, silence_reason = NULL, silenced_by = NULL
+             WHERE id = $1 AND silenced_until IS NOT NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Ban operations
+    // Expired bans are kept for the record but no longer match
+
+    /// Creates a ban, or returns `None` if the same IP or domain is already
+    /// banned.
+    pub async fn create_ban(
+        &self,
+        ban_type: &str,
+        value: &str,
+        reason: &str,
+        banned_by: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Ban>> {
+        let ban = sqlx::query_as!(
+            Ban,
+            r#"
+            INSERT INTO bans (id, ban_type, value, reason, banned_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (ban_type, value) DO NOTHING
+            RETURNING id, ban_type, value, reason, banned_by, expires_at, created_at
+            "#,
+            Uuid::new_v4(),
+            ban_type,
+            value,
+            reason,
+            banned_by,
+            expires_at
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(ban)
+    }
+
+    /// Every ban, newest first.
+    pub async fn list_bans(&self) -> Result<Vec<Ban>> {
+        let bans = sqlx::query_as!(
+            Ban,
+            "SELECT id, ban_type, value, reason, banned_by, expires_at, created_at
+             FROM bans ORDER BY created_at DESC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(bans)
+    }
+
+    /// Lifts a ban, returning it.
+    pub async fn delete_ban(&self, id: Uuid) -> Result<Option<Ban>> {
+        let ban = sqlx::query_as!(
+            Ban,
+            "DELETE FROM bans WHERE id = $1
+             RETURNING id, ban_type, value, reason, banned_by, expires_at, created_at",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(ban)
+    }
+
+    /// The active ban covering an IP address, if any.
+    pub async fn find_ip_ban(&self, ip: &str) -> Result<Option<Ban>> {
+        let ban = sqlx::query_as!(
+            Ban,
+            r#"
+            SELECT id, ban_type, value, reason, banned_by, expires_at, created_at
+            FROM bans
+            WHERE ban_type = 'ip' AND $1::text::inet <<= value::inet
+              AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY expires_at DESC NULLS FIRST
+            LIMIT 1
+            "#,
+            ip
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(ban)
+    }
+
+    /// The active ban covering an email domain or one of its parents.
+    pub async fn find_email_domain_ban(&self, domain: &str) -> Result<Option<Ban>> {
+        let ban = sqlx::query_as!(
+            Ban,
+            r#"
+            SELECT id, ban_type, value, reason, banned_by, expires_at, created_at
+            FROM bans
+            WHERE ban_type = 'email_domain'
+              AND (lower($1) = value OR lower($1) LIKE '%.' || value)
+              AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY expires_at DESC NULLS FIRST
+            LIMIT 1
+            "#,
+            domain
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(ban)
+    }
+
+    // Audit operations
+    // The table refuses updates and deletes, so entries are only ever added
+
+    pub async fn insert_audit_entry(&self, entry: NewAuditEntry) -> Result<()> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (id, actor_id, actor_username, action, target_type, target_id, before, after, ip_address)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            Uuid::new_v4(),
+            entry.actor_id,
+            entry.actor_username,
+            entry.action,
+            entry.target_type,
+            entry.target_id,
+            entry.before,
+            entry.after,
+            entry.ip_address
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Audit entries matching `filter`, newest first. `actor` is a username
+    /// or user ID.
+    pub async fn list_audit_log(&self, filter: &AuditQuery, limit: i64, offset: i64) -> Result<Vec<AuditEntry>> {
+        let entries = sqlx::query_as!(
+            AuditEntry,
+            r#"
+            SELECT id, actor_id, actor_username, action, target_type, target_id, before, after,
+                   ip_address, created_at
+            FROM audit_log
+            WHERE ($1::text IS NULL OR actor_username = $1 OR actor_id::text = $1)
+              AND ($2::text IS NULL OR action = $2 OR action LIKE $2 || '.%')
+              AND ($3::text IS NULL OR target_type = $3)
+              AND ($4::text IS NULL OR target_id = $4)
+              AND ($5::timestamptz IS NULL OR created_at >= $5)
+              AND ($6::timestamptz IS NULL OR created_at < $6)
+            ORDER BY created_at DESC
+            LIMIT $7 OFFSET $8
+            "#,
+            filter.actor,
+            filter.action,
+            filter.target_type,
+            filter.target_id,
+            filter.from,
+            filter.to,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(entries)
+    }
+
+    pub async fn set_user_role(&self, user_id: Uuid, role: UserRole) -> Result<Option<User>> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            UPDATE users SET role = $2 WHERE id = $1
+            RETURNING id, username, email, password_hash, role as "role: UserRole", created_at,
+                      suspended_until, suspension_reason, suspended_by,
+                      silenced_until, silence_reason, silenced_by
+            "#,
+            user_id,
+            role as UserRole
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    // Solution operations
+
+    /// Accepts `reply_id` as the solution of its topic, replacing any earlier
+    /// one, or clears the solution when it is `None`. The reputation for the
+    /// solution moves with it; topic authors answering themselves get none.
+    pub async fn set_solution(&self, topic_id: Uuid, reply_id: Option<Uuid>) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::update_solution(&mut tx, topic_id, reply_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // `set_solution` within a transaction that changes the topic's replies
+    async fn update_solution(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        topic_id: Uuid,
+        reply_id: Option<Uuid>,
+    ) -> Result<()> {
+        // Serialize concurrent changes to the same topic
+        let topic_author = sqlx::query_scalar!("SELECT user_id FROM topics WHERE id = $1 FOR UPDATE", topic_id)
+            .fetch_one(&mut **tx)
+            .await?;
+
+        let previous = sqlx::query_scalar!(
+            "UPDATE replies SET is_solution = false WHERE topic_id = $1 AND is_solution RETURNING id",
+            topic_id
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        if let Some(previous) = previous {
+            sqlx::query!(
+                "DELETE FROM reputation_events WHERE event_type = 'solution_accepted' AND source_id = $1",
+                previous
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        if let Some(reply_id) = reply_id {
+            let author = sqlx::query_scalar!(
+                "UPDATE replies SET is_solution = true WHERE id = $1 AND topic_id = $2 RETURNING user_id",
+                reply_id,
+                topic_id
+            )
+            .fetch_one(&mut **tx)
+            .await?;
+
+            if author != topic_author {
+                sqlx::query!(
+                    "INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, created_at)
+                     VALUES ($1, $2, 'solution_accepted', $3, 'reply', $4, NOW())",
+                    Uuid::new_v4(),
+                    author,
+                    SOLUTION_REPUTATION,
+                    reply_id
+                )
+                .execute(&mut **tx)
+                .await?;
+            }
+        }
+
+        sqlx::query!(
+            "UPDATE topics SET is_solved = $2 WHERE id = $1",
+            topic_id,
+            reply_id.is_some()
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The accepted solution of a topic, if it has one.
+    pub async fn get_solution(&self, topic_id: Uuid, viewer: Option<Uuid>) -> Result<Option<ReplyWithDetails>> {
+        let row = sqlx::query!(
+            r#"
+            SELECT r.id, r.topic_id, r.user_id, r.content, r.is_solution, r.likes_count,
+                   r.reply_to_id, r.created_at, r.updated_at, r.edited_at, r.edited_by,
+                   u.username, u.email, u.role as "role: UserRole",
+                   EXISTS(SELECT 1 FROM likes l WHERE l.reply_id = r.id AND l.user_id = $2) as "liked_by_me!"
+            FROM replies r
+            JOIN users u ON r.user_id = u.id
+            WHERE r.topic_id = $1 AND r.is_solution AND r.deleted_at IS NULL
+            "#,
+            topic_id,
+            viewer as Option<Uuid>
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| ReplyWithDetails {
+            reply: Reply {
+                id: row.id,
+                topic_id: row.topic_id,
+                user_id: row.user_id,
+                content: row.content,
+                is_solution: row.is_solution,
+                likes_count: row.likes_count,
+                reply_to_id: row.reply_to_id,
+                edited_at: row.edited_at,
+                edited_by: row.edited_by,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            user: UserInfo {
+                id: row.user_id,
+                username: row.username,
+                email: row.email,
+                role: row.role,
+            },
+            reply_to_user: None,
+            liked_by_me: row.liked_by_me,
+        }))
+    }
+
+    // Like operations
+    // Liking twice or unliking something not liked is a no-op, so clients
+    // can retry; the counters are kept by triggers
+
+    /// Also returns whether the like is new.
+    pub async fn set_reply_like(&self, reply_id: Uuid, user_id: Uuid, liked: bool) -> Result<(LikeStatus, bool)> {
+        let mut added = false;
+        if liked {
+            added = sqlx::query!(
+                "INSERT INTO likes (id, user_id, reply_id, created_at)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (user_id, reply_id) DO NOTHING",
+                Uuid::new_v4(),
+                user_id,
+                reply_id,
+                Utc::now()
+            )
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+                > 0;
+        } else {
+            sqlx::query!(
+                "DELETE FROM likes WHERE user_id = $1 AND reply_id = $2",
+                user_id,
+                reply_id
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        let count = sqlx::query_scalar!("SELECT likes_count FROM replies WHERE id = $1", reply_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok((LikeStatus { liked, count }, added))
+    }
+
+    /// Also returns whether the like is new.
+    pub async fn set_topic_like(&self, topic_id: Uuid, user_id: Uuid, liked: bool) -> Result<(LikeStatus, bool)> {
+        let mut added = false;
+        if liked {
+            added = sqlx::query!(
+                "INSERT INTO topic_likes (id, user_id, topic_id, created_at)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (user_id, topic_id) DO NOTHING",
+                Uuid::new_v4(),
+                user_id,
+                topic_id,
+                Utc::now()
+            )
+            .execute(&self.pool)
+            .await?
+            .rows_affected()
+                > 0;
+        } else {
+            sqlx::query!(
+                "DELETE FROM topic_likes WHERE user_id = $1 AND topic_id = $2",
+                user_id,
+                topic_id
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        let count = sqlx::query_scalar!("SELECT likes_count FROM topics WHERE id = $1", topic_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok((LikeStatus { liked, count }, added))
+    }
+
+    /// Users who liked a reply, most recent first.
+    pub async fn list_reply_likers(&self, reply_id: Uuid, limit: i64, offset: i64) -> Result<Vec<Liker>> {
+        let likers = sqlx::query_as!(
+            Liker,
+            r#"
+            SELECT l.user_id, u.username, p.display_name as "display_name?", p.avatar_url,
+                   l.created_at as liked_at
+            FROM likes l
+            JOIN users u ON l.user_id = u.id
+            LEFT JOIN user_profiles p ON p.user_id = l.user_id
+            WHERE l.reply_id = $1
+            ORDER BY l.created_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+            reply_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(likers)
+    }
+
+    // User profile operations
+    pub async fn get_user_profile(&self, user_id: Uuid) -> Result<Option<UserProfile>> {
+        let profile = sqlx::query_as!(
+            UserProfile,
+            "SELECT user_id, display_name, bio, avatar_url, location, website, reputation,
+                    topics_count, replies_count, likes_given, likes_received, last_seen_at,
+                    trust_level, trust_level_override, days_visited, created_at, updated_at
+             FROM user_profiles WHERE user_id = $1",
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(profile)
+    }
+
+    /// Applies the fields that are set; an empty string clears a field.
+    pub async fn update_user_profile(&self, user_id: Uuid, update: UpdateUserProfile) -> Result<Option<UserProfile>> {
+        let profile = sqlx::query_as!(
+            UserProfile,
+            "UPDATE user_profiles
+             SET display_name = COALESCE($2, display_name),
+                 bio = CASE WHEN $3::text IS NULL THEN bio ELSE NULLIF($3, '') END,
+                 location = CASE WHEN $4::text IS NULL THEN location ELSE NULLIF($4, '') END,
+                 website = CASE WHEN $5::text IS NULL THEN website ELSE NULLIF($5, '') END,
+                 updated_at = NOW()
+             WHERE user_id = $1
+             RETURNING user_id, display_name, bio, avatar_url, location, website, reputation,
+                       topics_count, replies_count, likes_given, likes_received, last_seen_at,
+                       trust_level, trust_level_override, days_visited, created_at, updated_at",
+            user_id,
+            update.display_name,
+            update.bio,
+            update.location,
+            update.website
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(profile)
+    }
+
+    /// Sets or, with `None`, clears the avatar.
+    pub async fn set_avatar_url(&self, user_id: Uuid, avatar_url: Option<&str>) -> Result<()> {
+        sqlx::query!(
+            "UPDATE user_profiles SET avatar_url = $2, updated_at = NOW() WHERE user_id = $1",
+            user_id,
+            avatar_url
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The avatars of those of the users who have one.
+    pub async fn list_avatar_urls(&self, user_ids: &[Uuid]) -> Result<Vec<(Uuid, String)>> {
+        let rows = sqlx::query!(
+            r#"SELECT user_id, avatar_url as "avatar_url!" FROM user_profiles
+               WHERE user_id = ANY($1) AND avatar_url IS NOT NULL"#,
+            user_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.user_id, row.avatar_url)).collect())
+    }
+
+    /// The user's latest topics, newest first.
+    pub async fn list_recent_topics_by_user(&self, user_id: Uuid, limit: i64) -> Result<Vec<UserTopic>> {
+        let topics = sqlx::query_as!(
+            UserTopic,
+            r#"
+            SELECT t.id, t.title, t.slug, c.name as category_name, t.replies_count, t.likes_count,
+                   t.is_solved, t.created_at
+            FROM topics t
+            JOIN categories c ON t.category_id = c.id
+            WHERE t.user_id = $1 AND t.deleted_at IS NULL
+            ORDER BY t.created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(topics)
+    }
+
+    /// The user's latest replies, newest first. Opening posts are left out,
+    /// as they are listed as topics.
+    pub async fn list_recent_replies_by_user(&self, user_id: Uuid, limit: i64) -> Result<Vec<UserReply>> {
+        let replies = sqlx::query_as!(
+            UserReply,
+            r#"
+            SELECT r.id, t.title as topic_title, t.slug as topic_slug,
+                   LEFT(r.content, 200) as "excerpt!", r.is_solution, r.likes_count, r.created_at
+            FROM replies r
+            JOIN topics t ON r.topic_id = t.id
+            WHERE r.user_id = $1 AND r.deleted_at IS NULL AND t.deleted_at IS NULL
+              AND r.id <> (SELECT first.id FROM replies first
+                           WHERE first.topic_id = r.topic_id
+                           ORDER BY first.created_at ASC LIMIT 1)
+            ORDER BY r.created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(replies)
+    }
+
+    // Trust level operations
+    // The level in force is the admin override if there is one, otherwise
+    // the level earned
+
+    pub async fn get_trust_level(&self, user_id: Uuid) -> Result<i16> {
+        let level = sqlx::query_scalar!(
+            r#"SELECT COALESCE(trust_level_override, trust_level) as "level!"
+               FROM user_profiles WHERE user_id = $1"#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(level.unwrap_or(0))
+    }
+
+    /// Marks the user as seen, counting a visit the first time they are
+    /// seen on a given day.
+    pub async fn record_visit(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query!(
+            "UPDATE user_profiles
+             SET days_visited = days_visited
+                     + CASE WHEN last_visited_on IS NULL OR last_visited_on < CURRENT_DATE THEN 1 ELSE 0 END,
+                 last_visited_on = CURRENT_DATE, last_seen_at = NOW()
+             WHERE user_id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Raises every member to the highest level whose requirements they
+    /// meet. Earned levels are never lowered; an admin override can do
+    /// that. Returns how many members went up.
+    pub async fn recompute_trust_levels(&self, requirements: &[TrustRequirement]) -> Result<u64> {
+        let levels: Vec<i16> = requirements.iter().map(|r| r.level).collect();
+        let days_since_joined: Vec<i32> = requirements.iter().map(|r| r.days_since_joined).collect();
+        let days_visited: Vec<i32> = requirements.iter().map(|r| r.days_visited).collect();
+        let replies: Vec<i64> = requirements.iter().map(|r| r.replies).collect();
+        let likes_received: Vec<i64> = requirements.iter().map(|r| r.likes_received).collect();
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_profiles p
+            SET trust_level = earned.level, updated_at = NOW()
+            FROM (
+                SELECT p.user_id, MAX(r.level) as level
+                FROM user_profiles p
+                JOIN UNNEST($1::smallint[], $2::int[], $3::int[], $4::bigint[], $5::bigint[])
+                     AS r(level, days_since_joined, days_visited, replies, likes_received)
+                  ON p.created_at <= NOW() - make_interval(days => r.days_since_joined)
+                 AND p.days_visited >= r.days_visited
+                 AND p.replies_count >= r.replies
+                 AND p.likes_received >= r.likes_received
+                GROUP BY p.user_id
+            ) earned
+            WHERE p.user_id = earned.user_id AND earned.level > p.trust_level
+            "#,
+            &levels,
+            &days_since_joined,
+            &days_visited,
+            &replies,
+            &likes_received
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Sets or, with `None`, clears the admin override.
+    pub async fn set_trust_level_override(&self, user_id: Uuid, level: Option<i16>) -> Result<Option<UserProfile>> {
+        let profile = sqlx::query_as!(
+            UserProfile,
+            "UPDATE user_profiles SET trust_level_override = $2, updated_at = NOW()
+             WHERE user_id = $1
+             RETURNING user_id, display_name, bio, avatar_url, location, website, reputation,
+                       topics_count, replies_count, likes_given, likes_received, last_seen_at,
+                       trust_level, trust_level_override, days_visited, created_at, updated_at",
+            user_id,
+            level
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(profile)
+    }
+
+    // Reputation operations
+    // Reputation is the sum of the ledger, kept in user_profiles.reputation
+    // by a trigger; like events are added and removed by the like triggers
+
+    pub async fn list_reputation_events(&self, user_id: Uuid, limit: i64, offset: i64) -> Result<Vec<ReputationEvent>> {
+        let events = sqlx::query_as!(
+            ReputationEvent,
+            "SELECT id, user_id, event_type, points, source_type, source_id, actor_id, reason, created_at
+             FROM reputation_events WHERE user_id = $1
+             ORDER BY created_at DESC
+             LIMIT $2 OFFSET $3",
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(events)
+    }
+
+    /// Features a topic, earning its author reputation, or returns `None` if
+    /// it is already featured.
+    pub async fn feature_topic(&self, topic_id: Uuid, moderator_id: Uuid) -> Result<Option<ReputationEvent>> {
+        let event = sqlx::query_as!(
+            ReputationEvent,
+            "INSERT INTO reputation_events (id, user_id, event_type, points, source_type, source_id, actor_id, created_at)
+             SELECT $1, t.user_id, 'topic_featured', $2, 'topic', t.id, $3, NOW()
+             FROM topics t WHERE t.id = $4
+             ON CONFLICT DO NOTHING
+             RETURNING id, user_id, event_type, points, source_type, source_id, actor_id, reason, created_at",
+            Uuid::new_v4(),
+            FEATURED_TOPIC_REPUTATION,
+            moderator_id,
+            topic_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    /// Unfeatures a topic, taking back the reputation, or returns `None` if
+    /// it isn't featured.
+    pub async fn unfeature_topic(&self, topic_id: Uuid) -> Result<Option<ReputationEvent>> {
+        let event = sqlx::query_as!(
+            ReputationEvent,
+            "DELETE FROM reputation_events WHERE event_type = 'topic_featured' AND source_id = $1
+             RETURNING id, user_id, event_type, points, source_type, source_id, actor_id, reason, created_at",
+            topic_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    pub async fn add_penalty(&self, user_id: Uuid, points: i32, reason: &str, moderator_id: Uuid) -> Result<ReputationEvent> {
+        let event = sqlx::query_as!(
+            ReputationEvent,
+            "INSERT INTO reputation_events (id, user_id, event_type, points, actor_id, reason, created_at)
+             VALUES ($1, $2, 'penalty', $3, $4, $5, NOW())
+             RETURNING id, user_id, event_type, points, source_type, source_id, actor_id, reason, created_at",
+            Uuid::new_v4(),
+            user_id,
+            -points,
+            moderator_id,
+            reason
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(event)
+    }
+
+    // Badge operations
+
+    /// Awards `badge` to everyone whose `metric` (see `BadgeRule::metric`)
+    /// has reached `threshold` and doesn't have it yet. Returns how many
+    /// were awarded.
+    pub async fn award_badge(&self, badge: &str, metric: &str, threshold: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO user_badges (user_id, badge, awarded_at)
+            SELECT m.user_id, $1, NOW()
+            FROM (
+                SELECT p.user_id,
+                       CASE $2::text
+                           WHEN 'solutions_accepted' THEN (
+                               SELECT COUNT(*) FROM reputation_events e
+                               WHERE e.user_id = p.user_id AND e.event_type = 'solution_accepted'
+                           )
+                           WHEN 'likes_received' THEN p.likes_received
+                           WHEN 'replies' THEN p.replies_count
+                           WHEN 'topics' THEN p.topics_count
+                           WHEN 'reputation' THEN p.reputation
+                           WHEN 'days_visited' THEN p.days_visited
+                           WHEN 'trust_level' THEN COALESCE(p.trust_level_override, p.trust_level)
+                       END as value
+                FROM user_profiles p
+            ) m
+            WHERE m.value >= $3
+            ON CONFLICT (user_id, badge) DO NOTHING
+            "#,
+            badge,
+            metric,
+            threshold
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn list_user_badges(&self, user_id: Uuid) -> Result<Vec<UserBadge>> {
+        let badges = sqlx::query_as!(
+            UserBadge,
+            "SELECT user_id, badge, awarded_at FROM user_badges WHERE user_id = $1 ORDER BY awarded_at",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(badges)
+    }
+
+    /// The badges of several members at once, for user cards.
+    pub async fn list_badges_for_users(&self, user_ids: &[Uuid]) -> Result<Vec<UserBadge>> {
+        let badges = sqlx::query_as!(
+            UserBadge,
+            "SELECT user_id, badge, awarded_at FROM user_badges WHERE user_id = ANY($1) ORDER BY awarded_at",
+            user_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(badges)
+    }
+
+    /// How many members hold each badge.
+    pub async fn count_badge_holders(&self) -> Result<Vec<(String, i64)>> {
+        let rows = sqlx::query!(
+            r#"SELECT badge, COUNT(*) as "holders!" FROM user_badges GROUP BY badge"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.badge, row.holders)).collect())
+    }
+
+    /// Holders of a badge, most recent first.
+    pub async fn list_badge_holders(&self, badge: &str, limit: i64, offset: i64) -> Result<Vec<BadgeHolder>> {
+        let holders = sqlx::query_as!(
+            BadgeHolder,
+            "SELECT b.user_id, u.username, b.awarded_at
+             FROM user_badges b
+             JOIN users u ON u.id = b.user_id
+             WHERE b.badge = $1
+             ORDER BY b.awarded_at DESC
+             LIMIT $2 OFFSET $3",
+            badge,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(holders)
+    }
+
+    // Statistics operations
+    // These scan whole tables, so they are run in the background by
+    // `stats::spawn_refresher` rather than per request
+
+    /// Forum totals, active members and replies per day for the last `days`
+    /// days.
+    pub async fn forum_stats(&self, days: i32) -> Result<ForumStats> {
+        let totals = sqlx::query!(
+            r#"
+            SELECT (SELECT COUNT(*) FROM topics WHERE deleted_at IS NULL) as "total_topics!",
+                   (SELECT COUNT(*) FROM replies WHERE deleted_at IS NULL) as "total_replies!",
+                   (SELECT COUNT(*) FROM users) as "total_users!",
+                   (SELECT COUNT(*) FROM user_profiles WHERE last_visited_on >= CURRENT_DATE) as "active_users_today!",
+                   (SELECT COUNT(*) FROM user_profiles WHERE last_visited_on >= CURRENT_DATE - 6) as "active_users_week!"
+            "#
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let posts_per_day = sqlx::query_as!(
+            DailyCount,
+            r#"
+            SELECT d.day::date as "day!", COUNT(r.id) as "count!"
+            FROM generate_series((CURRENT_DATE - ($1::int - 1))::timestamptz, CURRENT_DATE::timestamptz, INTERVAL '1 day') d(day)
+            LEFT JOIN replies r
+              ON r.created_at >= d.day AND r.created_at < d.day + INTERVAL '1 day' AND r.deleted_at IS NULL
+            GROUP BY d.day
+            ORDER BY d.day
+            "#,
+            days
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ForumStats {
+            total_topics: totals.total_topics,
+            total_replies: totals.total_replies,
+            total_users: totals.total_users,
+            active_users_today: totals.active_users_today,
+            active_users_week: totals.active_users_week,
+            posts_per_day,
+            computed_at: Utc::now(),
+        })
+    }
+
+    /// The top `limit` members by the reputation events since `since`: the
+    /// sum of their points, or with `event_type` the number of those events.
+    pub async fn leaderboard(
+        &self,
+        event_type: Option<&str>,
+        since: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<LeaderboardEntry>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT e.user_id, u.username, u.created_at as join_date,
+                   CASE WHEN $1::text IS NULL THEN SUM(e.points) ELSE COUNT(*) END as "score!",
+                   p.topics_count, p.replies_count, p.likes_received, p.reputation, p.last_seen_at
+            FROM reputation_events e
+            JOIN users u ON u.id = e.user_id
+            JOIN user_profiles p ON p.user_id = e.user_id
+            WHERE ($1::text IS NULL OR e.event_type = $1)
+              AND ($2::timestamptz IS NULL OR e.created_at >= $2)
+            GROUP BY e.user_id, u.username, u.created_at,
+                     p.topics_count, p.replies_count, p.likes_received, p.reputation, p.last_seen_at
+            HAVING CASE WHEN $1::text IS NULL THEN SUM(e.points) ELSE COUNT(*) END > 0
+            ORDER BY 4 DESC, u.username
+            LIMIT $3
+            "#,
+            event_type,
+            since,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| LeaderboardEntry {
+                rank: i as i64 + 1,
+                user_id: row.user_id,
+                username: row.username,
+                score: row.score,
+                stats: UserStats {
+                    topics_created: row.topics_count,
+                    replies_posted: row.replies_count,
+                    likes_received: row.likes_received,
+                    reputation: row.reputation,
+                    join_date: row.join_date,
+                    last_seen: row.last_seen_at,
+                },
+            })
+            .collect())
+    }
+
+    // Attachment operations
+    // Attachments on a deleted post or reply are hidden with it
+
+    /// Records an upload, and its blob and the blob's variants if the
+    /// content is new.
+    pub async fn create_attachment(
+        &self,
+        blob: &NewBlob,
+        filename: &str,
+        kind: AttachmentKind,
+        user_id: Uuid,
+        target: AttachTo,
+    ) -> Result<Attachment> {
+        let archive = blob.archive.as_ref().map(serde_json::to_value).transpose()?;
+        let mut tx = self.pool.begin().await?;
+
+        // Blobs recorded before hashes and descriptions were get them now
+        sqlx::query!(
+            "INSERT INTO blobs (sha256, md5, size, mime_type, description, width, height, archive)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+             ON CONFLICT (sha256) DO UPDATE SET
+                 last_uploaded_at = NOW(),
+                 md5 = COALESCE(blobs.md5, EXCLUDED.md5),
+                 description = COALESCE(blobs.description, EXCLUDED.description),
+                 archive = COALESCE(blobs.archive, EXCLUDED.archive)",
+            blob.sha256,
+            blob.md5,
+            blob.size,
+            blob.mime_type,
+            blob.description,
+            blob.width,
+            blob.height,
+            archive
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let variants: Vec<String> = blob.variants.iter().map(|v| v.variant.clone()).collect();
+        let mime_types: Vec<String> = blob.variants.iter().map(|v| v.mime_type.clone()).collect();
+        let sizes: Vec<i64> = blob.variants.iter().map(|v| v.size).collect();
+        let widths: Vec<i32> = blob.variants.iter().map(|v| v.width).collect();
+        let heights: Vec<i32> = blob.variants.iter().map(|v| v.height).collect();
+        sqlx::query!(
+            "INSERT INTO blob_variants (sha256, variant, mime_type, size, width, height)
+             SELECT $1, * FROM UNNEST($2::text[], $3::text[], $4::bigint[], $5::int[], $6::int[])
+             ON CONFLICT DO NOTHING",
+            blob.sha256,
+            &variants,
+            &mime_types,
+            &sizes,
+            &widths,
+            &heights
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            WITH inserted AS (
+                INSERT INTO attachments (id, sha256, filename, kind, user_id, post_id, reply_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                RETURNING *
+            )
+            SELECT a.id, a.sha256, a.filename, a.kind, b.mime_type, b.size, b.md5, b.description, a.user_id, a.post_id, a.reply_id,
+                   b.width, b.height, '/attachments/' || a.id as "url!", a.created_at
+            FROM inserted a JOIN blobs b ON b.sha256 = a.sha256
+            "#,
+            Uuid::new_v4(),
+            blob.sha256,
+            filename,
+            kind.as_str(),
+            user_id,
+            target.post_id,
+            target.reply_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(attachment)
+    }
+
+    pub async fn get_attachment(&self, id: Uuid) -> Result<Option<Attachment>> {
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            SELECT a.id, a.sha256, a.filename, a.kind, b.mime_type, b.size, b.md5, b.description, a.user_id, a.post_id, a.reply_id,
+                   b.width, b.height, '/attachments/' || a.id as "url!", a.created_at
+            FROM attachments a
+            JOIN blobs b ON b.sha256 = a.sha256
+            LEFT JOIN posts p ON p.id = a.post_id
+            LEFT JOIN replies r ON r.id = a.reply_id
+            WHERE a.id = $1 AND p.deleted_at IS NULL AND r.deleted_at IS NULL
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(attachment)
+    }
+
+    /// The attachments of a post or a reply, in upload order.
+    pub async fn list_attachments(&self, target: AttachTo) -> Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as!(
+            Attachment,
+            r#"
+            SELECT a.id, a.sha256, a.filename, a.kind, b.mime_type, b.size, b.md5, b.description, a.user_id, a.post_id, a.reply_id,
+                   b.width, b.height, '/attachments/' || a.id as "url!", a.created_at
+            FROM attachments a
+            JOIN blobs b ON b.sha256 = a.sha256
+            LEFT JOIN posts p ON p.id = a.post_id
+            LEFT JOIN replies r ON r.id = a.reply_id
+            WHERE (a.post_id = $1 OR a.reply_id = $2)
+              AND p.deleted_at IS NULL AND r.deleted_at IS NULL
+            ORDER BY a.created_at
+            "#,
+            target.post_id,
+            target.reply_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(attachments)
+    }
+
+    /// The challenge files of a post, in upload order.
+    pub async fn list_challenge_files(&self, post_id: Uuid) -> Result<Vec<ChallengeFile>> {
+        let files = sqlx::query_as!(
+            ChallengeFile,
+            r#"
+            SELECT a.id, a.filename, a.sha256, b.md5, b.size, b.description,
+                   b.archive as "archive: Json<ArchiveListing>", '/attachments/' || a.id as "url!"
+            FROM attachments a
+            JOIN blobs b ON b.sha256 = a.sha256
+            WHERE a.post_id = $1 AND a.kind = 'challenge'
+            ORDER BY a.created_at
+            "#,
+            post_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(files)
+    }
+
+    /// Attachments by id, leaving out any that are missing or hidden.
+    pub async fn get_attachments(&self, ids: &[Uuid]) -> Result<Vec<Attachment>> {
+        let attachments = sqlx::query_as!(
+            Attachment,
+            r#"
+            SELECT a.id, a.sha256, a.filename, a.kind, b.mime_type, b.size, b.md5, b.description, a.user_id, a.post_id, a.reply_id,
+                   b.width, b.height, '/attachments/' || a.id as "url!", a.created_at
+            FROM attachments a
+            JOIN blobs b ON b.sha256 = a.sha256
+            LEFT JOIN posts p ON p.id = a.post_id
+            LEFT JOIN replies r ON r.id = a.reply_id
+            WHERE a.id = ANY($1) AND p.deleted_at IS NULL AND r.deleted_at IS NULL
+            "#,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(attachments)
+    }
+
+    /// The image variants of the attachments, smallest first.
+    pub async fn list_attachment_variants(&self, ids: &[Uuid]) -> Result<Vec<AttachmentVariant>> {
+        let variants = sqlx::query_as!(
+            AttachmentVariant,
+            r#"
+            SELECT a.id as attachment_id, v.variant, v.mime_type, v.size, v.width, v.height,
+                   '/attachments/' || a.id || '/' || v.variant as "url!"
+            FROM attachments a
+            JOIN blob_variants v ON v.sha256 = a.sha256
+            WHERE a.id = ANY($1)
+            ORDER BY v.width
+            "#,
+            ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(variants)
+    }
+
+    /// Links an unlinked attachment to a post or reply. `None` if it is
+    /// missing or already linked.
+    pub async fn attach(&self, id: Uuid, target: AttachTo) -> Result<Option<Attachment>> {
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            UPDATE attachments a SET post_id = $2, reply_id = $3
+            FROM blobs b
+            WHERE a.id = $1 AND b.sha256 = a.sha256 AND a.post_id IS NULL AND a.reply_id IS NULL
+            RETURNING a.id, a.sha256, a.filename, a.kind, b.mime_type as "mime_type!", b.size as "size!",
+                      b.md5, b.description, a.user_id, a.post_id, a.reply_id, b.width, b.height,
+                      '/attachments/' || a.id as "url!", a.created_at
+            "#,
+            id,
+            target.post_id,
+            target.reply_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(attachment)
+    }
+
+    /// Removes an attachment, returning it. Its blob stays until collected.
+    pub async fn delete_attachment(&self, id: Uuid) -> Result<Option<Attachment>> {
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            DELETE FROM attachments a
+            USING blobs b
+            WHERE a.id = $1 AND b.sha256 = a.sha256
+            RETURNING a.id, a.sha256, a.filename, a.kind, b.mime_type as "mime_type!", b.size as "size!",
+                      b.md5, b.description, a.user_id, a.post_id, a.reply_id, b.width, b.height,
+                      '/attachments/' || a.id as "url!", a.created_at
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(attachment)
+    }
+
+    /// Removes uploads never linked to a post or reply within `hours`.
+    pub async fn delete_unlinked_attachments(&self, hours: i32) -> Result<u64> {
+        let result = sqlx::query!(
+            "DELETE FROM attachments
+             WHERE post_id IS NULL AND reply_id IS NULL AND created_at < NOW() - make_interval(hours => $1)",
+            hours
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Removes the records of blobs no attachment uses and that haven't been
+    /// uploaded for `hours`, returning their hashes so the content can be
+    /// removed from the blob store.
+    pub async fn delete_orphaned_blobs(&self, hours: i32) -> Result<Vec<String>> {
+        let hashes = sqlx::query_scalar!(
+            "DELETE FROM blobs b
+             WHERE b.last_uploaded_at < NOW() - make_interval(hours => $1)
+               AND NOT EXISTS (SELECT 1 FROM attachments a WHERE a.sha256 = b.sha256)
+             RETURNING b.sha256",
+            hours
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes)
+    }
+
+    // Notification operations
+
+    /// The members with these usernames, matched without regard to case.
+    pub async fn find_users_by_usernames(&self, usernames: &[String]) -> Result<Vec<(Uuid, String)>> {
+        let rows = sqlx::query!(
+            "SELECT id, username FROM users WHERE LOWER(username) = ANY($1)",
+            usernames
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.username)).collect())
+    }
+
+    /// How many members the user has mentioned in the last hour.
+    pub async fn count_recent_mentions_by(&self, user_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM notifications
+               WHERE actor_id = $1 AND notification_type = 'mention'
+                 AND created_at > NOW() - INTERVAL '1 hour'"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Notifies the members who haven't turned this kind of notification
+    /// off.
+    pub async fn create_notifications(
+        &self,
+        kind: NotificationType,
+        actor_id: Uuid,
+        topic_id: Option<Uuid>,
+        reply_id: Option<Uuid>,
+        message: Option<&str>,
+        user_ids: &[Uuid],
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO notifications (id, user_id, notification_type, actor_id, topic_id, reply_id, message)
+             SELECT gen_random_uuid(), u.user_id, $1::varchar, $2::uuid, $3::uuid, $4::uuid, $5::text
+             FROM UNNEST($6::uuid[]) AS u(user_id)
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM notification_preferences p
+                 WHERE p.user_id = u.user_id AND p.notification_type = $1::varchar AND NOT p.enabled
+             )",
+            kind.as_str(),
+            actor_id,
+            topic_id,
+            reply_id,
+            message,
+            user_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Notifies a member of a like on their topic, or on a reply when
+    /// `reply_id` is set. Likes on the same thing while the notification is
+    /// unread are counted into it and bring it back to the top.
+    pub async fn create_like_notification(
+        &self,
+        user_id: Uuid,
+        actor_id: Uuid,
+        topic_id: Uuid,
+        reply_id: Option<Uuid>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO notifications (id, user_id, notification_type, actor_id, topic_id, reply_id)
+             SELECT $1, $2, 'like', $3, $4, $5
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM notification_preferences
+                 WHERE user_id = $2 AND notification_type = 'like' AND NOT enabled
+             )
+             ON CONFLICT (user_id, (COALESCE(reply_id, topic_id))) WHERE notification_type = 'like' AND read_at IS NULL
+             DO UPDATE SET count = notifications.count + 1, actor_id = EXCLUDED.actor_id, created_at = NOW()",
+            Uuid::new_v4(),
+            user_id,
+            actor_id,
+            topic_id,
+            reply_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A member's latest notifications, newest first. Those about deleted
+    /// topics and replies are left out.
+    pub async fn list_notifications(&self, user_id: Uuid, limit: i64) -> Result<Vec<Notification>> {
+        // A reply's topic is looked up through the reply, which may have
+        // been moved to another topic since
+        let notifications = sqlx::query_as!(
+            Notification,
+            r#"
+            SELECT n.id, n.notification_type, n.actor_id, u.username as "actor_username?",
+                   t.id as "topic_id?", t.title as "topic_title?", n.reply_id,
+                   '/forum/t/' || t.slug || COALESCE('#reply-' || n.reply_id, '') as "url?",
+                   n.count, n.message, n.read_at, n.created_at
+            FROM notifications n
+            LEFT JOIN users u ON u.id = n.actor_id
+            LEFT JOIN replies r ON r.id = n.reply_id
+            LEFT JOIN topics t ON t.id = COALESCE(r.topic_id, n.topic_id)
+            WHERE n.user_id = $1 AND t.deleted_at IS NULL AND r.deleted_at IS NULL
+            ORDER BY n.created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    /// How many of the notifications `list_notifications` shows are unread.
+    pub async fn count_unread_notifications(&self, user_id: Uuid) -> Result<i64> {
+        let count = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM notifications n
+               LEFT JOIN replies r ON r.id = n.reply_id
+               LEFT JOIN topics t ON t.id = COALESCE(r.topic_id, n.topic_id)
+               WHERE n.user_id = $1 AND n.read_at IS NULL AND t.deleted_at IS NULL AND r.deleted_at IS NULL"#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Returns false if the member has no such notification. Marking one
+    /// read again leaves it as it was.
+    pub async fn mark_notification_read(&self, id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            "UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE id = $1 AND user_id = $2",
+            id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn mark_all_notifications_read(&self, user_id: Uuid) -> Result<()> {
+        sqlx::query!(
+            "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The kinds of notification the member turned off.
+    pub async fn disabled_notification_types(&self, user_id: Uuid) -> Result<Vec<String>> {
+        let kinds = sqlx::query_scalar!(
+            "SELECT notification_type FROM notification_preferences WHERE user_id = $1 AND NOT enabled",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(kinds)
+    }
+
+    pub async fn set_notification_preferences(&self, user_id: Uuid, preferences: &[(NotificationType, bool)]) -> Result<()> {
+        let kinds: Vec<String> = preferences.iter().map(|(kind, _)| kind.as_str().to_string()).collect();
+        let enabled: Vec<bool> = preferences.iter().map(|(_, enabled)| *enabled).collect();
+
+        sqlx::query!(
+            "INSERT INTO notification_preferences (user_id, notification_type, enabled)
+             SELECT $1, p.notification_type, p.enabled FROM UNNEST($2::varchar[], $3::bool[]) AS p(notification_type, enabled)
+             ON CONFLICT (user_id, notification_type) DO UPDATE SET enabled = EXCLUDED.enabled",
+            user_id,
+            &kinds,
+            &enabled
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Discussion operations
+    pub async fn get_category_by_name(&self, name: &str) -> Result<Option<Category>> {
+        let category = sqlx::query_as!(
+            Category,
+            "SELECT id, name, description, color, icon, sort_order, topics_count, posts_count, last_post_at, min_trust_level, created_at
+             FROM categories WHERE name = $1",
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(category)
+    }
+
+    /// The forum topic discussing a post or wiki page, with its `latest`
+    /// replies newest first.
+    pub async fn get_discussion(&self, content_type: &str, content_id: Uuid, latest: i64) -> Result<Option<Discussion>> {
+        let topic = sqlx::query_as!(
+            Topic,
+            "SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
+                    t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
+                    t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason
+             FROM discussion_links d
+             JOIN topics t ON d.topic_id = t.id
+             WHERE d.content_type = $1 AND d.content_id = $2 AND t.deleted_at IS NULL",
+            content_type,
+            content_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(topic) = topic else {
+            return Ok(None);
+        };
+
+        let latest_replies = self.list_latest_replies(topic.id, latest).await?;
+        Ok(Some(Discussion {
+            url: format!("/forum/t/{}", topic.slug),
+            // The opening post is stored as the first reply
+            replies_count: (topic.replies_count - 1).max(0),
+            latest_replies,
+            topic,
+        }))
+    }
+
+    /// Creates the discussion topic for a page unless it already has one.
+    /// Returns the topic and whether it was just created.
+    pub async fn find_or_create_discussion_topic(
+        &self,
+        content_type: &str,
+        content_id: Uuid,
+        topic: CreateTopic,
+        user_id: Uuid,
+    ) -> Result<(Topic, bool)> {
+        let slug = self.generate_slug(&topic.title).await?;
+
+        let mut tx = self.pool.begin().await?;
+
+        // Serialize concurrent requests for the same page
+        sqlx::query!(
+            r#"SELECT 1 as "locked!" FROM (SELECT pg_advisory_xact_lock(hashtext($1 || ':' || ($2::uuid)::text))) l"#,
+            content_type,
+            content_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let existing = sqlx::query_as!(
+            Topic,
+            "SELECT t.id, t.category_id, t.title, t.slug, t.user_id, t.views, t.replies_count, t.likes_count,
+                    t.is_pinned, t.is_locked, t.is_solved, t.last_reply_at, t.last_reply_user_id,
+                    t.created_at, t.updated_at, t.edited_at, t.edited_by, t.is_pinned_globally, t.lock_reason
+             FROM discussion_links d
+             JOIN topics t ON d.topic_id = t.id
+             WHERE d.content_type = $1 AND d.content_id = $2 AND t.deleted_at IS NULL",
+            content_type,
+            content_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(existing) = existing {
+            return Ok((existing, false));
+        }
+
+        // A discussion whose topic is in the trash starts over
+        sqlx::query!(
+            "DELETE FROM discussion_links WHERE content_type = $1 AND content_id = $2",
+            content_type,
+            content_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let created = Self::insert_topic(&mut tx, &topic, &slug, user_id).await?;
+        sqlx::query!(
+            "INSERT INTO discussion_links (id, content_type, content_id, topic_id, created_at)
+             VALUES ($1, $2, $3, $4, $5)",
+            Uuid::new_v4(),
+            content_type,
+            content_id,
+            created.id,
+            created.created_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.content_changed(created.id).await;
+        Ok((created, true))
+    }
+
+    async fn list_latest_replies(&self, topic_id: Uuid, limit: i64) -> Result<Vec<ReplyWithDetails>> {
+        let replies = sqlx::query!(
+            r#"
+            SELECT r.id, r.topic_id, r.user_id, r.content, r.is_solution, r.likes_count,
+                   r.reply_to_id, r.created_at, r.updated_at, r.edited_at, r.edited_by,
+                   u.username, u.email, u.role as "role: UserRole"
+            FROM replies r
+            JOIN users u ON r.user_id = u.id
+            WHERE r.topic_id = $1 AND r.deleted_at IS NULL
+              AND r.id <> (SELECT id FROM replies WHERE topic_id = $1 ORDER BY created_at ASC LIMIT 1)
+            ORDER BY r.created_at DESC
+            LIMIT $2
+            "#,
+            topic_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(replies
+            .into_iter()
+            .map(|row| ReplyWithDetails {
+                reply: Reply {
+                    id: row.id,
+                    topic_id: row.topic_id,
+                    user_id: row.user_id,
+                    content: row.content,
+                    is_solution: row.is_solution,
+                    likes_count: row.likes_count,
+                    reply_to_id: row.reply_to_id,
+                    edited_at: row.edited_at,
+                    edited_by: row.edited_by,
+                    created_at: row.created_at,
+                    updated_at: row.updated_at,
+                },
+                user: UserInfo {
+                    id: row.user_id,
+                    username: row.username,
+                    email: row.email,
+                    role: row.role,
+                },
+                reply_to_user: None,
+                liked_by_me: false,
+            })
+            .collect())
+    }
+
+    // Wiki operations
+    pub async fn create_wiki_page(&self, page: UpsertWikiPage, edited_by: Option<Uuid>, edit_summary: Option<&str>) -> Result<WikiPage> {
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+
+        let page = sqlx::query_as!(
+            WikiPage,
+            r#"
+            INSERT INTO wiki_pages (id, parent_id, path, lang, translation_group, title, content, source_path,
+                                    nav_order, created_at, updated_at, title_words, content_words)
+            VALUES ($1, $2, $3, $4,
+                    COALESCE((SELECT translation_group FROM wiki_pages WHERE path = $3::varchar AND lang <> $4::varchar LIMIT 1), $1),
+                    $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+            "#,
+            id,
+            page.parent_id,
+            page.path,
+            page.lang,
+            page.title,
+            page.content,
+            page.source_path,
+            page.nav_order,
+            now,
+            now,
+            cjk_words(&page.title),
+            cjk_words(&page.content)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        Self::record_wiki_revision(&mut tx, &page, edited_by, edit_summary).await?;
+        tx.commit().await?;
+
+        self.content_changed(page.id).await;
+        Ok(page)
+    }
+
+    pub async fn get_wiki_page_by_path(&self, lang: &str, path: &str) -> Result<Option<WikiPage>> {
+        let page = sqlx::query_as!(
+            WikiPage,
+            "SELECT id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+             FROM wiki_pages WHERE lang = $1 AND path = $2",
+            lang,
+            path
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(page)
+    }
+
+    /// Every language version of the page at `path`.
+    pub async fn list_wiki_pages_at_path(&self, path: &str) -> Result<Vec<WikiPage>> {
+        let pages = sqlx::query_as!(
+            WikiPage,
+            "SELECT id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+             FROM wiki_pages WHERE path = $1
+             ORDER BY lang",
+            path
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pages)
+    }
+
+    pub async fn list_wiki_translations(&self, translation_group: Uuid) -> Result<Vec<WikiPage>> {
+        let pages = sqlx::query_as!(
+            WikiPage,
+            "SELECT id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+             FROM wiki_pages WHERE translation_group = $1
+             ORDER BY lang",
+            translation_group
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pages)
+    }
+
+    pub async fn list_wiki_children(&self, parent_id: Uuid) -> Result<Vec<WikiPage>> {
+        let pages = sqlx::query_as!(
+            WikiPage,
+            "SELECT id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+             FROM wiki_pages WHERE parent_id = $1
+             ORDER BY nav_order, title",
+            parent_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pages)
+    }
+
+    /// Looks up the pages at the given paths, returned in the order of `paths`.
+    pub async fn get_wiki_pages_by_paths(&self, lang: &str, paths: &[String]) -> Result<Vec<WikiPage>> {
+        let pages = sqlx::query_as!(
+            WikiPage,
+            "SELECT id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+             FROM wiki_pages WHERE lang = $1 AND path = ANY($2)
+             ORDER BY array_position($2, path::text)",
+            lang,
+            paths
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(pages)
+    }
+
+    /// Replaces an imported page's content and position in the tree, only if
+    /// it is still at `base_revision`. Returns `None` when someone saved in
+    /// the meantime.
+    pub async fn update_imported_wiki_page(
+        &self,
+        id: Uuid,
+        base_revision: i32,
+        page: UpsertWikiPage,
+    ) -> Result<Option<WikiPage>> {
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+
+        let page = sqlx::query_as!(
+            WikiPage,
+            r#"
+            UPDATE wiki_pages
+            SET parent_id = $1, title = $2, content = $3, source_path = $4, nav_order = $5,
+                revision = revision + 1, updated_at = $6, title_words = $8, content_words = $9
+            WHERE id = $7 AND revision = $10
+            RETURNING id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+            "#,
+            page.parent_id,
+            page.title,
+            page.content,
+            page.source_path,
+            page.nav_order,
+            now,
+            id,
+            cjk_words(&page.title),
+            cjk_words(&page.content),
+            base_revision
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(page) = page else {
+            return Ok(None);
+        };
+
+        Self::record_wiki_revision(&mut tx, &page, None, Some("Re-imported from mkdocs")).await?;
+        tx.commit().await?;
+
+        self.content_changed(page.id).await;
+        Ok(Some(page))
+    }
+
+    /// Whether a page has revisions saved in the app since the last time
+    /// mkdocs was imported into it. Imports are saved by no one.
+    pub async fn wiki_page_edited_since_import(&self, id: Uuid) -> Result<bool> {
+        let edited = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM wiki_page_revisions
+                WHERE page_id = $1 AND revision > COALESCE((
+                    SELECT MAX(revision) FROM wiki_page_revisions
+                    WHERE page_id = $1 AND edited_by IS NULL
+                      AND edit_summary IN ('Imported from mkdocs', 'Re-imported from mkdocs')
+                ), 0)
+            ) as "edited!"
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(edited)
+    }
+
+    /// Saves an edit only if the page is still at `base_revision`. Returns
+    /// `None` when someone else saved in the meantime.
+    pub async fn update_wiki_page(&self, id: Uuid, update: UpdateWikiPage, edited_by: Uuid) -> Result<Option<WikiPage>> {
+        let now = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+
+        let page = sqlx::query_as!(
+            WikiPage,
+            r#"
+            UPDATE wiki_pages
+            SET title = COALESCE($1, title), content = COALESCE($2, content),
+                title_words = COALESCE($6, title_words), content_words = COALESCE($7, content_words),
+                revision = revision + 1, updated_at = $3
+            WHERE id = $4 AND revision = $5
+            RETURNING id, parent_id, path, lang, translation_group, title, content, source_path, nav_order, revision, created_at, updated_at
+            "#,
+            update.title,
+            update.content,
+            now,
+            id,
+            update.base_revision,
+            update.title.as_deref().map(cjk_words),
+            update.content.as_deref().map(cjk_words)
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(page) = page else {
+            return Ok(None);
+        };
+
+        Self::record_wiki_revision(&mut tx, &page, Some(edited_by), update.edit_summary.as_deref()).await?;
+        tx.commit().await?;
+
+        self.content_changed(page.id).await;
+        Ok(Some(page))
+    }
+
+    pub async fn delete_wiki_page(&self, id: Uuid) -> Result<bool> {
+        let result = sqlx::query!("DELETE FROM wiki_pages WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        self.content_changed(id).await;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn list_wiki_revisions(&self, page_id: Uuid) -> Result<Vec<WikiRevisionSummary>> {
+        let revisions = sqlx::query_as!(
+            WikiRevisionSummary,
+            r#"
+            SELECT r.id, r.revision, r.title, r.edited_by, u.username as "editor_username?",
+                   r.edit_summary, r.created_at
+            FROM wiki_page_revisions r
+            LEFT JOIN users u ON r.edited_by = u.id
+            WHERE r.page_id = $1
+            ORDER BY r.revision DESC
+            "#,
+            page_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(revisions)
+    }
+
+    pub async fn get_wiki_revision(&self, page_id: Uuid, revision: i32) -> Result<Option<WikiRevision>> {
+        let revision = sqlx::query_as!(
+            WikiRevision,
+            "SELECT id, page_id, revision, title, content, edited_by, edit_summary, created_at
+             FROM wiki_page_revisions WHERE page_id = $1 AND revision = $2",
+            page_id,
+            revision
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(revision)
+    }
+
+    async fn record_wiki_revision(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        page: &WikiPage,
+        edited_by: Option<Uuid>,
+        edit_summary: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO wiki_page_revisions (id, page_id, revision, title, content, edited_by, edit_summary, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            Uuid::new_v4(),
+            page.id,
+            page.revision,
+            page.title,
+            page.content,
+            edited_by,
+            edit_summary,
+            page.updated_at
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    // Translation operations
+
+    /// One row per language version of every post and wiki page, sorted so
+    /// the versions of each translation group are adjacent.
+    pub async fn list_translation_rows(&self) -> Result<Vec<TranslationRow>> {
+        let rows = sqlx::query_as!(
+            TranslationRow,
+            r#"
+            SELECT 'post' as "content_type!", translation_group as "translation_group!", lang as "lang!",
+                   title as "title!", '/' || lang || '/posts/' || id::text as "url!", updated_at as "updated_at!"
+            FROM posts
+            WHERE deleted_at IS NULL
+            UNION ALL
+            SELECT 'wiki', translation_group, lang, title,
+                   '/' || lang || '/wiki' || CASE WHEN path = '' THEN '' ELSE '/' || path END, updated_at
+            FROM wiki_pages
+            ORDER BY 1, 2, 3
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    // Search operations
+
+    /// Ranked hits of the given result types for a web-search style query
+    /// (`"exact phrase"`, `-exclude`, `or`). `segmented_query` is the same
+    /// query with CJK runs split into words. A `category` filter keeps forum
+    /// results in that category and a `tag` filter keeps posts with that tag.
+    /// This is the search run when no index is configured.
+    /// Headlines are only computed for the requested page.
+    pub async fn search(
+        &self,
+        request: &IndexQuery,
+        segmented_query: &str,
+        headline_options: &str,
+    ) -> Result<Vec<SearchHit>> {
+        let hits = sqlx::query_as!(
+            SearchHit,
+            r#"
+            WITH search AS (
+                SELECT websearch_to_tsquery('english', $1) || websearch_to_tsquery('simple', $1)
+                       || websearch_to_tsquery('simple', $6) AS query
+            ),
+            hits AS (
+                SELECT 'post' AS result_type, p.id, p.title::text AS title,
+                       '/' || p.lang || '/posts/' || p.id::text AS url, p.content AS body,
+                       ts_rank(p.search_vector, s.query) AS rank, p.created_at
+                FROM posts p CROSS JOIN search s
+                WHERE 'post' = ANY($2) AND p.published AND p.deleted_at IS NULL AND p.search_vector @@ s.query
+                  AND $7::text IS NULL AND ($8::text IS NULL OR $8 = ANY(p.tags))
+                UNION ALL
+                SELECT 'topic', t.id, t.title, '/forum/t/' || t.slug, coalesce(op.content, ''),
+                       ts_rank(t.search_vector, s.query), t.created_at
+                FROM topics t
+                JOIN categories c ON c.id = t.category_id
+                CROSS JOIN search s
+                LEFT JOIN LATERAL (
+                    SELECT content FROM replies WHERE topic_id = t.id ORDER BY created_at LIMIT 1
+                ) op ON true
+                WHERE 'topic' = ANY($2) AND t.deleted_at IS NULL AND t.search_vector @@ s.query
+                  AND ($7::text IS NULL OR c.name = $7) AND $8::text IS NULL
+                UNION ALL
+                SELECT 'reply', r.id, t.title, '/forum/t/' || t.slug || '#reply-' || r.id::text, r.content,
+                       ts_rank(r.search_vector, s.query), r.created_at
+                FROM replies r
+                JOIN topics t ON t.id = r.topic_id
+                JOIN categories c ON c.id = t.category_id
+                CROSS JOIN search s
+                WHERE 'reply' = ANY($2) AND r.deleted_at IS NULL AND t.deleted_at IS NULL
+                  AND r.search_vector @@ s.query
+                  AND ($7::text IS NULL OR c.name = $7) AND $8::text IS NULL
+                UNION ALL
+                SELECT 'wiki', w.id, w.title,
+                       '/' || w.lang || '/wiki' || CASE WHEN w.path = '' THEN '' ELSE '/' || w.path END, w.content,
+                       ts_rank(w.search_vector, s.query), w.created_at
+                FROM wiki_pages w CROSS JOIN search s
+                WHERE 'wiki' = ANY($2) AND w.search_vector @@ s.query
+                  AND $7::text IS NULL AND $8::text IS NULL
+                UNION ALL
+                -- Usernames are identifiers rather than prose, so match them by substring
+                SELECT 'user', u.id, coalesce(up.display_name, u.username), '/u/' || u.username,
+                       coalesce(up.bio, ''),
+                       (CASE WHEN lower(u.username) = lower($1) THEN 1.0
+                             WHEN starts_with(lower(u.username), lower($1)) THEN 0.5
+                             ELSE 0.1 END)::real,
+                       u.created_at
+                FROM users u
+                LEFT JOIN user_profiles up ON up.user_id = u.id
+                WHERE 'user' = ANY($2) AND $7::text IS NULL AND $8::text IS NULL
+                  AND (position(lower($1) in lower(u.username)) > 0
+                       OR position(lower($1) in lower(coalesce(up.display_name, ''))) > 0)
+            ),
+            page AS (
+                SELECT *, COUNT(*) OVER () AS total
+                FROM hits
+                ORDER BY rank DESC, created_at DESC
+                LIMIT $3 OFFSET $4
+            )
+            SELECT p.result_type as "result_type!", p.id as "id!", p.title as "title!", p.url as "url!",
+                   ts_headline('english', p.body, s.query, $5) as "headline!",
+                   p.rank as "rank!", p.created_at as "created_at!", p.total as "total!"
+            FROM page p CROSS JOIN search s
+            ORDER BY p.rank DESC, p.created_at DESC
+            "#,
+            request.query,
+            &request.types,
+            request.limit as i64,
+            i64::try_from(request.offset).unwrap_or(i64::MAX),
+            headline_options,
+            segmented_query,
+            request.category,
+            request.tag
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hits)
+    }
+
+    /// Recomputes the segmented CJK words of every post, topic, reply and
+    /// wiki page, e.g. after upgrading or switching `SEARCH_SEGMENTER`.
+    /// Returns the number of rows that changed.
+    pub async fn reindex_search_words(&self) -> Result<u64> {
+        let mut changed = 0;
+
+        let posts = sqlx::query!("SELECT id, title, summary, content, title_words, summary_words, content_words FROM posts")
+            .fetch_all(&self.pool)
+            .await?;
+        for post in posts {
+            let words = (
+                cjk_words(&post.title),
+                cjk_words(post.summary.as_deref().unwrap_or_default()),
+                cjk_words(&post.content),
+            );
+            if words != (post.title_words, post.summary_words, post.content_words) {
+                sqlx::query!(
+                    "UPDATE posts SET title_words = $1, summary_words = $2, content_words = $3 WHERE id = $4",
+                    words.0,
+                    words.1,
+                    words.2,
+                    post.id
+                )
+                .execute(&self.pool)
+                .await?;
+                changed += 1;
+            }
+        }
+
+        let topics = sqlx::query!("SELECT id, title, title_words FROM topics")
+            .fetch_all(&self.pool)
+            .await?;
+        for topic in topics {
+            let words = cjk_words(&topic.title);
+            if words != topic.title_words {
+                sqlx::query!("UPDATE topics SET title_words = $1 WHERE id = $2", words, topic.id)
+                    .execute(&self.pool)
+                    .await?;
+                changed += 1;
+            }
+        }
+
+        let replies = sqlx::query!("SELECT id, content, content_words FROM replies")
+            .fetch_all(&self.pool)
+            .await?;
+        for reply in replies {
+            let words = cjk_words(&reply.content);
+            if words != reply.content_words {
+                sqlx::query!("UPDATE replies SET content_words = $1 WHERE id = $2", words, reply.id)
+                    .execute(&self.pool)
+                    .await?;
+                changed += 1;
+            }
+        }
+
+        let pages = sqlx::query!("SELECT id, title, content, title_words, content_words FROM wiki_pages")
+            .fetch_all(&self.pool)
+            .await?;
+        for page in pages {
+            let words = (cjk_words(&page.title), cjk_words(&page.content));
+            if words != (page.title_words, page.content_words) {
+                sqlx::query!(
+                    "UPDATE wiki_pages SET title_words = $1, content_words = $2 WHERE id = $3",
+                    words.0,
+                    words.1,
+                    page.id
+                )
+                .execute(&self.pool)
+                .await?;
+                changed += 1;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// The documents the embedded search index holds for the row `id` (a
+    /// topic also brings its replies), or for every row when `id` is `None`.
+    /// Unpublished posts have none.
+    pub async fn list_search_documents(&self, id: Option<Uuid>) -> Result<Vec<SearchDocument>> {
+        let documents = sqlx::query_as!(
+            SearchDocument,
+            r#"
+            SELECT 'post' as "doc_type!", p.id as "id!", NULL::uuid as topic_id, p.title::text as "title!",
+                   coalesce(p.summary || E'\n\n', '') || p.content as "body!",
+                   '/' || p.lang || '/posts/' || p.id::text as "url!", NULL::text as category,
+                   p.tags as "tags!", p.created_at as "created_at!"
+            FROM posts p
+            WHERE p.published AND p.deleted_at IS NULL AND ($1::uuid IS NULL OR p.id = $1)
+            UNION ALL
+            SELECT 'topic', t.id, t.id, t.title, coalesce(op.content, ''), '/forum/t/' || t.slug,
+                   c.name, ARRAY[]::text[], t.created_at
+            FROM topics t
+            JOIN categories c ON c.id = t.category_id
+            LEFT JOIN LATERAL (
+                SELECT content FROM replies WHERE topic_id = t.id ORDER BY created_at LIMIT 1
+            ) op ON true
+            WHERE t.deleted_at IS NULL AND ($1::uuid IS NULL OR t.id = $1)
+            UNION ALL
+            SELECT 'reply', r.id, r.topic_id, t.title, r.content, '/forum/t/' || t.slug || '#reply-' || r.id::text,
+                   c.name, ARRAY[]::text[], r.created_at
+            FROM replies r
+            JOIN topics t ON t.id = r.topic_id
+            JOIN categories c ON c.id = t.category_id
+            WHERE r.deleted_at IS NULL AND t.deleted_at IS NULL
+              AND ($1::uuid IS NULL OR r.id = $1 OR r.topic_id = $1)
+            UNION ALL
+            SELECT 'wiki', w.id, NULL, w.title, w.content,
+                   '/' || w.lang || '/wiki' || CASE WHEN w.path = '' THEN '' ELSE '/' || w.path END,
+                   NULL, ARRAY[]::text[], w.created_at
+            FROM wiki_pages w
+            WHERE $1::uuid IS NULL OR w.id = $1
+            UNION ALL
+            SELECT 'user', u.id, NULL, coalesce(up.display_name, u.username),
+                   u.username || coalesce(E'\n\n' || up.bio, ''), '/u/' || u.username,
+                   NULL, ARRAY[]::text[], u.created_at
+            FROM users u
+            LEFT JOIN user_profiles up ON up.user_id = u.id
+            WHERE $1::uuid IS NULL OR u.id = $1
+            "#,
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(documents)
+    }
+
+    /// Titles of topics and published posts, post tags and users for search
+    /// suggestions, weighted by popularity. Tags have an empty `url`.
+    pub async fn list_suggestions(&self) -> Result<Vec<Suggestion>> {
+        let suggestions = sqlx::query_as!(
+            Suggestion,
+            r#"
+            SELECT 'topic' as "kind!", t.title::text as "text!", '/forum/t/' || t.slug as "url!",
+                   t.replies_count * 10 + t.views as "weight!"
+            FROM topics t
+            WHERE t.deleted_at IS NULL
+            UNION ALL
+            SELECT 'post', p.title, '/' || p.lang || '/posts/' || p.id::text, 10
+            FROM posts p
+            WHERE p.published AND p.deleted_at IS NULL
+            UNION ALL
+            SELECT 'tag', tag, '', COUNT(*) * 10
+            FROM posts p, unnest(p.tags) AS tag
+            WHERE p.published AND p.deleted_at IS NULL
+            GROUP BY tag
+            UNION ALL
+            SELECT 'user', u.username, '/u/' || u.username, coalesce(up.reputation, 0)
+            FROM users u
+            LEFT JOIN user_profiles up ON up.user_id = u.id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(suggestions)
+    }
+
+    /// Lowercase ASCII words of three or more letters used in published
+    /// content, with how often each occurs, for spelling suggestions.
+    pub async fn list_vocabulary(&self) -> Result<Vec<VocabularyWord>> {
+        let words = sqlx::query_as!(
+            VocabularyWord,
+            r#"
+            SELECT word as "word!", COUNT(*) as "count!"
+            FROM (
+                SELECT regexp_split_to_table(lower(title || ' ' || coalesce(summary, '') || ' ' || content), '[^a-z0-9]+') AS word
+                FROM posts WHERE published AND deleted_at IS NULL
+                UNION ALL
+                SELECT regexp_split_to_table(lower(title), '[^a-z0-9]+') FROM topics WHERE deleted_at IS NULL
+                UNION ALL
+                SELECT regexp_split_to_table(lower(content), '[^a-z0-9]+') FROM replies WHERE deleted_at IS NULL
+                UNION ALL
+                SELECT regexp_split_to_table(lower(title || ' ' || content), '[^a-z0-9]+') FROM wiki_pages
+                UNION ALL
+                SELECT lower(unnest(tags)) FROM posts WHERE published AND deleted_at IS NULL
+                UNION ALL
+                SELECT lower(username) FROM users
+            ) words
+            WHERE length(word) >= 3
+            GROUP BY word
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(words)
+    }
+
+    /// Rebuilds the embedded search index, if one is configured, from the
+    /// database. Returns the number of documents indexed.
+    pub async fn rebuild_search_index(&self) -> Result<Option<usize>> {
+        let Some(index) = self.search_index() else {
+            return Ok(None);
+        };
+
+        let documents = self.list_search_documents(None).await?;
+        let count = documents.len();
+        tokio::task::spawn_blocking(move || index.rebuild(documents)).await??;
+        Ok(Some(count))
+    }
+
+    /// Called after the row `id` is written: wakes whoever waits on
+    /// [`changes`](Self::changes) and brings the embedded search index, if
+    /// any, in line. An index failure is logged rather than failing the
+    /// write; `reindex-search` rebuilds the index.
+    async fn content_changed(&self, id: Uuid) {
+        self.changes.notify_one();
+
+        let Some(index) = self.search_index() else {
+            return;
+        };
+
+        let result = match self.list_search_documents(Some(id)).await {
+            Ok(documents) => match tokio::task::spawn_blocking(move || index.replace(id, documents)).await {
+                Ok(result) => result,
+                Err(e) => Err(e.into()),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to update the search index for {}: {}", id, e);
+        }
     }
 
     // Helper functions
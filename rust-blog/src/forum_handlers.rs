@@ -1,18 +1,30 @@
 use crate::{
+    attachments,
+    audit::{self, AuditEvent, ClientIp},
+    auth::{is_moderator, optional_claims},
+    badges,
+    i18n::{self, DEFAULT_LANG},
+    markdown, mentions,
     models::{
-        CreateCategory, UpdateCategory, CreateTopic, CreateReply, UpdateReply,
-        Claims, TopicWithDetails, CategoryWithStats, ReplyWithDetails
+        CreateCategory, UpdateCategory, CreateTopic, UpdateTopic, CreateReply, UpdateReply,
+        PinTopic, PinScope, LockTopic, MoveTopic, MergeTopic, SplitTopic, SplitOutcome, MergeOutcome,
+        Claims, Topic, Reply, TopicWithDetails, CategoryWithStats, ReplyWithDetails, DiscussionTarget
     },
+    notifications, restrictions,
+    stats::{LeaderboardMetric, LeaderboardWindow, LEADERBOARD_SIZE},
     templates::{ForumIndexTemplate, CategoryTemplate, TopicTemplate, CreateTopicTemplate},
+    trust,
+    wiki::{self, wiki_url},
     AppState,
 };
 use axum::{
     extract::{Path, Query, State, ConnectInfo},
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
     Extension, Json,
 };
 use askama::Template;
+use chrono::{DateTime, Duration, Utc};
 use serde::Deserialize;
 use std::net::SocketAddr;
 use uuid::Uuid;
@@ -22,6 +34,7 @@ pub struct TopicQuery {
     pub page: Option<u64>,
     pub limit: Option<i64>,
     pub category: Option<Uuid>,
+    pub solved: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -30,11 +43,49 @@ pub struct ReplyQuery {
     pub limit: Option<i64>,
 }
 
+#[derive(Deserialize)]
+pub struct LeaderboardQuery {
+    pub by: Option<LeaderboardMetric>,
+    pub window: Option<LeaderboardWindow>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct LikersQuery {
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+/// Forum category that "Discuss this page" topics are created in.
+pub const DISCUSSION_CATEGORY: &str = "Page Discussions";
+/// How many replies the discussion widget previews.
+pub const DISCUSSION_PREVIEW_REPLIES: i64 = 3;
+/// How many members the forum index sidebar ranks.
+pub const SIDEBAR_LEADERBOARD_SIZE: usize = 5;
+/// How long authors can edit or delete their own topics and replies.
+/// Moderators can at any time.
+pub const EDIT_GRACE_MINUTES: i64 = 60;
+
+// A post or wiki page that can be discussed
+struct DiscussedPage {
+    content_type: &'static str,
+    id: Uuid,
+    title: String,
+    url: String,
+}
+
 // Forum index page
 pub async fn forum_index(State(state): State<AppState>) -> impl IntoResponse {
     match state.db.list_categories().await {
         Ok(categories) => {
-            let template = ForumIndexTemplate { categories };
+            let template = ForumIndexTemplate {
+                categories,
+                stats: state.stats.forum(),
+                top_members: state
+                    .stats
+                    .leaderboard(LeaderboardMetric::Reputation, LeaderboardWindow::Month, SIDEBAR_LEADERBOARD_SIZE)
+                    .unwrap_or_default(),
+            };
             Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
         }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
@@ -53,15 +104,23 @@ pub async fn category_page(
 
     match state.db.get_category(category_id).await {
         Ok(Some(category)) => {
-            match state.db.list_topics(Some(category_id), Some(limit), Some(offset as i64)).await {
+            match state.db.list_topics(Some(category_id), params.solved, Some(limit), Some(offset as i64)).await {
                 Ok(topics) => {
+                    let user_ids: Vec<Uuid> = topics.iter().map(|topic| topic.user.id).collect();
+                    let avatars = match state.db.list_avatar_urls(&user_ids).await {
+                        Ok(avatars) => avatars.into_iter().collect(),
+                        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+                    };
+                    let has_next = topics.len() == limit as usize;
                     let template = CategoryTemplate {
                         category,
                         topics,
+                        solved: params.solved,
                         current_page: page,
-                        has_next: topics.len() == limit as usize,
+                        has_next,
+                        avatars,
                     };
-                    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+                    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
                 }
                 Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
             }
@@ -77,10 +136,12 @@ pub async fn topic_page(
     Path(slug): Path<String>,
     Query(params): Query<ReplyQuery>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let page = params.page.unwrap_or(1);
     let limit = params.limit.unwrap_or(20);
     let offset = (page - 1) * limit as u64;
+    let viewer = optional_claims(&headers).and_then(|claims| claims.sub.parse::<Uuid>().ok());
 
     match state.db.get_topic_by_slug(&slug).await {
         Ok(Some(topic)) => {
@@ -91,15 +152,52 @@ pub async fn topic_page(
                 &addr.ip().to_string()
             ).await;
 
-            match state.db.list_replies(topic.topic.id, Some(limit), Some(offset as i64)).await {
+            let solution = match state.db.get_solution(topic.topic.id, viewer).await {
+                Ok(solution) => solution,
+                Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+            };
+
+            match state.db.list_replies(topic.topic.id, viewer, Some(limit), Some(offset as i64)).await {
                 Ok(replies) => {
+                    let contents = replies.iter().chain(solution.iter()).map(|reply| reply.reply.content.as_str());
+                    let mentioned = match mentions::members_mentioned(&state.db, contents).await {
+                        Ok(mentioned) => mentioned,
+                        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+                    };
+
+                    let user_ids: Vec<Uuid> = replies.iter().map(|reply| reply.user.id).collect();
+                    let user_badges = match state.db.list_badges_for_users(&user_ids).await {
+                        Ok(awarded) => badges::by_user(awarded),
+                        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+                    };
+                    let avatars = match state.db.list_avatar_urls(&user_ids).await {
+                        Ok(avatars) => avatars.into_iter().collect(),
+                        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+                    };
+                    // Without the variants, images are rendered as written. The
+                    // solution is shown on the first page wherever it is
+                    let mut image_ids: Vec<Uuid> = replies
+                        .iter()
+                        .chain(solution.iter())
+                        .flat_map(|reply| markdown::attachment_ids(&reply.reply.content))
+                        .collect();
+                    image_ids.sort();
+                    image_ids.dedup();
+                    let images = attachments::responsive_images(&state.db, &image_ids).await.unwrap_or_default();
+
+                    let has_next = replies.len() == limit as usize;
                     let template = TopicTemplate {
                         topic,
                         replies,
+                        solution,
                         current_page: page,
-                        has_next: replies.len() == limit as usize,
+                        has_next,
+                        user_badges,
+                        avatars,
+                        mentioned,
+                        images,
                     };
-                    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+                    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
                 }
                 Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
             }
@@ -139,15 +237,23 @@ pub async fn api_list_categories(State(state): State<AppState>) -> impl IntoResp
 pub async fn api_create_category(
     State(state): State<AppState>,
     Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
     Json(category): Json<CreateCategory>,
 ) -> impl IntoResponse {
     // Only admins can create categories
     if !matches!(claims.role, crate::models::UserRole::Admin) {
         return (StatusCode::FORBIDDEN, "Admin access required").into_response();
     }
+    if !(trust::NEW..=trust::LEADER).contains(&category.min_trust_level) {
+        return (StatusCode::BAD_REQUEST, "min_trust_level must be between 0 and 4").into_response();
+    }
 
     match state.db.create_category(category).await {
-        Ok(created_category) => (StatusCode::CREATED, Json(created_category)).into_response(),
+        Ok(created_category) => {
+            let event = AuditEvent::new("category.create", "category", created_category.id).after(&created_category);
+            audit::record(&state, &claims, &ip, event).await;
+            (StatusCode::CREATED, Json(created_category)).into_response()
+        }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create category").into_response(),
     }
 }
@@ -172,7 +278,7 @@ pub async fn api_list_topics(
     let limit = params.limit.unwrap_or(20);
     let offset = (page - 1) * limit as u64;
 
-    match state.db.list_topics(params.category, Some(limit), Some(offset as i64)).await {
+    match state.db.list_topics(params.category, params.solved, Some(limit), Some(offset as i64)).await {
         Ok(topics) => Json(topics).into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
@@ -187,9 +293,34 @@ pub async fn api_create_topic(
         Ok(id) => id,
         Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
     };
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    let category = match state.db.get_category(topic.category_id).await {
+        Ok(Some(category)) => category,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Category not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if let Err(response) = trust::check_category(&state, &claims, category.min_trust_level).await {
+        return response;
+    }
+    if let Err(response) = trust::check_content(&state, &claims, &topic.content).await {
+        return response;
+    }
+    let mentioned = match mentions::check(&state, &claims, user_id, &topic.content).await {
+        Ok(mentioned) => mentioned,
+        Err(response) => return response,
+    };
 
     match state.db.create_topic(topic, user_id).await {
-        Ok(created_topic) => (StatusCode::CREATED, Json(created_topic)).into_response(),
+        Ok(created_topic) => {
+            if !mentioned.is_empty() {
+                let opening_post = state.db.get_first_reply_id(created_topic.id).await.ok().flatten();
+                mentions::notify(&state, user_id, created_topic.id, opening_post, &mentioned).await;
+            }
+            (StatusCode::CREATED, Json(created_topic)).into_response()
+        }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create topic").into_response(),
     }
 }
@@ -220,12 +351,14 @@ pub async fn api_list_replies(
     State(state): State<AppState>,
     Path(topic_id): Path<Uuid>,
     Query(params): Query<ReplyQuery>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     let page = params.page.unwrap_or(1);
     let limit = params.limit.unwrap_or(20);
     let offset = (page - 1) * limit as u64;
+    let viewer = optional_claims(&headers).and_then(|claims| claims.sub.parse::<Uuid>().ok());
 
-    match state.db.list_replies(topic_id, Some(limit), Some(offset as i64)).await {
+    match state.db.list_replies(topic_id, viewer, Some(limit), Some(offset as i64)).await {
         Ok(replies) => Json(replies).into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
@@ -241,13 +374,792 @@ pub async fn api_create_reply(
         Ok(id) => id,
         Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
     };
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if topic.is_locked && !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "This topic is locked").into_response();
+    }
+    if let Err(response) = trust::check_content(&state, &claims, &reply.content).await {
+        return response;
+    }
+    let mentioned = match mentions::check(&state, &claims, user_id, &reply.content).await {
+        Ok(mentioned) => mentioned,
+        Err(response) => return response,
+    };
 
     match state.db.create_reply(topic_id, reply, user_id).await {
-        Ok(created_reply) => (StatusCode::CREATED, Json(created_reply)).into_response(),
+        Ok(created_reply) => {
+            mentions::notify(&state, user_id, topic_id, Some(created_reply.id), &mentioned).await;
+            notifications::replied(&state, &topic, &created_reply, &mentioned).await;
+            (StatusCode::CREATED, Json(created_reply)).into_response()
+        }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create reply").into_response(),
     }
 }
 
+pub async fn api_update_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    Json(mut update): Json<UpdateTopic>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    if (update.is_pinned.is_some() || update.is_locked.is_some()) && !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Only moderators can pin or lock topics").into_response();
+    }
+    if let Err(error) = check_can_modify(&claims, user_id, topic.user_id, topic.created_at, topic.is_locked) {
+        return error.into_response();
+    }
+
+    if let Some(title) = update.title.take() {
+        match normalize_title(&title) {
+            Some(title) => update.title = Some(title),
+            None => return (StatusCode::BAD_REQUEST, "Title must be 1 to 255 characters").into_response(),
+        }
+    }
+
+    let privileged = topic.user_id != user_id || update.is_pinned.is_some() || update.is_locked.is_some();
+    match state.db.update_topic(topic_id, update, user_id).await {
+        Ok(Some(updated)) => {
+            if privileged {
+                let event = AuditEvent::new("topic.update", "topic", topic_id).before(&topic).after(&updated);
+                audit::record(&state, &claims, &ip, event).await;
+            }
+            if updated.title != topic.title {
+                let message = format!("A moderator renamed your topic from \"{}\"", topic.title);
+                notifications::moderated(&state, &claims, topic.user_id, Some(topic_id), None, &message).await;
+            }
+            if updated.is_locked != topic.is_locked {
+                let message = lock_message(updated.is_locked, None);
+                notifications::moderated(&state, &claims, topic.user_id, Some(topic_id), None, &message).await;
+            }
+            Json(updated).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update topic").into_response(),
+    }
+}
+
+pub async fn api_delete_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    if let Err(error) = check_can_modify(&claims, user_id, topic.user_id, topic.created_at, topic.is_locked) {
+        return error.into_response();
+    }
+    if let Err(error) = check_can_delete_topic(&state, &claims, user_id, topic_id).await {
+        return error.into_response();
+    }
+
+    match state.db.delete_topic(topic_id, Some(user_id)).await {
+        Ok(true) => {
+            if topic.user_id != user_id {
+                audit::record(&state, &claims, &ip, AuditEvent::new("topic.delete", "topic", topic_id).before(&topic)).await;
+                let message = format!("A moderator deleted your topic \"{}\"", topic.title);
+                notifications::moderated(&state, &claims, topic.user_id, None, None, &message).await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete topic").into_response(),
+    }
+}
+
+pub async fn api_update_reply(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(reply_id): Path<Uuid>,
+    Json(update): Json<UpdateReply>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    if update.content.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "Content can't be empty").into_response();
+    }
+
+    let (reply, topic) = match get_reply_and_topic(&state, reply_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    if let Err(error) = check_can_modify(&claims, user_id, reply.user_id, reply.created_at, topic.is_locked) {
+        return error.into_response();
+    }
+    if let Err(response) = trust::check_content(&state, &claims, &update.content).await {
+        return response;
+    }
+
+    match state.db.update_reply(reply_id, update, user_id).await {
+        Ok(Some(updated)) => {
+            if reply.user_id != user_id {
+                let event = AuditEvent::new("reply.update", "reply", reply_id).before(&reply).after(&updated);
+                audit::record(&state, &claims, &ip, event).await;
+                let message = "A moderator edited your reply";
+                notifications::moderated(&state, &claims, reply.user_id, Some(topic.id), Some(reply_id), message).await;
+            }
+            Json(updated).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update reply").into_response(),
+    }
+}
+
+// Deleting the opening post deletes the whole topic, as a topic can't be
+// left without one
+pub async fn api_delete_reply(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let (reply, topic) = match get_reply_and_topic(&state, reply_id).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+
+    if let Err(error) = check_can_modify(&claims, user_id, reply.user_id, reply.created_at, topic.is_locked) {
+        return error.into_response();
+    }
+
+    let opening_post = match state.db.get_first_reply_id(topic.id).await {
+        Ok(first) => first == Some(reply.id),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if opening_post {
+        if let Err(error) = check_can_delete_topic(&state, &claims, user_id, topic.id).await {
+            return error.into_response();
+        }
+    }
+
+    let (deleted, event, link, message) = if opening_post {
+        (
+            state.db.delete_topic(topic.id, Some(user_id)).await,
+            AuditEvent::new("topic.delete", "topic", topic.id).before(&topic),
+            None,
+            format!("A moderator deleted your topic \"{}\"", topic.title),
+        )
+    } else {
+        (
+            state.db.delete_reply(reply.id, Some(user_id)).await,
+            AuditEvent::new("reply.delete", "reply", reply.id).before(&reply),
+            Some(topic.id),
+            "A moderator deleted your reply".to_string(),
+        )
+    };
+
+    match deleted {
+        Ok(true) => {
+            if reply.user_id != user_id {
+                audit::record(&state, &claims, &ip, event).await;
+                notifications::moderated(&state, &claims, reply.user_id, link, None, &message).await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete reply").into_response(),
+    }
+}
+
+// Restoring from the trash is for moderators
+
+pub async fn api_restore_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.restore_topic(topic_id).await {
+        Ok(true) => match state.db.get_topic(topic_id).await {
+            Ok(Some(topic)) => {
+                audit::record(&state, &claims, &ip, AuditEvent::new("topic.restore", "topic", topic_id).after(&topic)).await;
+                Json(topic).into_response()
+            }
+            Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Ok(false) => (StatusCode::NOT_FOUND, "Topic not found in the trash").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore topic").into_response(),
+    }
+}
+
+pub async fn api_restore_reply(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.restore_reply(reply_id).await {
+        Ok(true) => match state.db.get_reply(reply_id).await {
+            Ok(Some(reply)) => {
+                audit::record(&state, &claims, &ip, AuditEvent::new("reply.restore", "reply", reply_id).after(&reply)).await;
+                Json(reply).into_response()
+            }
+            Ok(None) => (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Ok(false) => (StatusCode::NOT_FOUND, "Reply not found in the trash, or its topic is deleted").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore reply").into_response(),
+    }
+}
+
+async fn get_reply_and_topic(state: &AppState, reply_id: Uuid) -> Result<(Reply, Topic), Response> {
+    let reply = match state.db.get_reply(reply_id).await {
+        Ok(Some(reply)) => reply,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, "Reply not found").into_response()),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    };
+
+    match state.db.get_topic(reply.topic_id).await {
+        Ok(Some(topic)) => Ok((reply, topic)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Topic not found").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    }
+}
+
+// Moderators can edit and delete anything; authors only their own posts,
+// within the grace window and while the topic is unlocked
+fn check_can_modify(
+    claims: &Claims,
+    user_id: Uuid,
+    author_id: Uuid,
+    created_at: DateTime<Utc>,
+    locked: bool,
+) -> Result<(), (StatusCode, &'static str)> {
+    if is_moderator(&claims.role) {
+        return Ok(());
+    }
+    if author_id != user_id {
+        return Err((StatusCode::FORBIDDEN, "You can only change your own posts"));
+    }
+    if locked {
+        return Err((StatusCode::FORBIDDEN, "This topic is locked"));
+    }
+    if Utc::now() - created_at > Duration::minutes(EDIT_GRACE_MINUTES) {
+        return Err((StatusCode::FORBIDDEN, "The time to change this post has passed"));
+    }
+    Ok(())
+}
+
+// Deleting a topic trashes every reply in it, so once other members have
+// replied only moderators can
+async fn check_can_delete_topic(
+    state: &AppState,
+    claims: &Claims,
+    user_id: Uuid,
+    topic_id: Uuid,
+) -> Result<(), (StatusCode, &'static str)> {
+    if is_moderator(&claims.role) {
+        return Ok(());
+    }
+    match state.db.has_replies_from_others(topic_id, user_id).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err((StatusCode::CONFLICT, "Others have replied to this topic, so it can't be deleted")),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error")),
+    }
+}
+
+// Moderation API
+// Pinning, locking, moving, merging and splitting are for moderators
+
+pub async fn api_pin_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    body: Option<Json<PinTopic>>,
+) -> impl IntoResponse {
+    let Json(pin) = body.unwrap_or_default();
+    set_topic_pinned(&state, &claims, &ip, topic_id, true, pin.scope == PinScope::Global).await
+}
+
+pub async fn api_unpin_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_topic_pinned(&state, &claims, &ip, topic_id, false, false).await
+}
+
+async fn set_topic_pinned(
+    state: &AppState,
+    claims: &Claims,
+    ip: &ClientIp,
+    topic_id: Uuid,
+    pinned: bool,
+    globally: bool,
+) -> Response {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let before = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.set_topic_pinned(topic_id, pinned, globally).await {
+        Ok(Some(topic)) => {
+            let action = if pinned { "topic.pin" } else { "topic.unpin" };
+            audit::record(state, claims, ip, AuditEvent::new(action, "topic", topic_id).before(&before).after(&topic)).await;
+            Json(topic).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update topic").into_response(),
+    }
+}
+
+pub async fn api_lock_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    body: Option<Json<LockTopic>>,
+) -> impl IntoResponse {
+    let Json(lock) = body.unwrap_or_default();
+    let reason = lock.reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > 500) {
+        return (StatusCode::BAD_REQUEST, "Reason must be at most 500 characters").into_response();
+    }
+    set_topic_locked(&state, &claims, &ip, topic_id, true, reason).await
+}
+
+pub async fn api_unlock_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_topic_locked(&state, &claims, &ip, topic_id, false, None).await
+}
+
+async fn set_topic_locked(
+    state: &AppState,
+    claims: &Claims,
+    ip: &ClientIp,
+    topic_id: Uuid,
+    locked: bool,
+    reason: Option<&str>,
+) -> Response {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let before = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.set_topic_locked(topic_id, locked, reason).await {
+        Ok(Some(topic)) => {
+            let action = if locked { "topic.lock" } else { "topic.unlock" };
+            audit::record(state, claims, ip, AuditEvent::new(action, "topic", topic_id).before(&before).after(&topic)).await;
+            if topic.is_locked != before.is_locked {
+                let message = lock_message(locked, reason);
+                notifications::moderated(state, claims, topic.user_id, Some(topic_id), None, &message).await;
+            }
+            Json(topic).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update topic").into_response(),
+    }
+}
+
+// What the author is told when a moderator locks or unlocks their topic
+fn lock_message(locked: bool, reason: Option<&str>) -> String {
+    match (locked, reason) {
+        (true, Some(reason)) => format!("A moderator locked your topic: {}", reason),
+        (true, None) => "A moderator locked your topic".to_string(),
+        (false, _) => "A moderator unlocked your topic".to_string(),
+    }
+}
+
+pub async fn api_move_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    Json(target): Json<MoveTopic>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let category = match state.db.get_category(target.category_id).await {
+        Ok(Some(category)) => category,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Category not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let before = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.move_topic(topic_id, target.category_id).await {
+        Ok(Some(topic)) => {
+            let event = AuditEvent::new("topic.move", "topic", topic_id).before(&before).after(&topic);
+            audit::record(&state, &claims, &ip, event).await;
+            if topic.category_id != before.category_id {
+                let message = format!("A moderator moved your topic to {}", category.name);
+                notifications::moderated(&state, &claims, topic.user_id, Some(topic_id), None, &message).await;
+            }
+            Json(topic).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to move topic").into_response(),
+    }
+}
+
+// Returns the topic the replies were merged into
+pub async fn api_merge_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    Json(merge): Json<MergeTopic>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    if merge.into == topic_id {
+        return (StatusCode::BAD_REQUEST, "A topic can't be merged into itself").into_response();
+    }
+
+    let mut topics = Vec::new();
+    for id in [topic_id, merge.into] {
+        match state.db.get_topic(id).await {
+            Ok(Some(topic)) => topics.push(topic),
+            Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        }
+    }
+
+    match state.db.merge_topics(topic_id, merge.into).await {
+        Ok(MergeOutcome::Merged) => {}
+        Ok(MergeOutcome::BothDiscussions) => {
+            return (StatusCode::CONFLICT, "Both topics are page discussions and can't be merged").into_response();
+        }
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to merge topics").into_response(),
+    }
+
+    match state.db.get_topic(merge.into).await {
+        Ok(Some(topic)) => {
+            let event = AuditEvent::new("topic.merge", "topic", topic_id).before(&topics).after(&topic);
+            audit::record(&state, &claims, &ip, event).await;
+            let message = format!("A moderator merged your topic \"{}\" into this one", topics[0].title);
+            notifications::moderated(&state, &claims, topics[0].user_id, Some(topic.id), None, &message).await;
+            Json(topic).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_split_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+    Json(split): Json<SplitTopic>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let Some(title) = normalize_title(&split.title) else {
+        return (StatusCode::BAD_REQUEST, "Title must be 1 to 255 characters").into_response();
+    };
+    if split.reply_ids.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Choose the replies to split off").into_response();
+    }
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let category_id = split.category_id.unwrap_or(topic.category_id);
+    match state.db.split_topic(topic_id, &split.reply_ids, &title, category_id).await {
+        Ok(SplitOutcome::Split(created)) => {
+            let after = serde_json::json!({ "topic": created, "reply_ids": split.reply_ids });
+            let event = AuditEvent::new("topic.split", "topic", topic_id).before(&topic).after(&after);
+            audit::record(&state, &claims, &ip, event).await;
+            (StatusCode::CREATED, Json(created)).into_response()
+        }
+        Ok(SplitOutcome::NothingToMove) => {
+            (StatusCode::BAD_REQUEST, "None of the replies can be split off; the opening post stays").into_response()
+        }
+        Ok(SplitOutcome::UnknownCategory) => (StatusCode::NOT_FOUND, "Category not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to split topic").into_response(),
+    }
+}
+
+fn normalize_title(title: &str) -> Option<String> {
+    let title = title.trim();
+    (!title.is_empty() && title.chars().count() <= 255).then(|| title.to_string())
+}
+
+// Likes API
+// POST likes and DELETE unlikes; repeating either leaves the like as it is
+
+pub async fn api_like_reply(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_reply_like(&state, &claims, reply_id, true).await
+}
+
+pub async fn api_unlike_reply(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_reply_like(&state, &claims, reply_id, false).await
+}
+
+async fn set_reply_like(state: &AppState, claims: &Claims, reply_id: Uuid, liked: bool) -> Response {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let reply = match state.db.get_reply(reply_id).await {
+        Ok(Some(reply)) => reply,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if reply.user_id == user_id {
+        return (StatusCode::FORBIDDEN, "You can't like your own reply").into_response();
+    }
+
+    match state.db.set_reply_like(reply_id, user_id, liked).await {
+        Ok((status, added)) => {
+            if added {
+                notifications::liked(state, reply.user_id, user_id, reply.topic_id, Some(reply_id)).await;
+            }
+            Json(status).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update like").into_response(),
+    }
+}
+
+pub async fn api_like_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_topic_like(&state, &claims, topic_id, true).await
+}
+
+pub async fn api_unlike_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_topic_like(&state, &claims, topic_id, false).await
+}
+
+async fn set_topic_like(state: &AppState, claims: &Claims, topic_id: Uuid, liked: bool) -> Response {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if topic.user_id == user_id {
+        return (StatusCode::FORBIDDEN, "You can't like your own topic").into_response();
+    }
+
+    match state.db.set_topic_like(topic_id, user_id, liked).await {
+        Ok((status, added)) => {
+            if added {
+                notifications::liked(state, topic.user_id, user_id, topic_id, None).await;
+            }
+            Json(status).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update like").into_response(),
+    }
+}
+
+// Solutions API
+// The topic author or a moderator accepts one reply as the answer
+
+pub async fn api_mark_solution(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_solution(&state, &claims, reply_id, true).await
+}
+
+pub async fn api_unmark_solution(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(reply_id): Path<Uuid>,
+) -> impl IntoResponse {
+    set_solution(&state, &claims, reply_id, false).await
+}
+
+async fn set_solution(state: &AppState, claims: &Claims, reply_id: Uuid, accepted: bool) -> Response {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let reply = match state.db.get_reply(reply_id).await {
+        Ok(Some(reply)) => reply,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let topic = match state.db.get_topic(reply.topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    if topic.user_id != user_id && !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Only the topic author or a moderator can choose the solution").into_response();
+    }
+
+    let solution = if accepted {
+        match state.db.get_first_reply_id(topic.id).await {
+            Ok(Some(first)) if first == reply.id => {
+                return (StatusCode::BAD_REQUEST, "The opening post can't be the solution").into_response();
+            }
+            Ok(_) => Some(reply.id),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        }
+    } else if reply.is_solution {
+        None
+    } else {
+        // Unmarking a reply that isn't the solution leaves the current one
+        return Json(topic).into_response();
+    };
+
+    if state.db.set_solution(topic.id, solution).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update solution").into_response();
+    }
+    if accepted && !reply.is_solution {
+        notifications::solution_accepted(state, &reply, user_id).await;
+    }
+
+    match state.db.get_topic(topic.id).await {
+        Ok(Some(topic)) => Json(topic).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_list_reply_likes(
+    State(state): State<AppState>,
+    Path(reply_id): Path<Uuid>,
+    Query(params): Query<LikersQuery>,
+) -> impl IntoResponse {
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.get_reply(reply_id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Reply not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
+    match state.db.list_reply_likers(reply_id, limit, offset).await {
+        Ok(likers) => Json(likers).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+// Statistics API
+// Served from `stats::StatsCache`, which is refreshed every few minutes
+
+pub async fn api_forum_stats(State(state): State<AppState>) -> impl IntoResponse {
+    match state.stats.forum() {
+        Some(stats) => Json(stats).into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "Statistics are still being computed").into_response(),
+    }
+}
+
+pub async fn api_leaderboard(
+    State(state): State<AppState>,
+    Query(params): Query<LeaderboardQuery>,
+) -> impl IntoResponse {
+    let metric = params.by.unwrap_or_default();
+    let window = params.window.unwrap_or_default();
+    let limit = params.limit.unwrap_or(20).clamp(1, LEADERBOARD_SIZE as usize);
+
+    match state.stats.leaderboard(metric, window, limit) {
+        Some(entries) => Json(entries).into_response(),
+        None => (StatusCode::SERVICE_UNAVAILABLE, "Statistics are still being computed").into_response(),
+    }
+}
+
 // User profile API
 pub async fn api_get_user_profile(
     State(state): State<AppState>,
@@ -258,4 +1170,105 @@ pub async fn api_get_user_profile(
         Ok(None) => (StatusCode::NOT_FOUND, "User profile not found").into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
-}
\ No newline at end of file
+}
+
+// Discussion API
+// Reachable cross-origin so the static mkdocs site can embed the widget
+
+pub async fn api_get_discussion(
+    State(state): State<AppState>,
+    Query(target): Query<DiscussionTarget>,
+) -> impl IntoResponse {
+    let page = match resolve_discussed_page(&state, &target).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    match state.db.get_discussion(page.content_type, page.id, DISCUSSION_PREVIEW_REPLIES).await {
+        Ok(Some(discussion)) => Json(discussion).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "No discussion for this page yet").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+// Finds the discussion topic for a page, creating it on first use
+pub async fn api_discuss_page(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(target): Json<DiscussionTarget>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let page = match resolve_discussed_page(&state, &target).await {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+
+    let category = match state.db.get_category_by_name(DISCUSSION_CATEGORY).await {
+        Ok(Some(category)) => category,
+        Ok(None) => return (StatusCode::INTERNAL_SERVER_ERROR, "Discussion category is missing").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let topic = CreateTopic {
+        category_id: category.id,
+        title: format!("Discussion: {}", page.title).chars().take(255).collect(),
+        content: format!("Questions and comments about [{}]({}).", page.title, page.url),
+    };
+
+    let created = match state.db.find_or_create_discussion_topic(page.content_type, page.id, topic, user_id).await {
+        Ok((_, created)) => created,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create discussion").into_response(),
+    };
+
+    match state.db.get_discussion(page.content_type, page.id, DISCUSSION_PREVIEW_REPLIES).await {
+        Ok(Some(discussion)) if created => (StatusCode::CREATED, Json(discussion)).into_response(),
+        Ok(Some(discussion)) => Json(discussion).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "No discussion for this page yet").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+async fn resolve_discussed_page(state: &AppState, target: &DiscussionTarget) -> Result<DiscussedPage, Response> {
+    match target.content_type.as_str() {
+        "post" => {
+            let Some(id) = target.id else {
+                return Err((StatusCode::BAD_REQUEST, "Missing post id").into_response());
+            };
+            match state.db.get_post(id).await {
+                Ok(Some(post)) if post.published => Ok(DiscussedPage {
+                    content_type: "post",
+                    id: post.id,
+                    url: i18n::post_url(&post),
+                    title: post.title,
+                }),
+                Ok(_) => Err((StatusCode::NOT_FOUND, "Post not found").into_response()),
+                Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+            }
+        }
+        "wiki" => {
+            let lang = match target.lang.as_deref() {
+                None => DEFAULT_LANG,
+                Some(lang) => i18n::normalize_lang(lang)
+                    .ok_or_else(|| (StatusCode::BAD_REQUEST, "Unsupported language").into_response())?,
+            };
+            let Some(path) = wiki::normalize_path(target.path.as_deref().unwrap_or("")) else {
+                return Err((StatusCode::BAD_REQUEST, "Invalid wiki path").into_response());
+            };
+            match state.db.get_wiki_page_by_path(lang, &path).await {
+                Ok(Some(page)) => Ok(DiscussedPage {
+                    content_type: "wiki",
+                    id: page.id,
+                    url: wiki_url(&page.lang, &page.path),
+                    title: page.title,
+                }),
+                Ok(None) => Err((StatusCode::NOT_FOUND, "Wiki page not found").into_response()),
+                Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+            }
+        }
+        _ => Err((StatusCode::BAD_REQUEST, "content_type must be post or wiki").into_response()),
+    }
+}
@@ -1,13 +1,22 @@
 use crate::{
-    auth::{create_jwt, verify_jwt},
-    models::{CreatePost, UpdatePost, LoginRequest, CreateUser, AuthResponse, UserInfo, Claims},
-    templates::{IndexTemplate, PostTemplate, AdminTemplate},
+    audit::{self, AuditEvent, ClientIp},
+    auth::{create_jwt, is_moderator, verify_jwt},
+    forum_handlers::DISCUSSION_PREVIEW_REPLIES,
+    i18n::{self, SUPPORTED_LANGS},
+    models::{
+        CreatePost, UpdatePost, LoginRequest, CreateUser, AuthResponse, UserInfo, Claims, Post, TranslationLink, UserRole,
+        AuditQuery, UpdateUserRole, SetTrustLevel,
+    },
+    templates::{IndexTemplate, PostTemplate, AdminTemplate, TranslationReportTemplate, TrashTemplate, AuditLogTemplate},
+    restrictions,
+    trash,
+    trust,
     AppState,
 };
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
     Extension, Json,
 };
 use askama::Template;
@@ -20,13 +29,62 @@ pub struct ListQuery {
     pub page: Option<u64>,
     pub limit: Option<i64>,
     pub published: Option<bool>,
+    pub lang: Option<String>,
 }
 
-pub async fn index(State(state): State<AppState>) -> impl IntoResponse {
-    match state.db.list_posts(true, Some(5), Some(0)).await {
+#[derive(Deserialize)]
+pub struct TranslationReportQuery {
+    pub lang: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TrashQuery {
+    #[serde(rename = "type")]
+    pub content_type: Option<String>,
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+// Home page in the language negotiated from Accept-Language
+pub async fn index(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let lang = i18n::preferred_lang(&headers);
+    ([(header::VARY, "Accept-Language")], render_index(&state, lang).await)
+}
+
+// Home page in an explicit language, e.g. /zh
+pub async fn localized_index(
+    State(state): State<AppState>,
+    Path(lang): Path<String>,
+) -> impl IntoResponse {
+    match i18n::normalize_lang(&lang).filter(|normalized| *normalized == lang) {
+        Some(lang) => render_index(&state, lang).await,
+        None => (StatusCode::NOT_FOUND, "Page not found").into_response(),
+    }
+}
+
+async fn render_index(state: &AppState, lang: &str) -> Response {
+    // Fall back to every language rather than showing an empty blog
+    let posts = match state.db.list_posts(true, Some(lang), Some(5), Some(0)).await {
+        Ok(posts) if posts.is_empty() => state.db.list_posts(true, None, Some(5), Some(0)).await,
+        result => result,
+    };
+
+    match posts {
         Ok(posts) => {
-            let template = IndexTemplate { posts };
-            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+            let translations = SUPPORTED_LANGS
+                .iter()
+                .map(|lang| TranslationLink {
+                    lang: lang.to_string(),
+                    title: lang.to_string(),
+                    url: format!("/{}", lang),
+                })
+                .collect();
+            let template = IndexTemplate { posts, lang: lang.to_string(), translations };
+            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
         }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
@@ -39,8 +97,9 @@ pub async fn list_posts(
     let page = params.page.unwrap_or(1);
     let limit = params.limit.unwrap_or(10);
     let offset = (page - 1) * limit as u64;
+    let lang = params.lang.as_deref().and_then(i18n::normalize_lang);
 
-    match state.db.list_posts(true, Some(limit), Some(offset as i64)).await {
+    match state.db.list_posts(true, lang, Some(limit), Some(offset as i64)).await {
         Ok(posts) => Html(format!(
             r#"
             <!DOCTYPE html>
@@ -63,8 +122,8 @@ pub async fn list_posts(
             posts.iter()
                 .map(|post| format!(
                     r#"
-                    <article class="post">
-                        <h2><a href="/posts/{}">{}</a></h2>
+                    <article class="post" lang="{}">
+                        <h2><a href="{}">{}</a></h2>
                         <p class="meta">By {} on {}</p>
                         {}
                         <div class="tags">
@@ -72,7 +131,8 @@ pub async fn list_posts(
                         </div>
                     </article>
                     "#,
-                    post.id,
+                    post.lang,
+                    i18n::post_url(post),
                     post.title,
                     post.author,
                     post.created_at.format("%Y-%m-%d %H:%M"),
@@ -89,18 +149,98 @@ pub async fn list_posts(
     }
 }
 
+// Unprefixed post URL; readers are sent to the translation in their
+// language when there is one
 pub async fn get_post(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Path(id): Path<Uuid>,
 ) -> impl IntoResponse {
-    match state.db.get_post(id).await {
-        Ok(Some(post)) => {
-            let template = PostTemplate { post };
-            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
-        }
-        Ok(None) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    let (post, translations) = match post_with_translations(&state, id).await {
+        Ok(Some(found)) => found,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let available: Vec<&str> = translations.iter().map(|t| t.lang.as_str()).collect();
+    let negotiated = i18n::negotiate(&headers, &available).filter(|lang| *lang != post.lang);
+    if let Some(translation) = negotiated.and_then(|lang| translations.iter().find(|t| t.lang == lang)) {
+        return (
+            [(header::VARY, "Accept-Language")],
+            Redirect::temporary(&i18n::post_url(translation)),
+        )
+            .into_response();
     }
+
+    ([(header::VARY, "Accept-Language")], render_post(&state, post, &translations).await).into_response()
+}
+
+// Post in an explicit language, e.g. /zh/posts/:id. Asking for another
+// language than the post's own redirects to its translation.
+pub async fn get_localized_post(
+    State(state): State<AppState>,
+    Path((lang, id)): Path<(String, Uuid)>,
+) -> impl IntoResponse {
+    let Some(lang) = i18n::normalize_lang(&lang).filter(|normalized| *normalized == lang) else {
+        return (StatusCode::NOT_FOUND, "Page not found").into_response();
+    };
+
+    let (post, translations) = match post_with_translations(&state, id).await {
+        Ok(Some(found)) => found,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    if post.lang == lang {
+        return render_post(&state, post, &translations).await;
+    }
+    match translations.iter().find(|t| t.lang == lang) {
+        Some(translation) => Redirect::permanent(&i18n::post_url(translation)).into_response(),
+        None => (StatusCode::NOT_FOUND, "This post has not been translated into that language").into_response(),
+    }
+}
+
+// A post plus its published translations (and itself)
+async fn post_with_translations(state: &AppState, id: Uuid) -> anyhow::Result<Option<(Post, Vec<Post>)>> {
+    let Some(post) = state.db.get_post(id).await? else {
+        return Ok(None);
+    };
+    let translations = state
+        .db
+        .list_post_translations(post.translation_group)
+        .await?
+        .into_iter()
+        .filter(|t| t.published || t.id == post.id)
+        .collect();
+    Ok(Some((post, translations)))
+}
+
+async fn render_post(state: &AppState, post: Post, translations: &[Post]) -> Response {
+    let loaded = tokio::try_join!(
+        state.db.get_discussion("post", post.id, DISCUSSION_PREVIEW_REPLIES),
+        state.db.list_challenge_files(post.id),
+    );
+    let (discussion, challenge_files) = match loaded {
+        Ok(loaded) => loaded,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let translations = translations
+        .iter()
+        .map(|t| TranslationLink {
+            lang: t.lang.clone(),
+            title: t.title.clone(),
+            url: i18n::post_url(t),
+        })
+        .collect();
+    let template = PostTemplate {
+        discussion_target: serde_json::json!({ "content_type": "post", "id": post.id }).to_string(),
+        post,
+        translations,
+        discussion,
+        challenge_files,
+    };
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
 }
 
 pub async fn admin_panel() -> impl IntoResponse {
@@ -108,6 +248,16 @@ pub async fn admin_panel() -> impl IntoResponse {
     Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
 }
 
+pub async fn translation_report_page() -> impl IntoResponse {
+    let template = TranslationReportTemplate {};
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+}
+
+pub async fn trash_page() -> impl IntoResponse {
+    let template = TrashTemplate {};
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+}
+
 // API endpoints
 pub async fn api_list_posts(
     State(state): State<AppState>,
@@ -117,8 +267,13 @@ pub async fn api_list_posts(
     let limit = params.limit.unwrap_or(10);
     let offset = (page - 1) * limit as u64;
     let published_only = params.published.unwrap_or(true);
+    let lang = match params.lang.as_deref().map(i18n::normalize_lang) {
+        Some(None) => return (StatusCode::BAD_REQUEST, "Unsupported language").into_response(),
+        Some(lang) => lang,
+        None => None,
+    };
 
-    match state.db.list_posts(published_only, Some(limit), Some(offset as i64)).await {
+    match state.db.list_posts(published_only, lang, Some(limit), Some(offset as i64)).await {
         Ok(posts) => Json(posts).into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
@@ -127,10 +282,37 @@ pub async fn api_list_posts(
 pub async fn create_post(
     State(state): State<AppState>,
     Extension(claims): Extension<Claims>,
-    Json(post): Json<CreatePost>,
+    Extension(ip): Extension<ClientIp>,
+    Json(mut post): Json<CreatePost>,
 ) -> impl IntoResponse {
+    let lang = match post.lang.as_deref().map(i18n::normalize_lang) {
+        Some(None) => return (StatusCode::BAD_REQUEST, "Unsupported language").into_response(),
+        Some(Some(lang)) => lang,
+        None => i18n::DEFAULT_LANG,
+    };
+    post.lang = Some(lang.to_string());
+
+    // A translation group holds at most one post per language
+    if let Some(source_id) = post.translation_of {
+        let source = match state.db.get_post(source_id).await {
+            Ok(Some(source)) => source,
+            Ok(None) => return (StatusCode::BAD_REQUEST, "Translated post does not exist").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        };
+        match state.db.list_post_translations(source.translation_group).await {
+            Ok(translations) if translations.iter().any(|t| t.lang == lang) => {
+                return (StatusCode::CONFLICT, "A translation in this language already exists").into_response();
+            }
+            Ok(_) => {}
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        }
+    }
+
     match state.db.create_post(post).await {
-        Ok(created_post) => (StatusCode::CREATED, Json(created_post)).into_response(),
+        Ok(created_post) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("post.create", "post", created_post.id).after(&created_post)).await;
+            (StatusCode::CREATED, Json(created_post)).into_response()
+        }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create post").into_response(),
     }
 }
@@ -138,11 +320,21 @@ pub async fn create_post(
 pub async fn update_post(
     State(state): State<AppState>,
     Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
     Path(id): Path<Uuid>,
     Json(update): Json<UpdatePost>,
 ) -> impl IntoResponse {
+    let before = match state.db.get_post(id).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
     match state.db.update_post(id, update).await {
-        Ok(Some(post)) => Json(post).into_response(),
+        Ok(Some(post)) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("post.update", "post", id).before(&before).after(&post)).await;
+            Json(post).into_response()
+        }
         Ok(None) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update post").into_response(),
     }
@@ -151,15 +343,224 @@ pub async fn update_post(
 pub async fn delete_post(
     State(state): State<AppState>,
     Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
     Path(id): Path<Uuid>,
 ) -> impl IntoResponse {
-    match state.db.delete_post(id).await {
-        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let before = match state.db.get_post(id).await {
+        Ok(Some(post)) => post,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Post not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.delete_post(id, Some(user_id)).await {
+        Ok(true) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("post.delete", "post", id).before(&before)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
         Ok(false) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete post").into_response(),
     }
 }
 
+pub async fn restore_post(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.restore_post(id).await {
+        Ok(true) => match state.db.get_post(id).await {
+            Ok(Some(post)) => {
+                audit::record(&state, &claims, &ip, AuditEvent::new("post.restore", "post", id).after(&post)).await;
+                Json(post).into_response()
+            }
+            Ok(None) => (StatusCode::NOT_FOUND, "Post not found").into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Ok(false) => (StatusCode::NOT_FOUND, "Post not found in the trash").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to restore post").into_response(),
+    }
+}
+
+// Deleted posts, topics and replies; restoring is done per item
+pub async fn api_list_trash(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<TrashQuery>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let content_type = params.content_type.as_deref().filter(|t| !t.is_empty());
+    if content_type.is_some_and(|t| !matches!(t, "post" | "topic" | "reply")) {
+        return (StatusCode::BAD_REQUEST, "type must be post, topic or reply").into_response();
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.list_trash(content_type, trash::retention_days(), limit, offset).await {
+        Ok(items) => Json(items).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+// Audit log
+// Admins review what moderators and admins changed; entries can't be edited
+
+/// The most entries one CSV export holds.
+const MAX_AUDIT_EXPORT: i64 = 10_000;
+
+pub async fn audit_page() -> impl IntoResponse {
+    let template = AuditLogTemplate {};
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+}
+
+pub async fn api_audit_log(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(filter): Query<AuditQuery>,
+) -> impl IntoResponse {
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let page = filter.page.unwrap_or(1).max(1);
+    let limit = filter.limit.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.list_audit_log(&filter, limit, offset).await {
+        Ok(entries) => Json(entries).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+// The same filters as the log, as a CSV download of up to 10,000 entries
+pub async fn api_export_audit_log(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(filter): Query<AuditQuery>,
+) -> impl IntoResponse {
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    match state.db.list_audit_log(&filter, MAX_AUDIT_EXPORT, 0).await {
+        Ok(entries) => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+                (header::CONTENT_DISPOSITION, "attachment; filename=\"audit-log.csv\""),
+            ],
+            audit::to_csv(&entries),
+        )
+            .into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_update_user_role(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+    Json(update): Json<UpdateUserRole>,
+) -> impl IntoResponse {
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    if claims.sub == user_id.to_string() {
+        return (StatusCode::BAD_REQUEST, "You can't change your own role").into_response();
+    }
+
+    let before = match state.db.get_user_by_id(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.set_user_role(user_id, update.role).await {
+        Ok(Some(user)) => {
+            let event = AuditEvent::new("user.role", "user", user_id)
+                .before(&serde_json::json!({ "role": before.role }))
+                .after(&serde_json::json!({ "role": user.role }));
+            audit::record(&state, &claims, &ip, event).await;
+            Json(UserInfo::from(user)).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update role").into_response(),
+    }
+}
+
+pub async fn api_set_trust_level(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+    Json(update): Json<SetTrustLevel>,
+) -> impl IntoResponse {
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+    if update.level.is_some_and(|level| !(trust::NEW..=trust::LEADER).contains(&level)) {
+        return (StatusCode::BAD_REQUEST, "level must be between 0 and 4").into_response();
+    }
+
+    let before = match state.db.get_user_profile(user_id).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.set_trust_level_override(user_id, update.level).await {
+        Ok(Some(profile)) => {
+            let event = AuditEvent::new("user.trust_level", "user", user_id)
+                .before(&serde_json::json!({ "trust_level": before.trust_level, "override": before.trust_level_override }))
+                .after(&serde_json::json!({ "trust_level": profile.trust_level, "override": profile.trust_level_override }));
+            audit::record(&state, &claims, &ip, event).await;
+            Json(profile).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to set trust level").into_response(),
+    }
+}
+
+pub async fn api_translation_report(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<TranslationReportQuery>,
+) -> impl IntoResponse {
+    // Only admins can see the translation report
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let rows = match state.db.list_translation_rows().await {
+        Ok(rows) => rows,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let mut report = i18n::translation_report(rows);
+    if let Some(lang) = params.lang.as_deref().and_then(i18n::normalize_lang) {
+        report.retain(|entry| entry.missing.iter().chain(&entry.outdated).any(|l| l == lang));
+    }
+    if let Some(content_type) = params.content_type {
+        report.retain(|entry| entry.content_type == content_type);
+    }
+
+    Json(report).into_response()
+}
+
 pub async fn login(
     State(state): State<AppState>,
     Json(credentials): Json<LoginRequest>,
@@ -167,6 +568,13 @@ pub async fn login(
     match state.db.get_user_by_username(&credentials.username).await {
         Ok(Some(user)) => {
             if bcrypt::verify(&credentials.password, &user.password_hash).unwrap_or(false) {
+                // Banned addresses are refused before getting here
+                if user.is_suspended() {
+                    return (StatusCode::FORBIDDEN, restrictions::suspension_message(&user)).into_response();
+                }
+                if let Err(response) = restrictions::check_email_domain(&state, &user.email).await {
+                    return response;
+                }
                 match create_jwt(&user) {
                     Ok(token) => {
                         let response = AuthResponse {
@@ -190,6 +598,10 @@ pub async fn register(
     State(state): State<AppState>,
     Json(user_data): Json<CreateUser>,
 ) -> impl IntoResponse {
+    if let Err(response) = restrictions::check_email_domain(&state, &user_data.email).await {
+        return response;
+    }
+
     match state.db.create_user(user_data).await {
         Ok(user) => {
             match create_jwt(&user) {
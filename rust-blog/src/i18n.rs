@@ -0,0 +1,128 @@
+//! Language negotiation for posts and wiki pages, plus the report of
+//! translations that are missing or lag behind.
+//!
+//! Content lives under explicit language prefixes (`/en/...`, `/zh/...`,
+//! `/zh-tw/...`). Unprefixed URLs pick a language from `Accept-Language`.
+
+use crate::models::{Post, TranslationReportEntry, TranslationRow};
+use axum::http::{header, HeaderMap};
+use chrono::Duration;
+
+pub const SUPPORTED_LANGS: &[&str] = &["en", "zh", "zh-tw"];
+pub const DEFAULT_LANG: &str = "en";
+
+/// Maps a language tag such as `en-US`, `zh-CN` or `zh-Hant-HK` onto one of
+/// the supported languages.
+pub fn normalize_lang(tag: &str) -> Option<&'static str> {
+    let tag = tag.trim().to_ascii_lowercase().replace('_', "-");
+    if let Some(lang) = SUPPORTED_LANGS.iter().find(|lang| **lang == tag) {
+        return Some(lang);
+    }
+
+    let mut subtags = tag.split('-');
+    match subtags.next()? {
+        "en" => Some("en"),
+        "zh" => {
+            let subtags: Vec<&str> = subtags.collect();
+            let traditional = !subtags.contains(&"hans")
+                && subtags.iter().any(|s| matches!(*s, "hant" | "tw" | "hk" | "mo"));
+            Some(if traditional { "zh-tw" } else { "zh" })
+        }
+        _ => None,
+    }
+}
+
+/// Picks the language from `available` the client ranks highest in its
+/// `Accept-Language` header.
+pub fn negotiate(headers: &HeaderMap, available: &[&str]) -> Option<&'static str> {
+    let accept = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    // Stable sort keeps the header order between equal weights
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    ranges
+        .into_iter()
+        .filter_map(|(tag, _)| normalize_lang(tag))
+        .find(|lang| available.contains(lang))
+}
+
+/// The language to serve an unprefixed URL in.
+pub fn preferred_lang(headers: &HeaderMap) -> &'static str {
+    negotiate(headers, SUPPORTED_LANGS).unwrap_or(DEFAULT_LANG)
+}
+
+pub fn post_url(post: &Post) -> String {
+    format!("/{}/posts/{}", post.lang, post.id)
+}
+
+/// How far a translation may fall behind the newest version of the same
+/// content before it counts as outdated. Imports of the different language
+/// trees rarely happen at the same moment, so a strict comparison would flag
+/// everything.
+fn outdated_after() -> Duration {
+    Duration::days(1)
+}
+
+/// Groups translation rows (sorted by content type and translation group)
+/// and keeps the groups that are missing a language or have a version that
+/// lags behind the most recently updated one.
+pub fn translation_report(rows: Vec<TranslationRow>) -> Vec<TranslationReportEntry> {
+    let mut groups: Vec<Vec<TranslationRow>> = Vec::new();
+    for row in rows {
+        match groups.last_mut() {
+            Some(group)
+                if group[0].translation_group == row.translation_group
+                    && group[0].content_type == row.content_type =>
+            {
+                group.push(row)
+            }
+            _ => groups.push(vec![row]),
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|versions| {
+            let newest = versions.iter().map(|v| v.updated_at).max()?;
+            let missing: Vec<String> = SUPPORTED_LANGS
+                .iter()
+                .filter(|lang| !versions.iter().any(|v| v.lang == **lang))
+                .map(|lang| lang.to_string())
+                .collect();
+            let outdated: Vec<String> = versions
+                .iter()
+                .filter(|v| newest - v.updated_at > outdated_after())
+                .map(|v| v.lang.clone())
+                .collect();
+            if missing.is_empty() && outdated.is_empty() {
+                return None;
+            }
+
+            let title = versions
+                .iter()
+                .find(|v| v.lang == DEFAULT_LANG)
+                .unwrap_or(&versions[0])
+                .title
+                .clone();
+            Some(TranslationReportEntry {
+                content_type: versions[0].content_type.clone(),
+                translation_group: versions[0].translation_group,
+                title,
+                versions,
+                missing,
+                outdated,
+            })
+        })
+        .collect()
+}
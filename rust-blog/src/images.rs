@@ -0,0 +1,118 @@
+//! Processing of uploaded images. Images are turned upright by their EXIF
+//! orientation and re-encoded, which drops EXIF and other metadata such as
+//! GPS positions, and large ones get smaller variants for `srcset`. Each is
+//! saved as lossless WebP instead when that is smaller.
+
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+use std::io::Cursor;
+
+/// Larger images are refused before they are decoded.
+pub const MAX_DIMENSION: u32 = 8000;
+/// The variants made of images wider than them, by name and width.
+pub const VARIANTS: [(&str, u32); 2] = [("thumbnail", 320), ("medium", 1024)];
+const JPEG_QUALITY: u8 = 85;
+
+pub struct EncodedImage {
+    pub bytes: Vec<u8>,
+    pub mime_type: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct ProcessedImage {
+    pub original: EncodedImage,
+    pub variants: Vec<(&'static str, EncodedImage)>,
+}
+
+/// Whether `process` handles images of this type.
+pub fn is_processable(mime_type: &str) -> bool {
+    matches!(mime_type, "image/png" | "image/jpeg" | "image/webp" | "image/gif")
+}
+
+/// Decodes an image no larger than `MAX_DIMENSION`, turned upright.
+pub fn decode(bytes: &[u8]) -> Result<(DynamicImage, ImageFormat)> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader.format().context("Unknown image format")?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok((image, format))
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<EncodedImage> {
+    let mut bytes = Vec::new();
+    let mime_type = match format {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let encoder = JpegEncoder::new_with_quality(Cursor::new(&mut bytes), JPEG_QUALITY);
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+            "image/jpeg"
+        }
+        ImageFormat::WebP => {
+            // The encoder takes 8-bit RGB or RGBA only
+            let encoder = WebPEncoder::new_lossless(Cursor::new(&mut bytes));
+            if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8()).write_with_encoder(encoder)?;
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+            }
+            "image/webp"
+        }
+        _ => {
+            image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+            "image/png"
+        }
+    };
+    Ok(EncodedImage { bytes, mime_type, width: image.width(), height: image.height() })
+}
+
+// In its own format, or as WebP if that is smaller
+fn encode_smallest(image: &DynamicImage, format: ImageFormat) -> Result<EncodedImage> {
+    let encoded = encode(image, format)?;
+    if format == ImageFormat::WebP {
+        return Ok(encoded);
+    }
+    let webp = encode(image, ImageFormat::WebP)?;
+    Ok(if webp.bytes.len() < encoded.bytes.len() { webp } else { encoded })
+}
+
+/// Strips the metadata from an uploaded image and makes its variants.
+/// GIFs, which may be animated, are kept as they are, without variants.
+pub fn process(bytes: Vec<u8>) -> Result<ProcessedImage> {
+    let (image, format) = decode(&bytes)?;
+    if format == ImageFormat::Gif {
+        let original = EncodedImage { bytes, mime_type: "image/gif", width: image.width(), height: image.height() };
+        return Ok(ProcessedImage { original, variants: Vec::new() });
+    }
+
+    let original = encode_smallest(&image, format)?;
+    let mut variants = Vec::new();
+    for (name, width) in VARIANTS {
+        if image.width() > width {
+            let resized = image.resize(width, u32::MAX, FilterType::Lanczos3);
+            variants.push((name, encode_smallest(&resized, format)?));
+        }
+    }
+    Ok(ProcessedImage { original, variants })
+}
+
+/// The usual file extension for an image type.
+pub fn extension(mime_type: &str) -> Option<&'static str> {
+    match mime_type {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/webp" => Some("webp"),
+        "image/gif" => Some("gif"),
+        _ => None,
+    }
+}
@@ -0,0 +1,430 @@
+//! Descriptions of files from their content, in the style of `file(1)`, for
+//! the challenge files of write-ups: executables, captures, firmware images
+//! and archives are described in more detail than their MIME type gives.
+
+/// Describes content it recognizes, and returns `None` for anything else.
+type Describer = fn(&[u8]) -> Option<String>;
+
+/// Tried in order; the first to recognize the content describes it.
+const DESCRIBERS: [Describer; 9] = [elf, pe, mach_o, pcap, pcapng, firmware, archive, document, image];
+
+/// A description such as `ELF 64-bit LSB pie executable, x86-64, dynamically
+/// linked, interpreter /lib64/ld-linux-x86-64.so.2, stripped`.
+pub fn describe(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "empty".to_string();
+    }
+    DESCRIBERS
+        .iter()
+        .find_map(|describe| describe(bytes))
+        .unwrap_or_else(|| text(bytes))
+}
+
+// Fixed-size reads that are `None` past the end of the content
+
+fn u16_at(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let b: [u8; 2] = bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+    Some(if big_endian { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) })
+}
+
+fn u32_at(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let b: [u8; 4] = bytes.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) })
+}
+
+fn u64_at(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u64> {
+    let b: [u8; 8] = bytes.get(offset..offset.checked_add(8)?)?.try_into().ok()?;
+    Some(if big_endian { u64::from_be_bytes(b) } else { u64::from_le_bytes(b) })
+}
+
+// A NUL-terminated string of printable ASCII
+fn c_string(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let value = std::str::from_utf8(&bytes[..end]).ok()?;
+    (!value.is_empty() && value.chars().all(|c| c.is_ascii_graphic() || c == ' ')).then(|| value.to_string())
+}
+
+fn elf(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let is_64 = match bytes.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return Some("ELF, invalid class".to_string()),
+    };
+    let big_endian = *bytes.get(5)? == 2;
+    let word = |offset: usize| -> Option<usize> {
+        if is_64 {
+            u64_at(bytes, offset, big_endian).and_then(|value| usize::try_from(value).ok())
+        } else {
+            u32_at(bytes, offset, big_endian).map(|value| value as usize)
+        }
+    };
+
+    let e_type = u16_at(bytes, 16, big_endian)?;
+    let machine = match u16_at(bytes, 18, big_endian)? {
+        0x03 => "Intel 80386".to_string(),
+        0x08 => "MIPS".to_string(),
+        0x14 => "PowerPC".to_string(),
+        0x15 => "64-bit PowerPC".to_string(),
+        0x28 => "ARM".to_string(),
+        0x3e => "x86-64".to_string(),
+        0xb7 => "ARM aarch64".to_string(),
+        0xf3 => "RISC-V".to_string(),
+        other => format!("machine {:#x}", other),
+    };
+
+    // Program headers tell static from dynamic linking, and the section
+    // headers whether the symbol table is still there
+    let (ph_offset, sh_offset) = if is_64 { (word(32)?, word(40)?) } else { (word(28)?, word(32)?) };
+    let (ph_size, ph_count, sh_size, sh_count) = if is_64 {
+        (u16_at(bytes, 54, big_endian)?, u16_at(bytes, 56, big_endian)?, u16_at(bytes, 58, big_endian)?, u16_at(bytes, 60, big_endian)?)
+    } else {
+        (u16_at(bytes, 42, big_endian)?, u16_at(bytes, 44, big_endian)?, u16_at(bytes, 46, big_endian)?, u16_at(bytes, 48, big_endian)?)
+    };
+    let mut interpreter = None;
+    let mut dynamic = false;
+    for i in 0..ph_count as usize {
+        let header = ph_offset.saturating_add(i * ph_size as usize);
+        match u32_at(bytes, header, big_endian) {
+            Some(2) => dynamic = true,
+            Some(3) => {
+                let (offset, size) = if is_64 { (word(header + 8), word(header + 32)) } else { (word(header + 4), word(header + 16)) };
+                interpreter = offset
+                    .zip(size)
+                    .and_then(|(offset, size)| bytes.get(offset..offset.checked_add(size)?))
+                    .and_then(c_string);
+            }
+            _ => {}
+        }
+    }
+    let has_symbols = sh_offset != 0
+        && (0..sh_count as usize)
+            .any(|i| u32_at(bytes, sh_offset.saturating_add(i * sh_size as usize + 4), big_endian) == Some(2));
+
+    let kind = match (e_type, interpreter.is_some()) {
+        (1, _) => "relocatable",
+        (2, _) => "executable",
+        (3, true) => "pie executable",
+        (3, false) => "shared object",
+        (4, _) => "core file",
+        _ => "unknown type",
+    };
+    let mut description = format!(
+        "ELF {}-bit {} {}, {}",
+        if is_64 { 64 } else { 32 },
+        if big_endian { "MSB" } else { "LSB" },
+        kind,
+        machine
+    );
+    if matches!(e_type, 2 | 3) {
+        description.push_str(if dynamic || interpreter.is_some() { ", dynamically linked" } else { ", statically linked" });
+    }
+    if let Some(interpreter) = interpreter {
+        description.push_str(&format!(", interpreter {}", interpreter));
+    }
+    if e_type != 4 && sh_count > 0 {
+        description.push_str(if has_symbols { ", not stripped" } else { ", stripped" });
+    }
+    Some(description)
+}
+
+fn pe(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(b"MZ") {
+        return None;
+    }
+    let header = u32_at(bytes, 0x3c, false)
+        .map(|offset| offset as usize)
+        .filter(|&offset| bytes.get(offset..offset + 4) == Some(b"PE\0\0"));
+    let Some(header) = header else {
+        return Some("MS-DOS executable".to_string());
+    };
+
+    let machine = match u16_at(bytes, header + 4, false)? {
+        0x014c => "Intel 80386".to_string(),
+        0x8664 => "x86-64".to_string(),
+        0x01c0 | 0x01c4 => "ARM".to_string(),
+        0xaa64 => "Aarch64".to_string(),
+        other => format!("machine {:#x}", other),
+    };
+    let is_dll = u16_at(bytes, header + 22, false)? & 0x2000 != 0;
+    let optional = header + 24;
+    let format = match u16_at(bytes, optional, false) {
+        Some(0x20b) => "PE32+",
+        _ => "PE32",
+    };
+    let subsystem = match u16_at(bytes, optional + 68, false) {
+        Some(1) => "native",
+        Some(2) => "GUI",
+        Some(3) => "console",
+        Some(10..=13) => "EFI application",
+        _ => "unknown subsystem",
+    };
+    let kind = if is_dll { " (DLL)" } else { "" };
+    Some(format!("{} executable{} ({}) {}, for MS Windows", format, kind, subsystem, machine))
+}
+
+fn mach_o(bytes: &[u8]) -> Option<String> {
+    let (is_64, big_endian) = match bytes.get(..4)? {
+        [0xce, 0xfa, 0xed, 0xfe] => (false, false),
+        [0xcf, 0xfa, 0xed, 0xfe] => (true, false),
+        [0xfe, 0xed, 0xfa, 0xce] => (false, true),
+        [0xfe, 0xed, 0xfa, 0xcf] => (true, true),
+        _ => return None,
+    };
+    let cpu = match u32_at(bytes, 4, big_endian)? {
+        7 => "i386".to_string(),
+        0x0100_0007 => "x86_64".to_string(),
+        12 => "arm".to_string(),
+        0x0100_000c => "arm64".to_string(),
+        other => format!("cpu {:#x}", other),
+    };
+    let kind = match u32_at(bytes, 12, big_endian)? {
+        1 => "object",
+        2 => "executable",
+        6 => "dynamically linked shared library",
+        8 => "bundle",
+        _ => "file",
+    };
+    Some(format!("Mach-O {}{} {}", if is_64 { "64-bit " } else { "" }, cpu, kind))
+}
+
+fn link_type(link_type: u32) -> String {
+    match link_type {
+        0 => "BSD loopback".to_string(),
+        1 => "Ethernet".to_string(),
+        101 => "raw IP".to_string(),
+        105 => "802.11".to_string(),
+        113 => "Linux cooked v1".to_string(),
+        127 => "802.11 with radiotap header".to_string(),
+        189 => "USB with Linux header".to_string(),
+        195 => "IEEE 802.15.4".to_string(),
+        201 => "Bluetooth HCI H4 with pseudo-header".to_string(),
+        228 => "raw IPv4".to_string(),
+        229 => "raw IPv6".to_string(),
+        276 => "Linux cooked v2".to_string(),
+        other => format!("link type {}", other),
+    }
+}
+
+fn pcap(bytes: &[u8]) -> Option<String> {
+    let (big_endian, resolution) = match bytes.get(..4)? {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, "microsecond"),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, "microsecond"),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, "nanosecond"),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, "nanosecond"),
+        _ => return None,
+    };
+    Some(format!(
+        "pcap capture file, {} ts ({}) - version {}.{} ({}, capture length {})",
+        resolution,
+        if big_endian { "big-endian" } else { "little-endian" },
+        u16_at(bytes, 4, big_endian)?,
+        u16_at(bytes, 6, big_endian)?,
+        link_type(u32_at(bytes, 20, big_endian)?),
+        u32_at(bytes, 16, big_endian)?
+    ))
+}
+
+fn pcapng(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+        return None;
+    }
+    let big_endian = match bytes.get(8..12)? {
+        [0x4d, 0x3c, 0x2b, 0x1a] => false,
+        [0x1a, 0x2b, 0x3c, 0x4d] => true,
+        _ => return None,
+    };
+    Some(format!(
+        "pcapng capture file ({}) - version {}.{}",
+        if big_endian { "big-endian" } else { "little-endian" },
+        u16_at(bytes, 12, big_endian)?,
+        u16_at(bytes, 14, big_endian)?
+    ))
+}
+
+fn firmware(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(&[0x27, 0x05, 0x19, 0x56]) {
+        let size = u32_at(bytes, 12, true)?;
+        return Some(match bytes.get(32..64).and_then(c_string) {
+            Some(name) => format!("u-boot legacy uImage, {}, {} bytes", name, size),
+            None => format!("u-boot legacy uImage, {} bytes", size),
+        });
+    }
+    if bytes.starts_with(b"hsqs") || bytes.starts_with(b"sqsh") {
+        let big_endian = bytes.starts_with(b"sqsh");
+        return Some(format!(
+            "Squashfs filesystem, {} endian, version {}.{}",
+            if big_endian { "big" } else { "little" },
+            u16_at(bytes, 28, big_endian)?,
+            u16_at(bytes, 30, big_endian)?
+        ));
+    }
+    if bytes.starts_with(&[0xd0, 0x0d, 0xfe, 0xed]) {
+        return Some(format!("Device Tree Blob version {}, size={}", u32_at(bytes, 20, true)?, u32_at(bytes, 4, true)?));
+    }
+    if bytes.starts_with(b"ANDROID!") {
+        return Some("Android bootimg".to_string());
+    }
+    if bytes.starts_with(b"070701") || bytes.starts_with(b"070707") {
+        return Some("ASCII cpio archive".to_string());
+    }
+    if u16_at(bytes, 1080, false) == Some(0xef53) {
+        return Some("Linux ext2/ext3/ext4 filesystem data".to_string());
+    }
+    if bytes.get(32769..32774) == Some(b"CD001") {
+        return Some("ISO 9660 CD-ROM filesystem data".to_string());
+    }
+    None
+}
+
+fn archive(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        let version = u16_at(bytes, 4, false).unwrap_or(0);
+        return Some(format!("Zip archive data, at least v{}.{} to extract", version / 10, version % 10));
+    }
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let name = if bytes.get(3).is_some_and(|flags| flags & 0x08 != 0) {
+            bytes.get(10..).and_then(c_string).map(|name| format!(", was \"{}\"", name))
+        } else {
+            None
+        };
+        return Some(format!("gzip compressed data{}", name.unwrap_or_default()));
+    }
+    if bytes.get(257..262) == Some(b"ustar") {
+        return Some("POSIX tar archive".to_string());
+    }
+    if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        return Some("XZ compressed data".to_string());
+    }
+    if bytes.starts_with(b"BZh") {
+        let block = bytes.get(3).filter(|b| b.is_ascii_digit()).map(|b| (b - b'0') as u32 * 100);
+        return Some(match block {
+            Some(block) => format!("bzip2 compressed data, block size = {}k", block),
+            None => "bzip2 compressed data".to_string(),
+        });
+    }
+    if bytes.starts_with(&[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c]) {
+        return Some(format!("7-zip archive data, version {}.{}", bytes.get(6)?, bytes.get(7)?));
+    }
+    if bytes.starts_with(b"Rar!\x1a\x07") {
+        return Some("RAR archive data".to_string());
+    }
+    if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        return Some("Zstandard compressed data".to_string());
+    }
+    None
+}
+
+fn document(bytes: &[u8]) -> Option<String> {
+    if let Some(rest) = bytes.strip_prefix(b"%PDF-") {
+        let version: String = rest.iter().take_while(|b| b.is_ascii_digit() || **b == b'.').map(|&b| b as char).collect();
+        return Some(format!("PDF document, version {}", version));
+    }
+    if bytes.starts_with(&[0xca, 0xfe, 0xba, 0xbe]) {
+        // Java classes share their magic with fat Mach-O binaries, which
+        // count architectures where classes have a version above 40
+        let major = u16_at(bytes, 6, true)?;
+        return Some(if major > 40 {
+            format!("compiled Java class data, version {}.{}", major, u16_at(bytes, 4, true)?)
+        } else {
+            format!("Mach-O universal binary with {} architectures", u32_at(bytes, 4, true)?)
+        });
+    }
+    if bytes.starts_with(b"SQLite format 3\0") {
+        return Some("SQLite 3.x database".to_string());
+    }
+    None
+}
+
+fn image(bytes: &[u8]) -> Option<String> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some(format!("PNG image data, {} x {}", u32_at(bytes, 16, true)?, u32_at(bytes, 20, true)?));
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        let version = std::str::from_utf8(&bytes[3..6]).ok()?;
+        return Some(format!("GIF image data, version {}, {} x {}", version, u16_at(bytes, 6, false)?, u16_at(bytes, 8, false)?));
+    }
+    if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+        return Some("JPEG image data".to_string());
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return Some("RIFF (little-endian) data, Web/P image".to_string());
+    }
+    if bytes.starts_with(b"BM") && u32_at(bytes, 2, false).is_some_and(|size| size as usize == bytes.len()) {
+        return Some("PC bitmap".to_string());
+    }
+    None
+}
+
+fn text(bytes: &[u8]) -> String {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return "data".to_string();
+    };
+    if text.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c' | '\x1b')) {
+        return "data".to_string();
+    }
+    let encoding = if text.is_ascii() { "ASCII text" } else { "Unicode text, UTF-8 text" };
+    let lines = if text.contains("\r\n") { ", with CRLF line terminators" } else { "" };
+    match text.strip_prefix("#!").and_then(|rest| rest.lines().next()) {
+        Some(interpreter) => {
+            let interpreter = interpreter.trim();
+            let interpreter = interpreter.strip_prefix("/usr/bin/env ").unwrap_or(interpreter);
+            format!("{} script, {} executable{}", interpreter.trim(), encoding, lines)
+        }
+        None => format!("{}{}", encoding, lines),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::describe;
+
+    #[test]
+    fn empty_files_are_empty() {
+        assert_eq!(describe(b""), "empty");
+    }
+
+    #[test]
+    fn elf_headers_are_described() {
+        let mut header = vec![0u8; 64];
+        header[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        header[16] = 2;
+        header[18] = 0x3e;
+        assert_eq!(describe(&header), "ELF 64-bit LSB executable, x86-64, statically linked");
+    }
+
+    #[test]
+    fn truncated_headers_fall_back_to_data() {
+        assert_eq!(describe(b"\x7fELF\x02\x01"), "data");
+        assert_eq!(describe(b"\x89PNG\r\n\x1a\n"), "data");
+        assert_eq!(describe(&[0xd4, 0xc3, 0xb2, 0xa1, 0x02]), "data");
+        assert_eq!(describe(b"MZ"), "MS-DOS executable");
+        assert_eq!(describe(&[0x1f, 0x8b, 0x08, 0x08]), "gzip compressed data");
+    }
+
+    #[test]
+    fn images_and_archives_are_described() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(describe(&png), "PNG image data, 640 x 480");
+        assert_eq!(describe(b"PK\x03\x04\x14\x00"), "Zip archive data, at least v2.0 to extract");
+        assert_eq!(
+            describe(b"\x1f\x8b\x08\x08\0\0\0\0\0\x03flag.txt\0"),
+            "gzip compressed data, was \"flag.txt\""
+        );
+    }
+
+    #[test]
+    fn text_is_described_by_encoding_and_interpreter() {
+        assert_eq!(describe(b"hello\r\n"), "ASCII text, with CRLF line terminators");
+        assert_eq!(describe("héllo\n".as_bytes()), "Unicode text, UTF-8 text");
+        assert_eq!(
+            describe(b"#!/usr/bin/env python3\nprint('hi')\n"),
+            "python3 script, ASCII text executable"
+        );
+        assert_eq!(describe(&[0x00, 0x01, 0x02]), "data");
+    }
+}
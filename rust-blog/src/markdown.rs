@@ -0,0 +1,305 @@
+//! Markdown rendering for wiki pages and forum posts.
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub title: String,
+}
+
+/// An attached image with smaller variants, rendered with `srcset` so
+/// browsers can fetch the smallest that fills the space.
+#[derive(Debug, Clone)]
+pub struct ResponsiveImage {
+    pub width: i32,
+    pub height: i32,
+    /// URLs by the width of the image they serve, including the original.
+    pub sources: Vec<(String, i32)>,
+}
+
+fn options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
+// Heading attributes would let untrusted content set any attribute, such
+// as event handlers, ids and classes
+fn safe_options() -> Options {
+    let mut options = options();
+    options.remove(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
+/// Parses untrusted `content` the way `render_safe` does.
+pub fn parse_safe(content: &str) -> Parser<'_> {
+    Parser::new_ext(content, safe_options())
+}
+
+// The id in an attachment URL such as `/attachments/<id>`; variant URLs
+// are left as they are
+fn attachment_id(url: &str) -> Option<Uuid> {
+    let (_, rest) = url.split_once("/attachments/")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    rest.parse().ok()
+}
+
+/// The attachments `content` shows as images.
+pub fn attachment_ids(content: &str) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = Parser::new_ext(content, options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. }) => attachment_id(&dest_url),
+            _ => None,
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn responsive_img(url: &str, alt: &str, title: &str, image: &ResponsiveImage) -> String {
+    let srcset: Vec<String> = image
+        .sources
+        .iter()
+        .map(|(source, width)| format!("{} {}w", source, width))
+        .collect();
+    let mut tag = format!(
+        "<img src=\"{}\" srcset=\"{}\" sizes=\"(max-width: {}px) 100vw, {}px\" width=\"{}\" height=\"{}\" alt=\"{}\" loading=\"lazy\"",
+        escape_attribute(url),
+        escape_attribute(&srcset.join(", ")),
+        image.width,
+        image.width,
+        image.width,
+        image.height,
+        escape_attribute(alt),
+    );
+    if !title.is_empty() {
+        tag.push_str(&format!(" title=\"{}\"", escape_attribute(title)));
+    }
+    tag.push_str(" />");
+    tag
+}
+
+// Replaces the known attachments among the images with `responsive_img`
+fn make_images_responsive<'a>(events: Vec<Event<'a>>, images: &HashMap<Uuid, ResponsiveImage>) -> Vec<Event<'a>> {
+    if images.is_empty() {
+        return events;
+    }
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let Event::Start(Tag::Image { dest_url, title, .. }) = &event else {
+            output.push(event);
+            continue;
+        };
+        let Some(image) = attachment_id(dest_url).and_then(|id| images.get(&id)) else {
+            output.push(event);
+            continue;
+        };
+
+        let (url, title) = (dest_url.to_string(), title.to_string());
+        let mut alt = String::new();
+        let mut depth = 1;
+        for inner in events.by_ref() {
+            match inner {
+                Event::Start(Tag::Image { .. }) => depth += 1,
+                Event::End(TagEnd::Image) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+                _ => {}
+            }
+        }
+        output.push(Event::InlineHtml(CowStr::from(responsive_img(&url, &alt, &title, image))));
+    }
+    output
+}
+
+/// Renders `content` with an anchor on every heading and returns the table of
+/// contents built from its second to fourth level headings. Attached images
+/// found in `images` get a `srcset` of their variants.
+pub fn render_with_toc(content: &str, images: &HashMap<Uuid, ResponsiveImage>) -> (String, Vec<TocEntry>) {
+    let events: Vec<Event> = Parser::new_ext(content, options()).collect();
+    let mut events = make_images_responsive(events, images);
+    let mut toc = Vec::new();
+    let mut used_ids = HashMap::new();
+
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::Heading { level, .. }) = &events[i] else {
+            i += 1;
+            continue;
+        };
+        let level = *level as u8;
+
+        let mut title = String::new();
+        let mut end = i + 1;
+        while end < events.len() && !matches!(events[end], Event::End(TagEnd::Heading(_))) {
+            if let Event::Text(text) | Event::Code(text) = &events[end] {
+                title.push_str(text);
+            }
+            end += 1;
+        }
+
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+            let anchor = match id {
+                Some(existing) => existing.to_string(),
+                None => {
+                    let anchor = unique_anchor(&title, &mut used_ids);
+                    *id = Some(CowStr::from(anchor.clone()));
+                    anchor
+                }
+            };
+            if (2..=4).contains(&level) {
+                toc.push(TocEntry {
+                    level,
+                    id: anchor,
+                    title: title.trim().to_string(),
+                });
+            }
+        }
+
+        i = end;
+    }
+
+    let mut output = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut output, events.into_iter());
+    (output, toc)
+}
+
+// Relative URLs and http, https and mailto ones; `javascript:` and the
+// like aren't
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim_start();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => {
+            matches!(url[..i].to_ascii_lowercase().as_str(), "http" | "https" | "mailto")
+        }
+        _ => true,
+    }
+}
+
+/// Renders untrusted `content`, such as forum posts: raw HTML is shown as
+/// written rather than interpreted, and links and images to anything but
+/// `is_safe_url` URLs lose their target. Attached images found in `images`
+/// get a `srcset` of their variants.
+pub fn render_safe(content: &str, images: &HashMap<Uuid, ResponsiveImage>) -> String {
+    let events: Vec<Event> = parse_safe(content)
+        .map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            mut event => {
+                if let Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) = &mut event {
+                    if !is_safe_url(dest_url) {
+                        *dest_url = CowStr::Borrowed("");
+                    }
+                }
+                event
+            }
+        })
+        .collect();
+    let events = make_images_responsive(events, images);
+
+    let mut output = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut output, events.into_iter());
+    output
+}
+
+fn unique_anchor(title: &str, used: &mut HashMap<String, usize>) -> String {
+    let mut anchor = String::new();
+    for c in title.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            anchor.push(c);
+        } else if (c.is_whitespace() || c == '-') && !anchor.ends_with('-') {
+            anchor.push('-');
+        }
+    }
+    let anchor = match anchor.trim_matches('-') {
+        "" => "section".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+
+    let count = used.entry(anchor.clone()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        anchor
+    } else {
+        format!("{}-{}", anchor, *count - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(content: &str) -> String {
+        render_safe(content, &HashMap::new())
+    }
+
+    #[test]
+    fn renders_markdown() {
+        assert_eq!(render("**bold** and `code`"), "<p><strong>bold</strong> and <code>code</code></p>\n");
+    }
+
+    #[test]
+    fn raw_html_is_shown_as_text() {
+        assert!(render("<script>alert(1)</script>").contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(render("hi <img src=x onerror=alert(1)>").contains("&lt;img src=x onerror=alert(1)&gt;"));
+        assert!(!render("<div onclick=\"x\">a</div>").contains("<div"));
+        assert_eq!(render("# hi {onmouseover=alert(1)}"), "<h1>hi {onmouseover=alert(1)}</h1>\n");
+        assert_eq!(render("## a {#reply-1 .mention}"), "<h2>a {#reply-1 .mention}</h2>\n");
+    }
+
+    #[test]
+    fn unsafe_links_lose_their_target() {
+        assert_eq!(render("[x](javascript:alert(1))"), "<p><a href=\"\">x</a></p>\n");
+        assert_eq!(render("[x](JavaScript:alert(1))"), "<p><a href=\"\">x</a></p>\n");
+        assert_eq!(render("![x](data:image/svg+xml,abc)"), "<p><img src=\"\" alt=\"x\" /></p>\n");
+    }
+
+    #[test]
+    fn safe_links_are_kept() {
+        assert!(render("[x](https://example.com/a:b)").contains("href=\"https://example.com/a:b\""));
+        assert!(render("[x](/forum/t/a?b=c:d)").contains("href=\"/forum/t/a?b=c:d\""));
+        assert!(render("[x](mailto:a@b.com)").contains("href=\"mailto:a@b.com\""));
+    }
+
+    #[test]
+    fn attached_images_get_a_srcset() {
+        let id = Uuid::new_v4();
+        let image = ResponsiveImage {
+            width: 1600,
+            height: 900,
+            sources: vec![(format!("/attachments/{}/small", id), 480), (format!("/attachments/{}", id), 1600)],
+        };
+        let html = render_safe(&format!("![chart](/attachments/{})", id), &HashMap::from([(id, image)]));
+        assert!(html.contains(&format!("srcset=\"/attachments/{0}/small 480w, /attachments/{0} 1600w\"", id)));
+        assert!(html.contains("alt=\"chart\""));
+    }
+}
@@ -0,0 +1,301 @@
+//! `@username` mentions in topics and replies. Mentions of existing members
+//! notify them and are shown as links to their profiles. Mentions inside
+//! HTML tags, code and links aren't mentions.
+
+use crate::auth::is_moderator;
+use crate::database::Database;
+use crate::models::{Claims, NotificationType};
+use crate::AppState;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Longer usernames can't be registered.
+const MAX_USERNAME_CHARS: usize = 50;
+/// A topic or reply mentioning more people is refused.
+pub const MAX_MENTIONS_PER_POST: usize = 10;
+/// How many people a member can mention an hour, across all their posts;
+/// moderators aren't limited.
+pub const MAX_MENTIONS_PER_HOUR: i64 = 30;
+
+/// Elements whose text is left alone.
+const SKIPPED_ELEMENTS: [&str; 3] = ["a", "code", "pre"];
+
+fn is_username_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-')
+}
+
+// Only `<` starting a tag, so `a < b` doesn't hide what follows
+fn starts_tag(bytes: &[u8], i: usize) -> bool {
+    bytes.get(i + 1).is_some_and(|b| b.is_ascii_alphabetic() || *b == b'/')
+}
+
+// The lowercase name of the element the tag at `i` opens or closes, and
+// whether it closes it
+fn tag_name(bytes: &[u8], i: usize) -> (String, bool) {
+    let closing = bytes.get(i + 1) == Some(&b'/');
+    let start = if closing { i + 2 } else { i + 1 };
+    let name = bytes[start.min(bytes.len())..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .map(|b| b.to_ascii_lowercase() as char)
+        .collect();
+    (name, closing)
+}
+
+// Not part of an email address or a path
+fn starts_mention(bytes: &[u8], i: usize) -> bool {
+    i == 0 || !(is_username_byte(bytes[i - 1]) || matches!(bytes[i - 1], b'@' | b'/'))
+}
+
+// The byte ranges of the mentions, `@` included
+fn find(content: &str) -> Vec<(usize, usize)> {
+    let bytes = content.as_bytes();
+    let mut found = Vec::new();
+    let (mut in_tag, mut in_code) = (false, false);
+    // How deep inside links and code elements
+    let mut skipped = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' if !in_code && starts_tag(bytes, i) => {
+                in_tag = true;
+                let (name, closing) = tag_name(bytes, i);
+                if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+                    skipped = if closing { skipped.saturating_sub(1) } else { skipped + 1 };
+                }
+            }
+            b'>' if in_tag => in_tag = false,
+            b'`' if !in_tag => in_code = !in_code,
+            b'@' if !in_tag && !in_code && skipped == 0 && starts_mention(bytes, i) => {
+                let mut end = i + 1 + bytes[i + 1..].iter().take_while(|b| is_username_byte(**b)).count();
+                // A mention can end a sentence
+                while end > i + 1 && matches!(bytes[end - 1], b'.' | b'-') {
+                    end -= 1;
+                }
+                if end > i + 1 && end - i - 1 <= MAX_USERNAME_CHARS {
+                    found.push((i, end));
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+/// The usernames mentioned in `content`, each once, in lowercase.
+pub fn usernames(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = find(content)
+        .into_iter()
+        .map(|(start, end)| content[start + 1..end].to_lowercase())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// `html` with the mentions of `members`, by lowercase username, linked to
+/// their profiles. `html` must already be escaped or sanitized; only the
+/// links are added.
+pub fn link(html: &str, members: &HashMap<String, String>) -> String {
+    let mut linked = String::with_capacity(html.len());
+    let mut last = 0;
+    for (start, end) in find(html) {
+        let Some(username) = members.get(&html[start + 1..end].to_lowercase()) else {
+            continue;
+        };
+        linked.push_str(&html[last..start]);
+        linked.push_str(&format!("<a href=\"/u/{0}\" class=\"mention\">@{0}</a>", username));
+        last = end;
+    }
+    linked.push_str(&html[last..]);
+    linked
+}
+
+/// The members mentioned anywhere in `contents`, for `link`, looked up at
+/// once.
+pub async fn members_mentioned<'a>(
+    db: &Database,
+    contents: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<HashMap<String, String>> {
+    let mut names: Vec<String> = contents.into_iter().flat_map(usernames).collect();
+    names.sort();
+    names.dedup();
+    if names.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    Ok(db
+        .find_users_by_usernames(&names)
+        .await?
+        .into_iter()
+        .map(|(_, username)| (username.to_lowercase(), username))
+        .collect())
+}
+
+// The usernames a new post mentions, unless it mentions too many people
+fn post_usernames(content: &str) -> Result<Vec<String>, String> {
+    let names = usernames(content);
+    if names.len() > MAX_MENTIONS_PER_POST {
+        return Err(format!("A post can mention at most {} people", MAX_MENTIONS_PER_POST));
+    }
+    Ok(names)
+}
+
+/// The members mentioned in new content, other than its author. Refuses
+/// content mentioning more than `MAX_MENTIONS_PER_POST` people, or more
+/// than the author may mention this hour.
+pub async fn check(state: &AppState, claims: &Claims, author_id: Uuid, content: &str) -> Result<Vec<Uuid>, Response> {
+    let names = post_usernames(content).map_err(|message| (StatusCode::BAD_REQUEST, message).into_response())?;
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mentioned: Vec<Uuid> = match state.db.find_users_by_usernames(&names).await {
+        Ok(users) => users.into_iter().map(|(id, _)| id).filter(|id| *id != author_id).collect(),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    };
+    if mentioned.is_empty() || is_moderator(&claims.role) {
+        return Ok(mentioned);
+    }
+
+    let recent = match state.db.count_recent_mentions_by(author_id).await {
+        Ok(count) => count,
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    };
+    if recent + mentioned.len() as i64 > MAX_MENTIONS_PER_HOUR {
+        let message = format!("You can mention at most {} people an hour; try again later", MAX_MENTIONS_PER_HOUR);
+        return Err((StatusCode::TOO_MANY_REQUESTS, message).into_response());
+    }
+    Ok(mentioned)
+}
+
+/// Notifies the mentioned members of a new topic or reply.
+pub async fn notify(state: &AppState, author_id: Uuid, topic_id: Uuid, reply_id: Option<Uuid>, mentioned: &[Uuid]) {
+    if mentioned.is_empty() {
+        return;
+    }
+    let created = state
+        .db
+        .create_notifications(NotificationType::Mention, author_id, Some(topic_id), reply_id, None, mentioned)
+        .await;
+    if let Err(e) = created {
+        tracing::warn!("Failed to notify mentioned members: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(usernames: &[&str]) -> HashMap<String, String> {
+        usernames.iter().map(|name| (name.to_lowercase(), name.to_string())).collect()
+    }
+
+    #[test]
+    fn finds_mentions() {
+        assert_eq!(usernames("@alice and @bob.smith, hi"), vec!["alice", "bob.smith"]);
+        assert_eq!(usernames("@carol"), vec!["carol"]);
+    }
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert!(usernames("write to a@b.com").is_empty());
+        assert!(usernames("user@example.org or admin@localhost").is_empty());
+    }
+
+    #[test]
+    fn paths_and_double_at_are_not_mentions() {
+        assert!(usernames("see https://example.com/@alice").is_empty());
+        assert!(usernames("@@alice").is_empty());
+    }
+
+    #[test]
+    fn mentions_in_code_are_ignored() {
+        assert!(usernames("run `npm i @alice/pkg` first").is_empty());
+        assert!(usernames("```\n@alice\n```").is_empty());
+        assert!(usernames("<code>@alice</code> <pre>@bob</pre>").is_empty());
+        assert_eq!(usernames("`@alice` then @bob"), vec!["bob"]);
+    }
+
+    #[test]
+    fn mentions_in_links_and_tags_are_ignored() {
+        assert!(usernames(r#"<a href="/x">@alice</a>"#).is_empty());
+        assert!(usernames(r#"<img alt="@alice">"#).is_empty());
+        assert_eq!(usernames(r#"<a href="/x"><b>@alice</b></a> @bob"#), vec!["bob"]);
+    }
+
+    #[test]
+    fn a_less_than_sign_does_not_hide_mentions() {
+        assert_eq!(usernames("a < b, ask @alice"), vec!["alice"]);
+    }
+
+    #[test]
+    fn trailing_punctuation_is_not_part_of_the_username() {
+        assert_eq!(usernames("thanks @alice."), vec!["alice"]);
+        assert_eq!(usernames("@alice, @bob! @carol? @dave's @erin-"), vec!["alice", "bob", "carol", "dave", "erin"]);
+        assert_eq!(usernames("(@alice)"), vec!["alice"]);
+    }
+
+    #[test]
+    fn overlong_usernames_are_not_mentions() {
+        let name = "a".repeat(MAX_USERNAME_CHARS + 1);
+        assert!(usernames(&format!("@{}", name)).is_empty());
+        assert_eq!(usernames(&format!("@{}", &name[1..])).len(), 1);
+    }
+
+    #[test]
+    fn duplicate_and_case_variant_mentions_count_once() {
+        assert_eq!(usernames("@Alice @alice @ALICE @bob @alice"), vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn links_members_with_their_own_spelling() {
+        let linked = link("hi @ALICE and @Alice", &members(&["Alice"]));
+        assert_eq!(
+            linked,
+            r#"hi <a href="/u/Alice" class="mention">@Alice</a> and <a href="/u/Alice" class="mention">@Alice</a>"#
+        );
+    }
+
+    #[test]
+    fn leaves_unknown_usernames_and_emails_alone() {
+        assert_eq!(link("@nobody, mail a@alice.com", &members(&["alice"])), "@nobody, mail a@alice.com");
+    }
+
+    #[test]
+    fn does_not_link_inside_code_or_links() {
+        let html = r#"<code>@alice</code> <a href="/x">@alice</a>"#;
+        assert_eq!(link(html, &members(&["alice"])), html);
+    }
+
+    #[test]
+    fn links_in_escaped_content() {
+        let html = "&lt;script&gt;@alice&lt;/script&gt;";
+        assert_eq!(
+            link(html, &members(&["alice"])),
+            r#"&lt;script&gt;<a href="/u/alice" class="mention">@alice</a>&lt;/script&gt;"#
+        );
+    }
+
+    #[test]
+    fn posts_can_mention_up_to_the_limit() {
+        let names: Vec<String> = (0..MAX_MENTIONS_PER_POST).map(|n| format!("@user{}", n)).collect();
+        assert_eq!(post_usernames(&names.join(" ")).unwrap().len(), MAX_MENTIONS_PER_POST);
+
+        let names: Vec<String> = (0..=MAX_MENTIONS_PER_POST).map(|n| format!("@user{}", n)).collect();
+        assert!(post_usernames(&names.join(" ")).is_err());
+    }
+
+    #[test]
+    fn repeated_mentions_count_once_towards_the_limit() {
+        let content = ["@alice @Alice"; MAX_MENTIONS_PER_POST + 1].join(" ");
+        assert_eq!(post_usernames(&content).unwrap(), vec!["alice"]);
+    }
+}
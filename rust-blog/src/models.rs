@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -13,6 +13,8 @@ pub struct Post {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub tags: Vec<String>,
+    pub lang: String,
+    pub translation_group: Uuid,
 }
 
 // Forum Models
@@ -28,6 +30,8 @@ pub struct Category {
     pub topics_count: i64,
     pub posts_count: i64,
     pub last_post_at: Option<DateTime<Utc>>,
+    /// The trust level needed to start topics here.
+    pub min_trust_level: i16,
     pub created_at: DateTime<Utc>,
 }
 
@@ -40,13 +44,20 @@ pub struct Topic {
     pub user_id: Uuid,
     pub views: i64,
     pub replies_count: i64,
+    pub likes_count: i64,
     pub is_pinned: bool,
+    /// Also pinned to the top of the list of all topics.
+    pub is_pinned_globally: bool,
     pub is_locked: bool,
+    /// Why a moderator locked the topic, shown to readers.
+    pub lock_reason: Option<String>,
     pub is_solved: bool,
     pub last_reply_at: Option<DateTime<Utc>>,
     pub last_reply_user_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub edited_by: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -60,6 +71,8 @@ pub struct Reply {
     pub reply_to_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub edited_by: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -76,10 +89,32 @@ pub struct UserProfile {
     pub likes_given: i64,
     pub likes_received: i64,
     pub last_seen_at: Option<DateTime<Utc>>,
+    /// The level earned from activity; see `trust.rs`.
+    pub trust_level: i16,
+    /// Set by an admin, and used instead of `trust_level` while set.
+    pub trust_level_override: Option<i16>,
+    pub days_visited: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl UserProfile {
+    pub fn effective_trust_level(&self) -> i16 {
+        self.trust_level_override.unwrap_or(self.trust_level)
+    }
+
+    pub fn stats(&self) -> UserStats {
+        UserStats {
+            topics_created: self.topics_count,
+            replies_posted: self.replies_count,
+            likes_received: self.likes_received,
+            reputation: self.reputation,
+            join_date: self.created_at,
+            last_seen: self.last_seen_at,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Like {
     pub id: Uuid,
@@ -105,6 +140,10 @@ pub struct CreatePost {
     pub author: String,
     pub published: Option<bool>,
     pub tags: Option<Vec<String>>,
+    pub lang: Option<String>,
+    /// An existing post this one translates; both end up in the same
+    /// translation group.
+    pub translation_of: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -124,6 +163,24 @@ pub struct User {
     pub password_hash: String,
     pub role: UserRole,
     pub created_at: DateTime<Utc>,
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    pub suspended_by: Option<Uuid>,
+    pub silenced_until: Option<DateTime<Utc>>,
+    pub silence_reason: Option<String>,
+    pub silenced_by: Option<Uuid>,
+}
+
+impl User {
+    /// Suspended users can't sign in or do anything with their token.
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_until.is_some_and(|until| until > Utc::now())
+    }
+
+    /// Silenced users can read but not post.
+    pub fn is_silenced(&self) -> bool {
+        self.silenced_until.is_some_and(|until| until > Utc::now())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
@@ -173,7 +230,7 @@ impl From<User> for UserInfo {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub username: String,
@@ -189,6 +246,8 @@ pub struct CreateCategory {
     pub description: String,
     pub color: String,
     pub icon: Option<String>,
+    #[serde(default)]
+    pub min_trust_level: i16,
 }
 
 #[derive(Debug, Deserialize)]
@@ -214,6 +273,64 @@ pub struct UpdateTopic {
     pub is_locked: Option<bool>,
 }
 
+/// Where a pinned topic stays on top: its category, or also the list of
+/// all topics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinScope {
+    #[default]
+    Category,
+    Global,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PinTopic {
+    #[serde(default)]
+    pub scope: PinScope,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LockTopic {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveTopic {
+    pub category_id: Uuid,
+}
+
+/// Merges the topic in the path into `into`.
+#[derive(Debug, Deserialize)]
+pub struct MergeTopic {
+    pub into: Uuid,
+}
+
+/// Splits `reply_ids` off into a new topic, in the same category unless
+/// `category_id` is given.
+#[derive(Debug, Deserialize)]
+pub struct SplitTopic {
+    pub reply_ids: Vec<Uuid>,
+    pub title: String,
+    pub category_id: Option<Uuid>,
+}
+
+/// What came of a merge.
+#[derive(Debug)]
+pub enum MergeOutcome {
+    Merged,
+    /// Both topics discuss a page, and a page can only have one discussion.
+    BothDiscussions,
+}
+
+/// What came of a split.
+#[derive(Debug)]
+pub enum SplitOutcome {
+    Split(Box<Topic>),
+    /// None of the replies could move; the opening post stays.
+    NothingToMove,
+    UnknownCategory,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateReply {
     pub content: String,
@@ -225,6 +342,7 @@ pub struct UpdateReply {
     pub content: String,
 }
 
+/// Fields left out are unchanged; an empty string clears a field.
 #[derive(Debug, Deserialize)]
 pub struct UpdateUserProfile {
     pub display_name: Option<String>,
@@ -233,6 +351,32 @@ pub struct UpdateUserProfile {
     pub website: Option<String>,
 }
 
+/// A topic listed on its author's profile.
+#[derive(Debug, Serialize)]
+pub struct UserTopic {
+    pub id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub category_name: String,
+    pub replies_count: i64,
+    pub likes_count: i64,
+    pub is_solved: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A reply listed on its author's profile, with the topic it is in.
+#[derive(Debug, Serialize)]
+pub struct UserReply {
+    pub id: Uuid,
+    pub topic_title: String,
+    pub topic_slug: String,
+    /// The start of the reply's content.
+    pub excerpt: String,
+    pub is_solution: bool,
+    pub likes_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
 // Extended response models with related data
 
 #[derive(Debug, Serialize)]
@@ -250,6 +394,25 @@ pub struct ReplyWithDetails {
     pub reply: Reply,
     pub user: UserInfo,
     pub reply_to_user: Option<UserInfo>,
+    /// Whether the user viewing the reply has liked it.
+    pub liked_by_me: bool,
+}
+
+/// The state of a like after liking or unliking.
+#[derive(Debug, Serialize)]
+pub struct LikeStatus {
+    pub liked: bool,
+    pub count: i64,
+}
+
+/// A user who liked a reply.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Liker {
+    pub user_id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub liked_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize)]
@@ -259,7 +422,7 @@ pub struct CategoryWithStats {
     pub latest_topic: Option<TopicWithDetails>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UserStats {
     pub topics_created: i64,
     pub replies_posted: i64,
@@ -269,10 +432,872 @@ pub struct UserStats {
     pub last_seen: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ForumStats {
     pub total_topics: i64,
     pub total_replies: i64,
     pub total_users: i64,
     pub active_users_today: i64,
-}
\ No newline at end of file
+    /// Members seen in the last 7 days, today included.
+    pub active_users_week: i64,
+    /// Replies posted each day, oldest first, with days without any included.
+    pub posts_per_day: Vec<DailyCount>,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyCount {
+    pub day: NaiveDate,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub user_id: Uuid,
+    pub username: String,
+    /// Reputation earned, solutions accepted or likes received in the window.
+    pub score: i64,
+    pub stats: UserStats,
+}
+
+// Trash Models
+
+/// A deleted post, topic or reply waiting in the trash.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TrashItem {
+    pub content_type: String,
+    pub id: Uuid,
+    /// The topic of a topic or reply.
+    pub topic_id: Option<Uuid>,
+    /// The title of the post or topic, or of a reply's topic.
+    pub title: String,
+    pub excerpt: String,
+    pub author: String,
+    pub deleted_at: DateTime<Utc>,
+    pub deleted_by: Option<Uuid>,
+    pub deleted_by_username: Option<String>,
+    /// Hidden automatically by reports; it isn't purged until a moderator
+    /// hides it for good.
+    pub hidden_by_reports: bool,
+    /// When the item will be permanently deleted.
+    pub purge_at: DateTime<Utc>,
+}
+
+// Report Models
+
+/// What a report is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportTarget {
+    Post,
+    Topic,
+    Reply,
+    User,
+}
+
+impl ReportTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTarget::Post => "post",
+            ReportTarget::Topic => "topic",
+            ReportTarget::Reply => "reply",
+            ReportTarget::User => "user",
+        }
+    }
+
+    pub fn parse(content_type: &str) -> Option<Self> {
+        match content_type {
+            "post" => Some(ReportTarget::Post),
+            "topic" => Some(ReportTarget::Topic),
+            "reply" => Some(ReportTarget::Reply),
+            "user" => Some(ReportTarget::User),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+    OffTopic,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Abuse => "abuse",
+            ReportReason::OffTopic => "off_topic",
+            ReportReason::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Report {
+    pub id: Uuid,
+    pub reporter_id: Uuid,
+    pub content_type: String,
+    pub content_id: Uuid,
+    pub reason: String,
+    pub details: Option<String>,
+    /// `open`, `dismissed` or `actioned`.
+    pub status: String,
+    pub resolution: Option<String>,
+    pub resolved_by: Option<Uuid>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateReport {
+    pub content_type: ReportTarget,
+    pub content_id: Uuid,
+    pub reason: ReportReason,
+    pub details: Option<String>,
+}
+
+/// The open reports about one post, topic, reply or user, as shown in the
+/// moderation queue.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReportQueueItem {
+    pub content_type: String,
+    pub content_id: Uuid,
+    /// The open reports, oldest first; resolving any of them resolves all.
+    pub report_ids: Vec<Uuid>,
+    pub report_count: i64,
+    pub reasons: Vec<String>,
+    pub details: Vec<String>,
+    /// The title of the post or topic, a reply's topic, or the username.
+    pub title: Option<String>,
+    pub excerpt: Option<String>,
+    /// The member responsible: the author of a topic or reply, or the
+    /// reported user. Posts have no forum author.
+    pub user_id: Option<Uuid>,
+    pub author: Option<String>,
+    pub url: Option<String>,
+    /// Whether the content is in the trash, by a moderator or because it
+    /// passed the report threshold.
+    pub hidden: bool,
+    pub first_reported_at: DateTime<Utc>,
+    pub last_reported_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    /// Closes the reports and restores content hidden by the threshold.
+    Dismiss,
+    /// Moves the content to the trash.
+    Hide,
+    /// Warns the author.
+    Warn,
+    /// Suspends the author for `days`.
+    Suspend,
+}
+
+impl ModerationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationAction::Dismiss => "dismiss",
+            ModerationAction::Hide => "hide",
+            ModerationAction::Warn => "warn",
+            ModerationAction::Suspend => "suspend",
+        }
+    }
+}
+
+/// Resolves a report, and every other open report on the same target.
+#[derive(Debug, Deserialize)]
+pub struct ResolveReport {
+    pub action: ModerationAction,
+    /// The warning or suspension reason shown to the member; required for
+    /// both.
+    pub message: Option<String>,
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UserWarning {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub moderator_id: Option<Uuid>,
+    pub report_id: Option<Uuid>,
+    pub message: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// Restriction Models
+
+/// Suspends or silences a user for `days`.
+#[derive(Debug, Deserialize)]
+pub struct RestrictUser {
+    pub days: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BanType {
+    /// An IP address, or a range in CIDR notation.
+    Ip,
+    /// An email domain and its subdomains.
+    EmailDomain,
+}
+
+impl BanType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BanType::Ip => "ip",
+            BanType::EmailDomain => "email_domain",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Ban {
+    pub id: Uuid,
+    pub ban_type: String,
+    pub value: String,
+    pub reason: String,
+    pub banned_by: Option<Uuid>,
+    /// Permanent when missing.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Bans an IP address or email domain, for `days` or for good.
+#[derive(Debug, Deserialize)]
+pub struct CreateBan {
+    pub ban_type: BanType,
+    pub value: String,
+    pub reason: String,
+    pub days: Option<i64>,
+}
+
+// Audit Models
+
+/// A privileged change, as recorded in the append-only audit log.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    /// Kept after the user is deleted, along with their username.
+    pub actor_id: Option<Uuid>,
+    pub actor_username: String,
+    /// `<target type>.<verb>`, such as `topic.lock` or `user.role`.
+    pub action: String,
+    pub target_type: String,
+    /// A UUID, or a path for wiki pages.
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct NewAuditEntry {
+    pub actor_id: Option<Uuid>,
+    pub actor_username: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+}
+
+/// Filters for the audit log. `action` matches an action exactly, or all
+/// actions on a target type (`topic` matches `topic.lock`).
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserRole {
+    pub role: UserRole,
+}
+
+/// Pins a member at a trust level, or with `null` goes back to the level
+/// they have earned.
+#[derive(Debug, Deserialize)]
+pub struct SetTrustLevel {
+    pub level: Option<i16>,
+}
+
+// Reputation Models
+
+/// One entry in a member's reputation ledger; their reputation is the sum
+/// of the points.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReputationEvent {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// `like_received`, `solution_accepted`, `topic_featured` or `penalty`.
+    pub event_type: String,
+    pub points: i32,
+    /// The `reply` or `topic` the event is about, if any.
+    pub source_type: Option<String>,
+    pub source_id: Option<Uuid>,
+    /// Who liked, featured or penalized.
+    pub actor_id: Option<Uuid>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Takes `points` (a positive number) off a member's reputation.
+#[derive(Debug, Deserialize)]
+pub struct CreatePenalty {
+    pub points: i32,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UserBadge {
+    pub user_id: Uuid,
+    pub badge: String,
+    pub awarded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct BadgeHolder {
+    pub user_id: Uuid,
+    pub username: String,
+    pub awarded_at: DateTime<Utc>,
+}
+
+// Attachment Models
+
+/// What an attachment is. Challenge files are the binaries, captures and
+/// firmware a write-up is about: they are kept byte for byte, whatever their
+/// type, shown with their hashes and always downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentKind {
+    #[default]
+    File,
+    Challenge,
+}
+
+impl AttachmentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentKind::File => "file",
+            AttachmentKind::Challenge => "challenge",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "file" => Some(AttachmentKind::File),
+            "challenge" => Some(AttachmentKind::Challenge),
+            _ => None,
+        }
+    }
+}
+
+/// An uploaded file. The content is in the blob store under `sha256`.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub sha256: String,
+    pub filename: String,
+    /// `file` or `challenge`.
+    pub kind: String,
+    /// Detected from the content, not taken from the upload.
+    pub mime_type: String,
+    pub size: i64,
+    /// Unset for content uploaded before hashes were recorded.
+    pub md5: Option<String>,
+    /// What the content is, in the style of `file(1)`.
+    pub description: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub post_id: Option<Uuid>,
+    pub reply_id: Option<Uuid>,
+    /// Set for images.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Where the file is served.
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A smaller version of an image attachment.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AttachmentVariant {
+    #[serde(skip)]
+    pub attachment_id: Uuid,
+    pub variant: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub width: i32,
+    pub height: i32,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentWithVariants {
+    #[serde(flatten)]
+    pub attachment: Attachment,
+    /// Smallest first; empty unless the attachment is a large image.
+    pub variants: Vec<AttachmentVariant>,
+}
+
+/// Uploaded content to record, with the variants made of it.
+#[derive(Debug)]
+pub struct NewBlob {
+    pub sha256: String,
+    pub md5: String,
+    pub size: i64,
+    pub mime_type: String,
+    pub description: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub archive: Option<ArchiveListing>,
+    pub variants: Vec<NewBlobVariant>,
+}
+
+#[derive(Debug)]
+pub struct NewBlobVariant {
+    pub variant: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// A file in an uploaded archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub size: i64,
+    pub is_dir: bool,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveListing {
+    pub entries: Vec<ArchiveEntry>,
+    /// Whether there are more entries than are listed.
+    pub truncated: bool,
+}
+
+/// A challenge file as shown on its post.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ChallengeFile {
+    pub id: Uuid,
+    pub filename: String,
+    pub sha256: String,
+    pub md5: Option<String>,
+    pub size: i64,
+    pub description: Option<String>,
+    /// The files inside, for zip and tar archives.
+    pub archive: Option<sqlx::types::Json<ArchiveListing>>,
+    pub url: String,
+}
+
+impl ChallengeFile {
+    /// The size in the largest whole unit, such as `1.4 MB`.
+    pub fn size_label(&self) -> String {
+        const KB: i64 = 1024;
+        match self.size {
+            size if size < KB => format!("{} bytes", size),
+            size if size < KB * KB => format!("{:.1} KB", size as f64 / KB as f64),
+            size => format!("{:.1} MB", size as f64 / (KB * KB) as f64),
+        }
+    }
+}
+
+/// What an attachment belongs to: a post, a reply, or neither yet.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct AttachTo {
+    pub post_id: Option<Uuid>,
+    pub reply_id: Option<Uuid>,
+}
+
+// Notification Models
+
+/// What a notification is about. Members can turn each kind off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationType {
+    /// Someone mentioned the member.
+    Mention,
+    /// Someone replied to the member's topic.
+    Reply,
+    /// Someone replied to one of the member's replies.
+    Quote,
+    /// Members liked the member's topic or reply.
+    Like,
+    /// The member's reply was accepted as the solution.
+    Solution,
+    /// A moderator acted on the member or their content.
+    Moderation,
+}
+
+impl NotificationType {
+    pub const ALL: [NotificationType; 6] = [
+        NotificationType::Mention,
+        NotificationType::Reply,
+        NotificationType::Quote,
+        NotificationType::Like,
+        NotificationType::Solution,
+        NotificationType::Moderation,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationType::Mention => "mention",
+            NotificationType::Reply => "reply",
+            NotificationType::Quote => "quote",
+            NotificationType::Like => "like",
+            NotificationType::Solution => "solution",
+            NotificationType::Moderation => "moderation",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        NotificationType::ALL.into_iter().find(|t| t.as_str() == kind)
+    }
+}
+
+/// Something that happened for a member to see.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    /// `mention`, `reply`, `quote`, `like`, `solution` or `moderation`.
+    pub notification_type: String,
+    pub actor_id: Option<Uuid>,
+    pub actor_username: Option<String>,
+    pub topic_id: Option<Uuid>,
+    pub topic_title: Option<String>,
+    pub reply_id: Option<Uuid>,
+    /// Where it happened.
+    pub url: Option<String>,
+    /// How many likes it stands for; the actor is the latest to like.
+    pub count: i32,
+    /// What a moderator did.
+    pub message: Option<String>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnreadNotifications {
+    pub unread: i64,
+}
+
+/// Whether a member gets one kind of notification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPreference {
+    pub notification_type: NotificationType,
+    pub enabled: bool,
+}
+
+// Discussion Models
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DiscussionLink {
+    pub id: Uuid,
+    pub content_type: String,
+    pub content_id: Uuid,
+    pub topic_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Identifies the page being discussed: a post by `id`, or a wiki page by
+/// `lang` and `path`.
+#[derive(Debug, Deserialize)]
+pub struct DiscussionTarget {
+    pub content_type: String,
+    pub id: Option<Uuid>,
+    pub lang: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Discussion {
+    pub topic: Topic,
+    pub url: String,
+    /// Replies after the opening post.
+    pub replies_count: i64,
+    pub latest_replies: Vec<ReplyWithDetails>,
+}
+
+// Wiki Models
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WikiPage {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub path: String,
+    pub lang: String,
+    pub translation_group: Uuid,
+    pub title: String,
+    pub content: String,
+    pub source_path: Option<String>,
+    pub nav_order: i32,
+    pub revision: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WikiRevision {
+    pub id: Uuid,
+    pub page_id: Uuid,
+    pub revision: i32,
+    pub title: String,
+    pub content: String,
+    pub edited_by: Option<Uuid>,
+    pub edit_summary: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WikiRevisionSummary {
+    pub id: Uuid,
+    pub revision: i32,
+    pub title: String,
+    pub edited_by: Option<Uuid>,
+    pub editor_username: Option<String>,
+    pub edit_summary: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct UpsertWikiPage {
+    pub parent_id: Option<Uuid>,
+    pub path: String,
+    pub lang: String,
+    pub title: String,
+    pub content: String,
+    pub source_path: Option<String>,
+    pub nav_order: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWikiPage {
+    pub path: String,
+    pub lang: Option<String>,
+    pub title: String,
+    pub content: String,
+    pub edit_summary: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWikiPage {
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub edit_summary: Option<String>,
+    /// The revision the editor started from; the save is rejected if the
+    /// page has moved on since.
+    pub base_revision: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RollbackWikiPage {
+    pub revision: i32,
+    pub base_revision: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportWikiRequest {
+    pub mkdocs_path: String,
+    pub path_prefix: Option<String>,
+    pub asset_base_url: Option<String>,
+    pub lang: Option<String>,
+    /// Overwrite pages edited in the app since they were last imported.
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WikiBreadcrumb {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WikiConflict {
+    pub message: String,
+    pub current_revision: i32,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WikiImportReport {
+    pub lang: String,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub missing_files: Vec<String>,
+    /// Pages left alone because they were edited in the app since they
+    /// were last imported, or while the import ran.
+    pub conflicts: Vec<String>,
+}
+
+// Translation Models
+
+/// Another language version of the page being viewed, used for hreflang
+/// links and the language switcher.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationLink {
+    pub lang: String,
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TranslationRow {
+    pub content_type: String,
+    pub translation_group: Uuid,
+    pub lang: String,
+    pub title: String,
+    pub url: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TranslationReportEntry {
+    pub content_type: String,
+    pub translation_group: Uuid,
+    pub title: String,
+    pub versions: Vec<TranslationRow>,
+    pub missing: Vec<String>,
+    /// Languages whose version is older than the most recently updated one.
+    pub outdated: Vec<String>,
+}
+
+// Search Models
+
+/// `type` is a comma-separated list of result types, e.g. `topic,reply`.
+/// `category` (forum category name) and `tag` (post tag) narrow the results.
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    #[serde(rename = "type")]
+    pub types: Option<String>,
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    pub page: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SearchHit {
+    pub result_type: String,
+    pub id: Uuid,
+    pub title: String,
+    pub url: String,
+    /// `ts_headline` output with matches wrapped in sentinel characters.
+    pub headline: String,
+    pub rank: f32,
+    pub created_at: DateTime<Utc>,
+    /// Number of hits across all pages.
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    #[serde(rename = "type")]
+    pub result_type: String,
+    pub id: Uuid,
+    pub title: String,
+    pub url: String,
+    /// Plain-text excerpt around the matches.
+    pub snippet: String,
+    /// The same excerpt, HTML-escaped, with matches wrapped in `<mark>`.
+    pub highlight: String,
+    pub rank: f32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub query: String,
+    pub types: Vec<String>,
+    pub results: Vec<SearchResult>,
+    pub total: i64,
+    pub page: i64,
+    pub limit: i64,
+    pub has_next: bool,
+    /// Result counts per category and tag; only the embedded index computes
+    /// them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<SearchFacets>,
+    /// A spelling correction, offered when nothing matched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchFacets {
+    pub categories: Vec<FacetCount>,
+    pub tags: Vec<FacetCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: u64,
+}
+
+/// A published post, topic, reply, wiki page or user as stored in the
+/// embedded search index.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SearchDocument {
+    pub doc_type: String,
+    pub id: Uuid,
+    /// The topic a reply belongs to, or the topic itself.
+    pub topic_id: Option<Uuid>,
+    pub title: String,
+    pub body: String,
+    pub url: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A topic, post, tag or user offered while the search query is typed.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Suggestion {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+    pub url: String,
+    /// Popularity used to order suggestions with equally good matches.
+    #[serde(skip)]
+    pub weight: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestQuery {
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuggestResponse {
+    pub query: String,
+    pub suggestions: Vec<Suggestion>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_you_mean: Option<String>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct VocabularyWord {
+    pub word: String,
+    pub count: i64,
+}
@@ -0,0 +1,145 @@
+use crate::{
+    models::{Claims, NotificationPreference, NotificationType, UnreadNotifications},
+    templates::NotificationsTemplate,
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use askama::Template;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// How many notifications are listed.
+const NOTIFICATION_LIMIT: i64 = 50;
+
+// The page loads the signed-in member's notifications from the API
+pub async fn notifications_page() -> impl IntoResponse {
+    let template = NotificationsTemplate {};
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+}
+
+// Notifications API
+
+pub async fn api_list_notifications(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    match state.db.list_notifications(user_id, NOTIFICATION_LIMIT).await {
+        Ok(notifications) => Json(notifications).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_unread_count(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    unread_count(&state, user_id).await
+}
+
+// Both return the unread count left, for the badge
+pub async fn api_mark_read(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(notification_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    match state.db.mark_notification_read(notification_id, user_id).await {
+        Ok(true) => unread_count(&state, user_id).await,
+        Ok(false) => (StatusCode::NOT_FOUND, "Notification not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update notification").into_response(),
+    }
+}
+
+pub async fn api_mark_all_read(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    match state.db.mark_all_notifications_read(user_id).await {
+        Ok(()) => unread_count(&state, user_id).await,
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update notifications").into_response(),
+    }
+}
+
+async fn unread_count(state: &AppState, user_id: Uuid) -> Response {
+    match state.db.count_unread_notifications(user_id).await {
+        Ok(unread) => Json(UnreadNotifications { unread }).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+// Preferences API
+// Every kind of notification is listed, on unless the member turned it off
+
+pub async fn api_get_preferences(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    preferences(&state, user_id).await
+}
+
+// Kinds left out of the request stay as they were
+pub async fn api_update_preferences(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(update): Json<Vec<NotificationPreference>>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    // The last setting for a kind wins
+    let changes: HashMap<NotificationType, bool> =
+        update.into_iter().map(|preference| (preference.notification_type, preference.enabled)).collect();
+    let changes: Vec<(NotificationType, bool)> = changes.into_iter().collect();
+
+    match state.db.set_notification_preferences(user_id, &changes).await {
+        Ok(()) => preferences(&state, user_id).await,
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update preferences").into_response(),
+    }
+}
+
+async fn preferences(state: &AppState, user_id: Uuid) -> Response {
+    let disabled: Vec<NotificationType> = match state.db.disabled_notification_types(user_id).await {
+        Ok(disabled) => disabled.iter().filter_map(|kind| NotificationType::parse(kind)).collect(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let preferences: Vec<NotificationPreference> = NotificationType::ALL
+        .into_iter()
+        .map(|notification_type| NotificationPreference {
+            notification_type,
+            enabled: !disabled.contains(&notification_type),
+        })
+        .collect();
+    Json(preferences).into_response()
+}
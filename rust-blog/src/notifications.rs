@@ -0,0 +1,86 @@
+//! Notifications about replies to a member's topics and replies, likes,
+//! accepted solutions and what moderators did to them or their content;
+//! mentions are sent from `mentions`. Failing to notify is logged and
+//! doesn't fail the action itself.
+
+use crate::models::{Claims, NotificationType, Reply, Topic};
+use crate::AppState;
+use uuid::Uuid;
+
+async fn send(
+    state: &AppState,
+    kind: NotificationType,
+    actor_id: Uuid,
+    topic_id: Option<Uuid>,
+    reply_id: Option<Uuid>,
+    message: Option<&str>,
+    user_ids: &[Uuid],
+) {
+    if let Err(e) = state.db.create_notifications(kind, actor_id, topic_id, reply_id, message, user_ids).await {
+        tracing::warn!("Failed to send {} notifications: {}", kind.as_str(), e);
+    }
+}
+
+/// Notifies the topic's author of a new reply, and the author of the reply
+/// it answers. Neither is notified of their own reply, or again if they
+/// were just mentioned in it.
+pub async fn replied(state: &AppState, topic: &Topic, reply: &Reply, mentioned: &[Uuid]) {
+    let notify = |user_id: Uuid| user_id != reply.user_id && !mentioned.contains(&user_id);
+
+    let answered = match reply.reply_to_id {
+        Some(reply_to_id) => match state.db.get_reply(reply_to_id).await {
+            Ok(answered) => answered.map(|answered| answered.user_id),
+            Err(e) => {
+                tracing::warn!("Failed to look up the reply {} answers: {}", reply.id, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    if let Some(user_id) = answered.filter(|user_id| notify(*user_id)) {
+        send(state, NotificationType::Quote, reply.user_id, Some(topic.id), Some(reply.id), None, &[user_id]).await;
+    }
+    // A topic author answered directly gets the one notification
+    if answered != Some(topic.user_id) && notify(topic.user_id) {
+        send(state, NotificationType::Reply, reply.user_id, Some(topic.id), Some(reply.id), None, &[topic.user_id]).await;
+    }
+}
+
+/// Notifies `author_id` of a new like on their topic, or on their reply
+/// when `reply_id` is set.
+pub async fn liked(state: &AppState, author_id: Uuid, actor_id: Uuid, topic_id: Uuid, reply_id: Option<Uuid>) {
+    if let Err(e) = state.db.create_like_notification(author_id, actor_id, topic_id, reply_id).await {
+        tracing::warn!("Failed to send like notification: {}", e);
+    }
+}
+
+/// Notifies a reply's author that it was accepted as the solution, unless
+/// they accepted it themselves.
+pub async fn solution_accepted(state: &AppState, reply: &Reply, actor_id: Uuid) {
+    if reply.user_id == actor_id {
+        return;
+    }
+    let (topic_id, reply_id) = (Some(reply.topic_id), Some(reply.id));
+    send(state, NotificationType::Solution, actor_id, topic_id, reply_id, None, &[reply.user_id]).await;
+}
+
+/// Tells a member what a moderator did, linking to the topic or reply it
+/// was done to when that can still be read. Moderators acting on their own
+/// content aren't told.
+pub async fn moderated(
+    state: &AppState,
+    claims: &Claims,
+    user_id: Uuid,
+    topic_id: Option<Uuid>,
+    reply_id: Option<Uuid>,
+    message: &str,
+) {
+    let Ok(moderator_id) = claims.sub.parse::<Uuid>() else {
+        return;
+    };
+    if user_id == moderator_id {
+        return;
+    }
+    send(state, NotificationType::Moderation, moderator_id, topic_id, reply_id, Some(message), &[user_id]).await;
+}
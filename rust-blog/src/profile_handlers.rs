@@ -0,0 +1,282 @@
+use crate::{
+    auth::is_moderator,
+    avatars::{self, MAX_AVATAR_BYTES},
+    badges,
+    models::{Claims, UpdateUserProfile},
+    templates::ProfileTemplate,
+    trust::{self, Capability},
+    AppState,
+};
+use axum::{
+    extract::{Multipart, Path, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Redirect},
+    Extension, Json,
+};
+use askama::Template;
+use uuid::Uuid;
+
+pub const MAX_DISPLAY_NAME_CHARS: usize = 100;
+pub const MAX_BIO_CHARS: usize = 2000;
+pub const MAX_LOCATION_CHARS: usize = 100;
+pub const MAX_WEBSITE_CHARS: usize = 500;
+/// How many topics and replies a profile page lists.
+const PROFILE_ACTIVITY_LIMIT: i64 = 10;
+
+fn user_id_of(claims: &Claims) -> Result<Uuid, (StatusCode, &'static str)> {
+    claims.sub.parse::<Uuid>().map_err(|_| (StatusCode::BAD_REQUEST, "Invalid user ID"))
+}
+
+fn too_long(field: &str, max: usize) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, format!("{} can be at most {} characters", field, max))
+}
+
+/// Trims the fields and checks their lengths and the website's scheme.
+fn validate(update: UpdateUserProfile) -> Result<UpdateUserProfile, (StatusCode, String)> {
+    let trim = |field: Option<String>| field.map(|value| value.trim().to_string());
+    let update = UpdateUserProfile {
+        display_name: trim(update.display_name),
+        bio: trim(update.bio),
+        location: trim(update.location),
+        website: trim(update.website),
+    };
+
+    if let Some(display_name) = &update.display_name {
+        if display_name.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Display name can't be empty".to_string()));
+        }
+        if display_name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+            return Err(too_long("Display name", MAX_DISPLAY_NAME_CHARS));
+        }
+    }
+    if update.bio.as_ref().is_some_and(|bio| bio.chars().count() > MAX_BIO_CHARS) {
+        return Err(too_long("Bio", MAX_BIO_CHARS));
+    }
+    if update.location.as_ref().is_some_and(|location| location.chars().count() > MAX_LOCATION_CHARS) {
+        return Err(too_long("Location", MAX_LOCATION_CHARS));
+    }
+    if let Some(website) = &update.website {
+        if website.chars().count() > MAX_WEBSITE_CHARS {
+            return Err(too_long("Website", MAX_WEBSITE_CHARS));
+        }
+        let is_http = website.starts_with("https://") || website.starts_with("http://");
+        if !website.is_empty() && (!is_http || website.contains(char::is_whitespace)) {
+            return Err((StatusCode::BAD_REQUEST, "Website must be an http:// or https:// URL".to_string()));
+        }
+    }
+    Ok(update)
+}
+
+// Own profile API
+
+pub async fn api_get_my_profile(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match user_id_of(&claims) {
+        Ok(id) => id,
+        Err(error) => return error.into_response(),
+    };
+
+    match state.db.get_user_profile(user_id).await {
+        Ok(Some(profile)) => Json(profile).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "User profile not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_update_my_profile(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(update): Json<UpdateUserProfile>,
+) -> impl IntoResponse {
+    let user_id = match user_id_of(&claims) {
+        Ok(id) => id,
+        Err(error) => return error.into_response(),
+    };
+    let update = match validate(update) {
+        Ok(update) => update,
+        Err(error) => return error.into_response(),
+    };
+
+    // A profile shouldn't be a way around the trust levels for posting
+    // links and images
+    if update.website.as_ref().is_some_and(|website| !website.is_empty()) {
+        if let Err(response) = trust::require(&state, &claims, Capability::PostLinks).await {
+            return response;
+        }
+    }
+    if let Some(bio) = &update.bio {
+        if let Err(response) = trust::check_content(&state, &claims, bio).await {
+            return response;
+        }
+    }
+
+    match state.db.update_user_profile(user_id, update).await {
+        Ok(Some(profile)) => Json(profile).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "User profile not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update profile").into_response(),
+    }
+}
+
+// Avatars
+// Uploaded as the multipart field "avatar"
+
+pub async fn api_upload_avatar(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    mut multipart: Multipart,
+) -> impl IntoResponse {
+    let user_id = match user_id_of(&claims) {
+        Ok(id) => id,
+        Err(error) => return error.into_response(),
+    };
+    let too_large = format!("Avatars can be at most {} MB", MAX_AVATAR_BYTES / (1024 * 1024));
+
+    let mut upload = None;
+    loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("avatar") => match field.bytes().await {
+                Ok(bytes) => upload = Some(bytes),
+                Err(_) => return (StatusCode::PAYLOAD_TOO_LARGE, too_large).into_response(),
+            },
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(_) => return (StatusCode::BAD_REQUEST, "Invalid multipart body").into_response(),
+        }
+    }
+    let Some(upload) = upload else {
+        return (StatusCode::BAD_REQUEST, "No avatar field in the upload").into_response();
+    };
+    if upload.len() > MAX_AVATAR_BYTES {
+        return (StatusCode::PAYLOAD_TOO_LARGE, too_large).into_response();
+    }
+
+    // Decoding and resizing is CPU-bound
+    let images = match tokio::task::spawn_blocking(move || avatars::process(&upload)).await {
+        Ok(Ok(images)) => images,
+        Ok(Err(message)) => return (StatusCode::UNPROCESSABLE_ENTITY, message).into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to process avatar").into_response(),
+    };
+
+    let previous = match state.db.get_user_profile(user_id).await {
+        Ok(profile) => profile.and_then(|profile| profile.avatar_url),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let avatar_url = match avatars::save(state.blobs.as_ref(), user_id, images).await {
+        Ok(url) => url,
+        Err(e) => {
+            tracing::warn!("Failed to save avatar: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save avatar").into_response();
+        }
+    };
+    if state.db.set_avatar_url(user_id, Some(&avatar_url)).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to save avatar").into_response();
+    }
+    if let Some(previous) = previous {
+        if let Err(e) = avatars::remove(state.blobs.as_ref(), user_id, &previous).await {
+            tracing::warn!("Failed to remove old avatar: {}", e);
+        }
+    }
+
+    let sizes: serde_json::Map<String, serde_json::Value> = avatars::AVATAR_SIZES
+        .iter()
+        .map(|&size| (size.to_string(), avatars::sized_url(Some(&avatar_url), size).into()))
+        .collect();
+    (StatusCode::CREATED, Json(serde_json::json!({ "avatar_url": avatar_url, "sizes": sizes }))).into_response()
+}
+
+pub async fn api_delete_avatar(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match user_id_of(&claims) {
+        Ok(id) => id,
+        Err(error) => return error.into_response(),
+    };
+
+    let previous = match state.db.get_user_profile(user_id).await {
+        Ok(profile) => profile.and_then(|profile| profile.avatar_url),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if state.db.set_avatar_url(user_id, None).await.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to remove avatar").into_response();
+    }
+    if let Some(previous) = previous {
+        if let Err(e) = avatars::remove(state.blobs.as_ref(), user_id, &previous).await {
+            tracing::warn!("Failed to remove avatar files: {}", e);
+        }
+    }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// An avatar file from the blob store. Each upload gets new file names, so
+/// they can be cached forever.
+pub async fn serve_avatar(
+    State(state): State<AppState>,
+    Path((user_id, file)): Path<(Uuid, String)>,
+) -> impl IntoResponse {
+    let Some(key) = avatars::key_for(user_id, &file) else {
+        return (StatusCode::NOT_FOUND, "Avatar not found").into_response();
+    };
+    match state.blobs.get(&key).await {
+        Ok(Some(png)) => (
+            [
+                (header::CONTENT_TYPE, "image/png"),
+                (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            ],
+            png,
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Avatar not found").into_response(),
+        Err(e) => {
+            tracing::warn!("Failed to read avatar {}: {}", key, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read avatar").into_response()
+        }
+    }
+}
+
+// Profile pages
+
+pub async fn profile_page(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let user = match state.db.get_user_by_username(&username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let profile = match state.db.get_user_profile(user.id).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User profile not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let activity = tokio::try_join!(
+        state.db.list_user_badges(user.id),
+        state.db.list_recent_topics_by_user(user.id, PROFILE_ACTIVITY_LIMIT),
+        state.db.list_recent_replies_by_user(user.id, PROFILE_ACTIVITY_LIMIT),
+    );
+    let (awarded, topics, replies) = match activity {
+        Ok(activity) => activity,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let template = ProfileTemplate {
+        username: user.username,
+        is_moderator: is_moderator(&user.role),
+        stats: profile.stats(),
+        trust_level: trust::level_name(profile.effective_trust_level()),
+        profile,
+        badges: badges::awarded(awarded),
+        topics,
+        replies,
+    };
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+}
+
+/// Profiles used to be linked at /forum/u/:username.
+pub async fn forum_profile_redirect(Path(username): Path<String>) -> impl IntoResponse {
+    Redirect::permanent(&format!("/u/{}", username))
+}
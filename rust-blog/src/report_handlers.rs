@@ -0,0 +1,262 @@
+use crate::{
+    audit::{self, AuditEvent, ClientIp},
+    auth::is_moderator,
+    models::{Claims, CreateReport, ModerationAction, ReportTarget, ResolveReport},
+    notifications,
+    restrictions::{self, MAX_RESTRICTION_DAYS},
+    templates::ReportsTemplate,
+    trust::{self, Capability},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Extension, Json,
+};
+use askama::Template;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// How many members have to report a post, topic or reply before it is
+/// hidden until a moderator reviews it.
+pub const DEFAULT_HIDE_THRESHOLD: i64 = 3;
+/// How long a suspension lasts when the moderator doesn't say.
+pub const DEFAULT_SUSPENSION_DAYS: i64 = 7;
+const MAX_DETAILS_LENGTH: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct QueueQuery {
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+/// `REPORT_HIDE_THRESHOLD` overrides the default; 0 turns hiding off.
+pub fn hide_threshold() -> i64 {
+    std::env::var("REPORT_HIDE_THRESHOLD")
+        .ok()
+        .and_then(|threshold| threshold.parse().ok())
+        .filter(|threshold| *threshold >= 0)
+        .unwrap_or(DEFAULT_HIDE_THRESHOLD)
+}
+
+pub async fn reports_page() -> impl IntoResponse {
+    let template = ReportsTemplate {};
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string()))
+}
+
+// Members can report anything but their own content
+pub async fn api_create_report(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(mut report): Json<CreateReport>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(response) = trust::require(&state, &claims, Capability::Flag).await {
+        return response;
+    }
+    if let Err(response) = restrictions::check_silence(&state, &claims).await {
+        return response;
+    }
+
+    report.details = report.details.map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+    if report.details.as_ref().is_some_and(|d| d.chars().count() > MAX_DETAILS_LENGTH) {
+        return (StatusCode::BAD_REQUEST, "Details can be at most 1000 characters").into_response();
+    }
+
+    // The member behind the target, or None if it doesn't exist
+    let author = match report.content_type {
+        ReportTarget::Post => state.db.get_post(report.content_id).await.map(|p| p.map(|_| None)),
+        ReportTarget::Topic => state.db.get_topic(report.content_id).await.map(|t| t.map(|t| Some(t.user_id))),
+        ReportTarget::Reply => state.db.get_reply(report.content_id).await.map(|r| r.map(|r| Some(r.user_id))),
+        ReportTarget::User => state.db.get_user_by_id(report.content_id).await.map(|u| u.map(|u| Some(u.id))),
+    };
+    let author = match author {
+        Ok(Some(author)) if author == Some(user_id) => {
+            return (StatusCode::BAD_REQUEST, "You can't report yourself").into_response();
+        }
+        Ok(Some(author)) => author,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Nothing to report").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let target = report.content_type;
+    let content_id = report.content_id;
+    let created = match state.db.create_report(user_id, report).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return (StatusCode::CONFLICT, "You have already reported this").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create report").into_response(),
+    };
+
+    // Only members' topics and replies are hidden automatically; blog posts
+    // and moderators' content wait for a moderator
+    let hideable = match author {
+        Some(author_id) if target != ReportTarget::User => match state.db.get_user_by_id(author_id).await {
+            Ok(author) => author.is_some_and(|author| !is_moderator(&author.role)),
+            Err(e) => {
+                tracing::warn!("Failed to look up the author of {}: {}", content_id, e);
+                false
+            }
+        },
+        _ => false,
+    };
+
+    let threshold = hide_threshold();
+    if threshold > 0 && hideable {
+        match state.db.count_open_reporters(target.as_str(), content_id).await {
+            Ok(count) if count >= threshold => {
+                if let Err(e) = state.db.hide_reported_content(target.as_str(), content_id, None).await {
+                    tracing::warn!("Failed to hide reported {} {}: {}", target.as_str(), content_id, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to count reports on {}: {}", content_id, e),
+        }
+    }
+
+    (StatusCode::CREATED, Json(created)).into_response()
+}
+
+// Moderation queue
+// Reports are handled per target: resolving one resolves all open reports
+// about the same post, topic, reply or user
+
+pub async fn api_report_queue(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<QueueQuery>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.list_report_queue(limit, offset).await {
+        Ok(items) => Json(items).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_resolve_report(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(report_id): Path<Uuid>,
+    Json(resolve): Json<ResolveReport>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    let moderator_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let report = match state.db.get_report(report_id).await {
+        Ok(Some(report)) => report,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Report not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if report.status != "open" {
+        return (StatusCode::CONFLICT, "This report has already been resolved").into_response();
+    }
+
+    let content_type = report.content_type.as_str();
+    let message = resolve.message.as_deref().map(str::trim).filter(|m| !m.is_empty());
+
+    // Who to tell what was done, and what to tell them
+    let mut notice = None;
+    let result = match resolve.action {
+        ModerationAction::Dismiss => state.db.unhide_reported_content(content_type, report.content_id).await.map(|_| ()),
+        ModerationAction::Hide => {
+            if ReportTarget::parse(content_type) == Some(ReportTarget::User) {
+                return (StatusCode::BAD_REQUEST, "Users can't be hidden; warn or suspend them").into_response();
+            }
+            if let Ok(Some(author_id)) = state.db.reported_user_id(content_type, report.content_id).await {
+                notice = Some((author_id, format!("A moderator hid your {} after it was reported", content_type)));
+            }
+            state.db.hide_reported_content(content_type, report.content_id, Some(moderator_id)).await.map(|_| ())
+        }
+        ModerationAction::Warn | ModerationAction::Suspend => {
+            let user = match state.db.reported_user_id(content_type, report.content_id).await {
+                Ok(Some(user_id)) => state.db.get_user_by_id(user_id).await,
+                Ok(None) => {
+                    return (StatusCode::BAD_REQUEST, "Posts have no forum author to warn or suspend").into_response();
+                }
+                Err(e) => Err(e),
+            };
+            let user = match user {
+                Ok(Some(user)) => user,
+                Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+                Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+            };
+            if is_moderator(&user.role) {
+                return (StatusCode::FORBIDDEN, "Moderators can't be warned or suspended").into_response();
+            }
+
+            let Some(message) = message else {
+                return (StatusCode::BAD_REQUEST, "A warning or suspension needs a message").into_response();
+            };
+            if resolve.action == ModerationAction::Warn {
+                notice = Some((user.id, format!("A moderator warned you: {}", message)));
+                state.db.warn_user(user.id, moderator_id, Some(report.id), message).await.map(|_| ())
+            } else {
+                let days = resolve.days.unwrap_or(DEFAULT_SUSPENSION_DAYS);
+                if !(1..=MAX_RESTRICTION_DAYS).contains(&days) {
+                    return (StatusCode::BAD_REQUEST, "days must be between 1 and 3650").into_response();
+                }
+                let until = Utc::now() + Duration::days(days);
+                notice = Some((user.id, format!("A moderator suspended you for {} days: {}", days, message)));
+                state.db.suspend_user(user.id, until, message, moderator_id).await.map(|_| ())
+            }
+        }
+    };
+    if result.is_err() {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve report").into_response();
+    }
+
+    let status = match resolve.action {
+        ModerationAction::Dismiss => "dismissed",
+        _ => "actioned",
+    };
+    match state.db.resolve_reports(content_type, report.content_id, status, resolve.action.as_str(), moderator_id).await {
+        Ok(_) => {
+            let after = serde_json::json!({
+                "action": resolve.action.as_str(),
+                "message": message,
+                "days": resolve.days,
+                "content_type": content_type,
+                "content_id": report.content_id,
+            });
+            audit::record(&state, &claims, &ip, AuditEvent::new("report.resolve", "report", report.id).before(&report).after(&after)).await;
+            if let Some((user_id, message)) = notice {
+                notifications::moderated(&state, &claims, user_id, None, None, &message).await;
+            }
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to resolve report").into_response(),
+    }
+}
+
+// The signed-in member's own warnings
+pub async fn api_my_warnings(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    match state.db.list_warnings(user_id).await {
+        Ok(warnings) => Json(warnings).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
@@ -0,0 +1,253 @@
+use crate::{
+    audit::{self, AuditEvent, ClientIp},
+    auth::{is_moderator, optional_claims},
+    badges::{self, BadgeSummary, BADGES},
+    models::{Claims, CreatePenalty},
+    notifications,
+    templates::{BadgeTemplate, BadgesTemplate},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use askama::Template;
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// The largest penalty a moderator can give at once.
+pub const MAX_PENALTY: i32 = 1000;
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+// Reputation API
+// Anyone can see why a member has the reputation they have; the reasons for
+// penalties are only shown to the member and moderators
+
+pub async fn api_user_reputation(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<LedgerQuery>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    let profile = match state.db.get_user_profile(user_id).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User profile not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let mut events = match state.db.list_reputation_events(user_id, limit, offset).await {
+        Ok(events) => events,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let viewer = optional_claims(&headers);
+    let sees_reasons = viewer.is_some_and(|claims| is_moderator(&claims.role) || claims.sub == user_id.to_string());
+    if !sees_reasons {
+        for event in &mut events {
+            event.reason = None;
+        }
+    }
+
+    Json(serde_json::json!({
+        "user_id": user_id,
+        "reputation": profile.reputation,
+        "events": events,
+    }))
+    .into_response()
+}
+
+pub async fn api_add_penalty(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+    Json(penalty): Json<CreatePenalty>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    let moderator_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let reason = penalty.reason.trim();
+    if reason.is_empty() {
+        return (StatusCode::BAD_REQUEST, "A reason is required").into_response();
+    }
+    if !(1..=MAX_PENALTY).contains(&penalty.points) {
+        return (StatusCode::BAD_REQUEST, "points must be between 1 and 1000").into_response();
+    }
+
+    match state.db.get_user_by_id(user_id).await {
+        Ok(Some(user)) if is_moderator(&user.role) => {
+            return (StatusCode::FORBIDDEN, "Moderators can't be penalized").into_response();
+        }
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
+    match state.db.add_penalty(user_id, penalty.points, reason, moderator_id).await {
+        Ok(event) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("user.penalty", "user", user_id).after(&event)).await;
+            let message = format!("A moderator took {} reputation from you: {}", penalty.points, reason);
+            notifications::moderated(&state, &claims, user_id, None, None, &message).await;
+            (StatusCode::CREATED, Json(event)).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to add penalty").into_response(),
+    }
+}
+
+// Featuring a topic earns its author reputation
+
+pub async fn api_feature_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    let moderator_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let topic = match state.db.get_topic(topic_id).await {
+        Ok(Some(topic)) => topic,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Topic not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    match state.db.feature_topic(topic_id, moderator_id).await {
+        Ok(Some(event)) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("topic.feature", "topic", topic_id).after(&event)).await;
+            let message = "A moderator featured your topic";
+            notifications::moderated(&state, &claims, topic.user_id, Some(topic_id), None, message).await;
+            (StatusCode::CREATED, Json(event)).into_response()
+        }
+        Ok(None) => (StatusCode::CONFLICT, "This topic is already featured").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to feature topic").into_response(),
+    }
+}
+
+pub async fn api_unfeature_topic(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(topic_id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.unfeature_topic(topic_id).await {
+        Ok(Some(event)) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("topic.unfeature", "topic", topic_id).before(&event)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "This topic isn't featured").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to unfeature topic").into_response(),
+    }
+}
+
+// Badges
+
+async fn badge_summaries(state: &AppState) -> Result<Vec<BadgeSummary>, Response> {
+    let counts: HashMap<String, i64> = state
+        .db
+        .count_badge_holders()
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response())?
+        .into_iter()
+        .collect();
+
+    Ok(BADGES
+        .iter()
+        .map(|badge| BadgeSummary {
+            badge,
+            holders: counts.get(badge.slug).copied().unwrap_or(0),
+        })
+        .collect())
+}
+
+pub async fn badges_page(State(state): State<AppState>) -> impl IntoResponse {
+    match badge_summaries(&state).await {
+        Ok(badges) => {
+            let template = BadgesTemplate { badges };
+            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+pub async fn badge_page(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+    Query(params): Query<LedgerQuery>,
+) -> impl IntoResponse {
+    let Some(badge) = badges::find(&slug) else {
+        return (StatusCode::NOT_FOUND, "Badge not found").into_response();
+    };
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.list_badge_holders(badge.slug, limit, offset).await {
+        Ok(holders) => {
+            let has_next = holders.len() == limit as usize;
+            let template = BadgeTemplate { badge, holders, current_page: page, has_next };
+            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_list_badges(State(state): State<AppState>) -> impl IntoResponse {
+    match badge_summaries(&state).await {
+        Ok(badges) => Json(badges).into_response(),
+        Err(response) => response,
+    }
+}
+
+pub async fn api_get_badge(
+    State(state): State<AppState>,
+    Path(slug): Path<String>,
+    Query(params): Query<LedgerQuery>,
+) -> impl IntoResponse {
+    let Some(badge) = badges::find(&slug) else {
+        return (StatusCode::NOT_FOUND, "Badge not found").into_response();
+    };
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let offset = (page - 1) as i64 * limit;
+
+    match state.db.list_badge_holders(badge.slug, limit, offset).await {
+        Ok(holders) => Json(serde_json::json!({ "badge": badge, "holders": holders })).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_user_badges(
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> impl IntoResponse {
+    match state.db.list_user_badges(user_id).await {
+        Ok(awarded) => Json(badges::awarded(awarded)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
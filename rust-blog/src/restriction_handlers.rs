@@ -0,0 +1,291 @@
+use crate::{
+    audit::{self, AuditEvent, ClientIp},
+    auth::is_moderator,
+    models::{BanType, Claims, CreateBan, RestrictUser, User},
+    notifications,
+    restrictions::{self, MAX_RESTRICTION_DAYS},
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+#[derive(Clone, Copy)]
+enum Restriction {
+    Suspend,
+    Silence,
+}
+
+// Suspending and silencing are for moderators, and can't be used on other
+// moderators
+
+pub async fn api_suspend_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+    Json(restrict): Json<RestrictUser>,
+) -> impl IntoResponse {
+    restrict_user(&state, &claims, &ip, user_id, restrict, Restriction::Suspend).await
+}
+
+pub async fn api_silence_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+    Json(restrict): Json<RestrictUser>,
+) -> impl IntoResponse {
+    restrict_user(&state, &claims, &ip, user_id, restrict, Restriction::Silence).await
+}
+
+pub async fn api_unsuspend_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+) -> impl IntoResponse {
+    lift_restriction(&state, &claims, &ip, user_id, Restriction::Suspend).await
+}
+
+pub async fn api_unsilence_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(user_id): Path<Uuid>,
+) -> impl IntoResponse {
+    lift_restriction(&state, &claims, &ip, user_id, Restriction::Silence).await
+}
+
+async fn lift_restriction(
+    state: &AppState,
+    claims: &Claims,
+    ip: &ClientIp,
+    user_id: Uuid,
+    restriction: Restriction,
+) -> Response {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    let before = match state.db.get_user_by_id(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let (lifted, action, not_found, message) = match restriction {
+        Restriction::Suspend => (
+            state.db.lift_suspension(user_id).await,
+            "user.unsuspend",
+            "User is not suspended",
+            "A moderator lifted your suspension",
+        ),
+        Restriction::Silence => (
+            state.db.lift_silence(user_id).await,
+            "user.unsilence",
+            "User is not silenced",
+            "A moderator lifted your silence; you can post again",
+        ),
+    };
+
+    match lifted {
+        Ok(true) => {
+            let event = AuditEvent::new(action, "user", user_id).before(&restriction_status(&before));
+            audit::record(state, claims, ip, event).await;
+            notifications::moderated(state, claims, user_id, None, None, message).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, not_found).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to lift restriction").into_response(),
+    }
+}
+
+async fn restrict_user(
+    state: &AppState,
+    claims: &Claims,
+    ip: &ClientIp,
+    user_id: Uuid,
+    restrict: RestrictUser,
+    restriction: Restriction,
+) -> Response {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    let moderator_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let reason = restrict.reason.trim();
+    if reason.is_empty() {
+        return (StatusCode::BAD_REQUEST, "A reason is required").into_response();
+    }
+    if !(1..=MAX_RESTRICTION_DAYS).contains(&restrict.days) {
+        return (StatusCode::BAD_REQUEST, "days must be between 1 and 3650").into_response();
+    }
+
+    let user = match state.db.get_user_by_id(user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    if is_moderator(&user.role) {
+        return (StatusCode::FORBIDDEN, "Moderators can't be suspended or silenced").into_response();
+    }
+
+    let until = Utc::now() + Duration::days(restrict.days);
+    let (result, action) = match restriction {
+        Restriction::Suspend => (state.db.suspend_user(user_id, until, reason, moderator_id).await, "user.suspend"),
+        Restriction::Silence => (state.db.silence_user(user_id, until, reason, moderator_id).await, "user.silence"),
+    };
+
+    match result {
+        Ok(_) => match state.db.get_user_by_id(user_id).await {
+            Ok(Some(updated)) => {
+                let status = restriction_status(&updated);
+                let event = AuditEvent::new(action, "user", user_id).before(&restriction_status(&user)).after(&status);
+                audit::record(state, claims, ip, event).await;
+                let message = match restriction {
+                    Restriction::Suspend => restrictions::suspension_message(&updated),
+                    Restriction::Silence => restrictions::silence_message(&updated),
+                };
+                notifications::moderated(state, claims, user_id, None, None, &message).await;
+                Json(status).into_response()
+            }
+            Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to restrict user").into_response(),
+    }
+}
+
+// A user's restrictions, without the rest of the user row
+fn restriction_status(user: &User) -> serde_json::Value {
+    serde_json::json!({
+        "user_id": user.id,
+        "suspended_until": user.suspended_until,
+        "suspension_reason": user.suspension_reason,
+        "suspended_by": user.suspended_by,
+        "silenced_until": user.silenced_until,
+        "silence_reason": user.silence_reason,
+        "silenced_by": user.silenced_by,
+    })
+}
+
+// Bans API
+// IP bans take an address or a CIDR range; email domain bans also cover
+// subdomains
+
+pub async fn api_list_bans(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.list_bans().await {
+        Ok(bans) => Json(bans).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_create_ban(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Json(ban): Json<CreateBan>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+    let moderator_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+
+    let reason = ban.reason.trim();
+    if reason.is_empty() {
+        return (StatusCode::BAD_REQUEST, "A reason is required").into_response();
+    }
+    let value = match ban.ban_type {
+        BanType::Ip => normalize_ip_range(&ban.value),
+        BanType::EmailDomain => normalize_email_domain(&ban.value),
+    };
+    let Some(value) = value else {
+        let message = match ban.ban_type {
+            BanType::Ip => "value must be an IP address or CIDR range",
+            BanType::EmailDomain => "value must be an email domain",
+        };
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    };
+    let expires_at = match ban.days {
+        Some(days) if !(1..=MAX_RESTRICTION_DAYS).contains(&days) => {
+            return (StatusCode::BAD_REQUEST, "days must be between 1 and 3650").into_response();
+        }
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    match state.db.create_ban(ban.ban_type.as_str(), &value, reason, moderator_id, expires_at).await {
+        Ok(Some(ban)) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("ban.create", "ban", ban.id).after(&ban)).await;
+            (StatusCode::CREATED, Json(ban)).into_response()
+        }
+        Ok(None) => (StatusCode::CONFLICT, "This is already banned").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create ban").into_response(),
+    }
+}
+
+pub async fn api_delete_ban(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(ban_id): Path<Uuid>,
+) -> impl IntoResponse {
+    if !is_moderator(&claims.role) {
+        return (StatusCode::FORBIDDEN, "Editor access required").into_response();
+    }
+
+    match state.db.delete_ban(ban_id).await {
+        Ok(Some(ban)) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("ban.delete", "ban", ban_id).before(&ban)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Ban not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete ban").into_response(),
+    }
+}
+
+// `1.2.3.4`, `1.2.3.0/24` or an IPv6 equivalent
+fn normalize_ip_range(value: &str) -> Option<String> {
+    let value = value.trim();
+    let (ip, prefix) = match value.split_once('/') {
+        Some((ip, prefix)) => (ip, Some(prefix.parse::<u8>().ok()?)),
+        None => (value, None),
+    };
+    let ip = ip.parse::<IpAddr>().ok()?;
+    let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+    match prefix {
+        Some(prefix) if prefix > max_prefix => None,
+        Some(prefix) => Some(format!("{}/{}", ip, prefix)),
+        None => Some(ip.to_string()),
+    }
+}
+
+// `example.com` or `@example.com`, lowercased
+fn normalize_email_domain(value: &str) -> Option<String> {
+    let domain = value.trim().trim_start_matches('@').to_lowercase();
+    let valid = domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    valid.then_some(domain)
+}
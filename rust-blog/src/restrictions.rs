@@ -0,0 +1,149 @@
+//! Suspensions, silences and IP and email domain bans. Rather than every
+//! handler checking them, `enforce` runs before every request that changes
+//! something; reading stays open to everyone. Silences only stop members
+//! posting, so the handlers that post check `check_silence`.
+
+use crate::{audit::ClientIp, auth::optional_claims, models::{Ban, Claims, User}, AppState};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+/// The longest suspension, silence or temporary ban, in days.
+pub const MAX_RESTRICTION_DAYS: i64 = 3650;
+
+/// The client's address: the first `X-Forwarded-For` hop when
+/// `TRUST_PROXY=true` (behind an ingress or load balancer), otherwise the
+/// peer address.
+pub fn client_ip(headers: &HeaderMap, addr: SocketAddr) -> String {
+    let trust_proxy = std::env::var("TRUST_PROXY").is_ok_and(|value| value == "true");
+    if trust_proxy {
+        let forwarded = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|ip| ip.parse::<std::net::IpAddr>().is_ok());
+        if let Some(ip) = forwarded {
+            return ip.to_string();
+        }
+    }
+    addr.ip().to_string()
+}
+
+/// The domain of an email address, lowercased.
+pub fn email_domain(email: &str) -> Option<String> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim().to_lowercase())
+        .filter(|domain| !domain.is_empty())
+}
+
+fn until(date: Option<DateTime<Utc>>) -> String {
+    match date {
+        Some(date) => format!("until {}", date.format("%Y-%m-%d %H:%M UTC")),
+        None => "permanently".to_string(),
+    }
+}
+
+pub fn suspension_message(user: &User) -> String {
+    format!(
+        "Your account is suspended {}: {}",
+        until(user.suspended_until),
+        user.suspension_reason.as_deref().unwrap_or("no reason given")
+    )
+}
+
+pub fn silence_message(user: &User) -> String {
+    format!(
+        "Your account is silenced {}: {}",
+        until(user.silenced_until),
+        user.silence_reason.as_deref().unwrap_or("no reason given")
+    )
+}
+
+pub fn ban_message(ban: &Ban) -> String {
+    let what = match ban.ban_type.as_str() {
+        "ip" => "Your IP address is banned",
+        _ => "Your email domain is banned",
+    };
+    format!("{} {}: {}", what, until(ban.expires_at), ban.reason)
+}
+
+/// Refuses writes from banned addresses and from suspended or banned-domain
+/// users, so every write path is covered without checking
+/// in each handler. Also makes the client address available to handlers
+/// as `ClientIp`.
+pub async fn enforce(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let ip = client_ip(request.headers(), addr);
+    request.extensions_mut().insert(ClientIp(ip.clone()));
+
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(request).await;
+    }
+
+    match state.db.find_ip_ban(&ip).await {
+        Ok(Some(ban)) => return (StatusCode::FORBIDDEN, ban_message(&ban)).into_response(),
+        Ok(None) => {}
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
+    let user_id = optional_claims(request.headers()).and_then(|claims| claims.sub.parse::<Uuid>().ok());
+    if let Some(user_id) = user_id {
+        let user = match state.db.get_user_by_id(user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return (StatusCode::UNAUTHORIZED, "User not found").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        };
+        if let Err(message) = check_user(&state, &user).await {
+            return message;
+        }
+    }
+
+    next.run(request).await
+}
+
+/// Whether a user may act at all: not suspended or under an email domain
+/// ban.
+async fn check_user(state: &AppState, user: &User) -> Result<(), Response> {
+    if user.is_suspended() {
+        return Err((StatusCode::FORBIDDEN, suspension_message(user)).into_response());
+    }
+    check_email_domain(state, &user.email).await
+}
+
+/// Refuses posting, editing, reporting and uploading from a silenced user.
+pub async fn check_silence(state: &AppState, claims: &Claims) -> Result<(), Response> {
+    let Ok(user_id) = claims.sub.parse::<Uuid>() else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid user ID").into_response());
+    };
+    match state.db.get_user_by_id(user_id).await {
+        Ok(Some(user)) if user.is_silenced() => Err((StatusCode::FORBIDDEN, silence_message(&user)).into_response()),
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((StatusCode::UNAUTHORIZED, "User not found").into_response()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    }
+}
+
+/// Refuses an email address whose domain is banned; also used when
+/// registering and logging in.
+pub async fn check_email_domain(state: &AppState, email: &str) -> Result<(), Response> {
+    let Some(domain) = email_domain(email) else {
+        return Ok(());
+    };
+    match state.db.find_email_domain_ban(&domain).await {
+        Ok(Some(ban)) => Err((StatusCode::FORBIDDEN, ban_message(&ban)).into_response()),
+        Ok(None) => Ok(()),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()),
+    }
+}
@@ -0,0 +1,369 @@
+//! Full-text search over posts, forum topics and replies, wiki pages and
+//! users, backed by the generated `search_vector` columns.
+//!
+//! Postgres marks matches in `ts_headline` output with control characters
+//! rather than HTML, because post bodies may themselves contain HTML. The
+//! markers are turned into a plain snippet and an escaped `<mark>` highlight
+//! here.
+//!
+//! Postgres doesn't segment Chinese: a run of Han characters becomes a single
+//! token. CJK runs are therefore split into words by a [`Segmenter`] both when
+//! content is written (into the `*_words` columns) and when a query is parsed.
+
+use crate::models::{SearchHit, SearchResult};
+use jieba_rs::Jieba;
+use std::sync::OnceLock;
+
+pub const SEARCH_TYPES: &[&str] = &["post", "topic", "reply", "wiki", "user"];
+pub const FORUM_SEARCH_TYPES: &[&str] = &["topic", "reply"];
+
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+pub const START_SEL: char = '\u{1}';
+pub const STOP_SEL: char = '\u{2}';
+
+/// Options passed to `ts_headline`.
+pub fn headline_options() -> String {
+    format!(
+        "StartSel={}, StopSel={}, MaxWords=35, MinWords=15, MaxFragments=2, FragmentDelimiter=\" … \"",
+        START_SEL, STOP_SEL
+    )
+}
+
+/// Parses the comma-separated `type` filter, falling back to `default` when
+/// it is absent or empty.
+pub fn parse_types(types: Option<&str>, default: &[&str]) -> Result<Vec<String>, String> {
+    let requested: Vec<&str> = types
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect();
+    if requested.is_empty() {
+        return Ok(default.iter().map(|t| t.to_string()).collect());
+    }
+
+    let mut parsed = Vec::new();
+    for t in requested {
+        if !SEARCH_TYPES.contains(&t) {
+            return Err(format!("Unknown search type '{}', expected one of: {}", t, SEARCH_TYPES.join(", ")));
+        }
+        if !parsed.iter().any(|p| p == t) {
+            parsed.push(t.to_string());
+        }
+    }
+    Ok(parsed)
+}
+
+pub fn to_result(hit: SearchHit, cjk_terms: &[String]) -> SearchResult {
+    let (snippet, highlight) = render_headline(&hit.headline, cjk_terms);
+    SearchResult {
+        result_type: hit.result_type,
+        id: hit.id,
+        title: hit.title,
+        url: hit.url,
+        snippet,
+        highlight,
+        rank: hit.rank,
+        created_at: hit.created_at,
+    }
+}
+
+const MAX_SNIPPET_CHARS: usize = 240;
+
+/// Splits a marked-up headline into a plain snippet and an HTML-escaped
+/// version with matches wrapped in `<mark>`. `cjk_terms` are marked as well,
+/// since Postgres can't find them inside unsegmented runs.
+pub fn render_headline(headline: &str, cjk_terms: &[String]) -> (String, String) {
+    let headline = headline.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let mut chars = Vec::with_capacity(headline.len());
+    let mut marked = Vec::with_capacity(headline.len());
+    let mut inside = false;
+    for c in headline.chars() {
+        match c {
+            START_SEL => inside = true,
+            STOP_SEL => inside = false,
+            _ => {
+                chars.push(c);
+                marked.push(inside);
+            }
+        }
+    }
+
+    for term in cjk_terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() {
+            continue;
+        }
+        let mut i = 0;
+        while i + term.len() <= chars.len() {
+            if chars[i..i + term.len()] == term[..] {
+                marked[i..i + term.len()].fill(true);
+                i += term.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // A long CJK run is a single word to ts_headline, which then returns it
+    // whole; keep the excerpt to a window around the first match
+    let (start, end) = if chars.len() <= MAX_SNIPPET_CHARS {
+        (0, chars.len())
+    } else {
+        let first = marked.iter().position(|m| *m).unwrap_or(0);
+        let start = first.saturating_sub(MAX_SNIPPET_CHARS / 3);
+        (start, (start + MAX_SNIPPET_CHARS).min(chars.len()))
+    };
+
+    let mut snippet = String::with_capacity(headline.len());
+    let mut highlight = String::with_capacity(headline.len() + 32);
+    if start > 0 {
+        snippet.push('…');
+        highlight.push('…');
+    }
+    for i in start..end {
+        if marked[i] && (i == start || !marked[i - 1]) {
+            highlight.push_str("<mark>");
+        }
+        let c = chars[i];
+        snippet.push(c);
+        match c {
+            '&' => highlight.push_str("&amp;"),
+            '<' => highlight.push_str("&lt;"),
+            '>' => highlight.push_str("&gt;"),
+            '"' => highlight.push_str("&quot;"),
+            '\'' => highlight.push_str("&#x27;"),
+            _ => highlight.push(c),
+        }
+        if marked[i] && (i + 1 == end || !marked[i + 1]) {
+            highlight.push_str("</mark>");
+        }
+    }
+    if end < chars.len() {
+        snippet.push('…');
+        highlight.push('…');
+    }
+
+    (snippet, highlight)
+}
+
+// CJK segmentation
+
+/// Splits runs of CJK characters into words.
+pub trait Segmenter: Send + Sync {
+    /// Words to index for a run of CJK text.
+    fn index_words(&self, run: &str) -> Vec<String>;
+    /// Words to search for when a query contains the run.
+    fn query_words(&self, run: &str) -> Vec<String>;
+}
+
+/// Dictionary-based segmentation. Indexing also emits the shorter words
+/// inside long compounds, so a query for part of a compound still matches.
+pub struct JiebaSegmenter {
+    jieba: Jieba,
+}
+
+impl Default for JiebaSegmenter {
+    fn default() -> Self {
+        Self { jieba: Jieba::new() }
+    }
+}
+
+impl Segmenter for JiebaSegmenter {
+    fn index_words(&self, run: &str) -> Vec<String> {
+        self.jieba.cut_for_search(run, true).into_iter().map(str::to_string).collect()
+    }
+
+    fn query_words(&self, run: &str) -> Vec<String> {
+        self.jieba.cut(run, true).into_iter().map(str::to_string).collect()
+    }
+}
+
+/// Overlapping character pairs, the n-gram approach of trigram matching
+/// scaled down to the length of a typical Chinese word. Needs no dictionary
+/// and finds any substring of two or more characters, at the cost of looser
+/// ranking.
+pub struct BigramSegmenter;
+
+impl Segmenter for BigramSegmenter {
+    fn index_words(&self, run: &str) -> Vec<String> {
+        let chars: Vec<char> = run.chars().collect();
+        if chars.len() < 2 {
+            return vec![run.to_string()];
+        }
+        chars.windows(2).map(|pair| pair.iter().collect()).collect()
+    }
+
+    fn query_words(&self, run: &str) -> Vec<String> {
+        self.index_words(run)
+    }
+}
+
+/// The segmenter chosen by `SEARCH_SEGMENTER` (`jieba`, the default, or
+/// `bigram`). Switching requires running `rust-blog reindex-search`.
+pub fn segmenter() -> &'static dyn Segmenter {
+    static SEGMENTER: OnceLock<Box<dyn Segmenter>> = OnceLock::new();
+    SEGMENTER
+        .get_or_init(|| match std::env::var("SEARCH_SEGMENTER").as_deref() {
+            Ok("bigram") => Box::new(BigramSegmenter),
+            Ok("jieba") | Err(_) => Box::new(JiebaSegmenter::default()),
+            Ok(other) => {
+                tracing::warn!("Unknown SEARCH_SEGMENTER '{}', using jieba", other);
+                Box::new(JiebaSegmenter::default())
+            }
+        })
+        .as_ref()
+}
+
+pub fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'       // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}'     // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'     // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'     // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}'     // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}'   // CJK Extensions B-F and supplement
+    )
+}
+
+/// Splits text into alternating non-CJK and CJK pieces.
+pub fn split_runs(text: &str) -> Vec<(bool, &str)> {
+    let mut runs: Vec<(bool, &str)> = Vec::new();
+    let mut start = 0;
+    let mut current = None;
+    for (i, c) in text.char_indices() {
+        let cjk = is_cjk(c);
+        if current.is_some_and(|current| current != cjk) {
+            runs.push((!cjk, &text[start..i]));
+            start = i;
+        }
+        current = Some(cjk);
+    }
+    if let Some(cjk) = current {
+        runs.push((cjk, &text[start..]));
+    }
+    runs
+}
+
+/// The CJK words in `text`, space-separated, for the `*_words` columns.
+/// Empty when the text has no CJK characters.
+pub fn cjk_words(text: &str) -> String {
+    let segmenter = segmenter();
+    split_runs(text)
+        .into_iter()
+        .filter(|(cjk, _)| *cjk)
+        .flat_map(|(_, run)| segmenter.index_words(run))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The query with each CJK run replaced by its words, leaving the other
+/// text and the search operators alone, plus the CJK words on their own for
+/// highlighting.
+pub fn segment_query(query: &str) -> (String, Vec<String>) {
+    let segmenter = segmenter();
+    let mut segmented = String::with_capacity(query.len() * 2);
+    let mut terms = Vec::new();
+    for (cjk, run) in split_runs(query) {
+        if !cjk {
+            segmented.push_str(run);
+            continue;
+        }
+        let words = segmenter.query_words(run);
+        segmented.push(' ');
+        segmented.push_str(&words.join(" "));
+        segmented.push(' ');
+        terms.extend(words);
+    }
+    (segmented, terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marked(text: &str) -> String {
+        format!("{}{}{}", START_SEL, text, STOP_SEL)
+    }
+
+    #[test]
+    fn runs_alternate_between_latin_and_han() {
+        assert_eq!(
+            split_runs("ROP栈溢出attack 教程"),
+            [(false, "ROP"), (true, "栈溢出"), (false, "attack "), (true, "教程")]
+        );
+        assert_eq!(split_runs("堆"), [(true, "堆")]);
+        assert!(split_runs("").is_empty());
+    }
+
+    #[test]
+    fn bigrams_overlap() {
+        assert_eq!(BigramSegmenter.index_words("栈溢出"), ["栈溢", "溢出"]);
+        assert_eq!(BigramSegmenter.index_words("堆"), ["堆"]);
+    }
+
+    #[test]
+    fn queries_keep_latin_text_and_operators() {
+        let (segmented, terms) = segment_query("\"heap\" -rop 栈溢出");
+        assert!(segmented.starts_with("\"heap\" -rop "));
+        assert_eq!(terms.concat(), "栈溢出");
+        assert!(terms.iter().all(|term| segmented.contains(term.as_str())));
+        assert_eq!(segment_query("heap"), ("heap".to_string(), Vec::new()));
+    }
+
+    #[test]
+    fn postgres_matches_are_marked() {
+        let headline = format!("{}  overflow\non the {}", marked("heap"), marked("heap"));
+        assert_eq!(
+            render_headline(&headline, &[]),
+            ("heap overflow on the heap".to_string(), "<mark>heap</mark> overflow on the <mark>heap</mark>".to_string())
+        );
+    }
+
+    #[test]
+    fn cjk_terms_are_marked_at_the_start_and_end() {
+        let (snippet, highlight) = render_headline("溢出攻击和溢出", &["溢出".to_string()]);
+        assert_eq!(snippet, "溢出攻击和溢出");
+        assert_eq!(highlight, "<mark>溢出</mark>攻击和<mark>溢出</mark>");
+    }
+
+    #[test]
+    fn marked_text_is_escaped() {
+        let headline = format!("a {} 'b'", marked("<b>&\"x\""));
+        assert_eq!(
+            render_headline(&headline, &[]).1,
+            "a <mark>&lt;b&gt;&amp;&quot;x&quot;</mark> &#x27;b&#x27;"
+        );
+    }
+
+    #[test]
+    fn long_headlines_are_cut_around_the_first_match() {
+        let headline = format!("{}溢出{}", "字".repeat(100), "字".repeat(200));
+        let (snippet, highlight) = render_headline(&headline, &["溢出".to_string()]);
+
+        let start = 100 - MAX_SNIPPET_CHARS / 3;
+        let expected = format!("…{}…", headline.chars().skip(start).take(MAX_SNIPPET_CHARS).collect::<String>());
+        assert_eq!(snippet, expected);
+        assert!(highlight.starts_with(&format!("…{}<mark>溢出</mark>", "字".repeat(100 - start))));
+        assert!(highlight.ends_with("字…"));
+    }
+
+    #[test]
+    fn matches_cut_by_the_window_are_closed() {
+        let start = 100 - MAX_SNIPPET_CHARS / 3;
+        let gap = start + MAX_SNIPPET_CHARS - 103;
+        let headline = format!("{}溢出{}溢出{}", "字".repeat(100), "字".repeat(gap), "字".repeat(50));
+        let (_, highlight) = render_headline(&headline, &["溢出".to_string()]);
+        assert!(highlight.ends_with("<mark>溢</mark>…"));
+    }
+
+    #[test]
+    fn long_headlines_without_matches_keep_the_start() {
+        let headline = "字".repeat(MAX_SNIPPET_CHARS + 1);
+        let (snippet, _) = render_headline(&headline, &[]);
+        assert_eq!(snippet, format!("{}…", "字".repeat(MAX_SNIPPET_CHARS)));
+    }
+}
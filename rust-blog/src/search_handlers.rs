@@ -0,0 +1,175 @@
+use crate::{
+    models::{SearchQuery, SearchResults, SuggestQuery, SuggestResponse},
+    search::{self, DEFAULT_PAGE_SIZE, FORUM_SEARCH_TYPES, MAX_PAGE_SIZE, SEARCH_TYPES},
+    search_index::IndexQuery,
+    suggest,
+    templates::SearchTemplate,
+    AppState,
+};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use askama::Template;
+
+const MAX_QUERY_LENGTH: usize = 256;
+
+pub async fn api_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    match run_search(&state, &params, SEARCH_TYPES).await {
+        Ok(results) => Json(results).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+// Live search in the forum header; returns the results as a plain list
+pub async fn api_forum_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    match run_search(&state, &params, FORUM_SEARCH_TYPES).await {
+        Ok(results) => Json(results.results).into_response(),
+        Err((status, message)) => (status, message).into_response(),
+    }
+}
+
+// Titles, tags and usernames matching what has been typed so far
+pub async fn api_suggest(
+    State(state): State<AppState>,
+    Query(params): Query<SuggestQuery>,
+) -> impl IntoResponse {
+    let query = params.q.as_deref().unwrap_or_default().trim();
+    if query.is_empty() {
+        return (StatusCode::BAD_REQUEST, "Search query is required").into_response();
+    }
+
+    let limit = params.limit.unwrap_or(suggest::DEFAULT_LIMIT).clamp(1, suggest::MAX_LIMIT);
+    let suggestions = state.suggestions.suggest(query, limit);
+    let did_you_mean = if suggestions.is_empty() {
+        state.suggestions.did_you_mean(query)
+    } else {
+        None
+    };
+
+    Json(SuggestResponse { query: query.to_string(), suggestions, did_you_mean }).into_response()
+}
+
+pub async fn search_page(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    render_search_page(&state, "/search", &params, SEARCH_TYPES).await
+}
+
+pub async fn forum_search_page(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
+    render_search_page(&state, "/forum/search", &params, FORUM_SEARCH_TYPES).await
+}
+
+async fn render_search_page(state: &AppState, action: &str, params: &SearchQuery, default_types: &[&str]) -> Response {
+    let query = params.q.as_deref().unwrap_or_default().trim().to_string();
+    let (results, error) = if query.is_empty() {
+        (None, None)
+    } else {
+        match run_search(state, params, default_types).await {
+            Ok(results) => (Some(results), None),
+            Err((_, message)) => (None, Some(message)),
+        }
+    };
+
+    let type_options = [
+        ("", "Everything"),
+        ("topic,reply", "Forum"),
+        ("post", "Blog posts"),
+        ("topic", "Topics"),
+        ("reply", "Replies"),
+        ("wiki", "Wiki pages"),
+        ("user", "Users"),
+    ]
+    .into_iter()
+    .map(|(value, label)| (value.to_string(), label.to_string()))
+    .collect();
+
+    let template = SearchTemplate {
+        action: action.to_string(),
+        query,
+        selected_types: params.types.clone().unwrap_or_else(|| default_types.join(",")),
+        type_options,
+        results,
+        error,
+    };
+    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+}
+
+async fn run_search(
+    state: &AppState,
+    params: &SearchQuery,
+    default_types: &[&str],
+) -> Result<SearchResults, (StatusCode, String)> {
+    let query = params.q.as_deref().unwrap_or_default().trim();
+    if query.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Search query is required".to_string()));
+    }
+    if query.chars().count() > MAX_QUERY_LENGTH {
+        return Err((StatusCode::BAD_REQUEST, format!("Search query must be at most {} characters", MAX_QUERY_LENGTH)));
+    }
+
+    let types = search::parse_types(params.types.as_deref(), default_types)
+        .map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let offset = (page - 1).saturating_mul(limit);
+
+    let category = params.category.clone().filter(|c| !c.is_empty());
+    let tag = params.tag.clone().filter(|t| !t.is_empty());
+
+    let request = IndexQuery {
+        query: query.to_string(),
+        types: types.clone(),
+        category,
+        tag,
+        limit: limit as usize,
+        offset: usize::try_from(offset).unwrap_or(usize::MAX),
+    };
+    let (results, total, facets) = match state.db.search_index() {
+        Some(index) => {
+            let found = match tokio::task::spawn_blocking(move || index.search(&request)).await {
+                Ok(Ok(found)) => found,
+                _ => return Err((StatusCode::INTERNAL_SERVER_ERROR, "Search index error".to_string())),
+            };
+            (found.results, found.total, Some(found.facets))
+        }
+        None => {
+            let (segmented_query, cjk_terms) = search::segment_query(query);
+            let hits = state
+                .db
+                .search(&request, &segmented_query, &search::headline_options())
+                .await
+                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()))?;
+
+            let total = hits.first().map_or(0, |hit| hit.total);
+            let results = hits.into_iter().map(|hit| search::to_result(hit, &cjk_terms)).collect();
+            (results, total, None)
+        }
+    };
+
+    let did_you_mean = if total == 0 { state.suggestions.did_you_mean(query) } else { None };
+
+    Ok(SearchResults {
+        query: query.to_string(),
+        types,
+        results,
+        total,
+        page,
+        limit,
+        has_next: offset.saturating_add(limit) < total,
+        facets,
+        did_you_mean,
+    })
+}
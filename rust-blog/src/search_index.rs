@@ -0,0 +1,389 @@
+//! Embedded search index, an alternative to Postgres full-text search for
+//! small deployments and tests.
+//!
+//! The index lives on disk under `SEARCH_INDEX_DIR` and is kept in step with
+//! the database by [`Database`](crate::database::Database), which replaces a
+//! row's documents whenever it is written. `rust-blog reindex-search`
+//! rebuilds it from scratch.
+
+use crate::models::{FacetCount, SearchDocument, SearchFacets, SearchResult};
+use crate::search::{self, START_SEL, STOP_SEL};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::sync::Mutex;
+use tantivy::collector::{Count, FacetCollector, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::query::{BooleanQuery, Occur, Query, QueryParser, TermQuery, TermSetQuery};
+use tantivy::schema::{
+    Facet, FacetOptions, Field, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value,
+    STORED, STRING,
+};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::tokenizer::{
+    Language, LowerCaser, Stemmer, TextAnalyzer, Token, TokenStream, Tokenizer,
+};
+use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use uuid::Uuid;
+
+const TOKENIZER: &str = "blog";
+const WRITER_MEMORY: usize = 50_000_000;
+const MAX_FACETS: usize = 20;
+
+/// A search, as run by the index or, without one, by the database. It owns
+/// its fields so it can be moved to a blocking task.
+#[derive(Debug, Clone)]
+pub struct IndexQuery {
+    pub query: String,
+    pub types: Vec<String>,
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+pub struct IndexResults {
+    pub results: Vec<SearchResult>,
+    pub total: i64,
+    pub facets: SearchFacets,
+}
+
+/// A search index kept alongside the database.
+pub trait SearchIndex: Send + Sync {
+    /// Removes the documents for `id`, including a topic's replies, and adds
+    /// `documents` in their place.
+    fn replace(&self, id: Uuid, documents: Vec<SearchDocument>) -> Result<()>;
+    /// Drops every document and indexes `documents`.
+    fn rebuild(&self, documents: Vec<SearchDocument>) -> Result<()>;
+    fn search(&self, query: &IndexQuery) -> Result<IndexResults>;
+    fn num_docs(&self) -> u64;
+}
+
+/// Opens the index configured by `SEARCH_BACKEND=tantivy`, or returns `None`
+/// to search with Postgres.
+pub fn from_env() -> Result<Option<TantivyIndex>> {
+    match std::env::var("SEARCH_BACKEND").as_deref() {
+        Ok("tantivy") => {
+            let dir = std::env::var("SEARCH_INDEX_DIR").unwrap_or_else(|_| "search-index".to_string());
+            Ok(Some(TantivyIndex::open(Path::new(&dir))?))
+        }
+        Ok("postgres") | Err(_) => Ok(None),
+        Ok(other) => anyhow::bail!("Unknown SEARCH_BACKEND '{}', expected postgres or tantivy", other),
+    }
+}
+
+struct Fields {
+    id: Field,
+    topic_id: Field,
+    doc_type: Field,
+    title: Field,
+    body: Field,
+    url: Field,
+    facets: Field,
+    created_at: Field,
+}
+
+pub struct TantivyIndex {
+    index: Index,
+    reader: IndexReader,
+    writer: Mutex<IndexWriter>,
+    fields: Fields,
+}
+
+impl TantivyIndex {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let text = TextOptions::default().set_stored().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        );
+
+        let mut builder = Schema::builder();
+        let fields = Fields {
+            id: builder.add_text_field("id", STRING | STORED),
+            topic_id: builder.add_text_field("topic_id", STRING),
+            doc_type: builder.add_text_field("doc_type", STRING | STORED),
+            title: builder.add_text_field("title", text.clone()),
+            body: builder.add_text_field("body", text),
+            url: builder.add_text_field("url", STORED),
+            facets: builder.add_facet_field("facets", FacetOptions::default()),
+            created_at: builder.add_date_field("created_at", STORED),
+        };
+        let schema = builder.build();
+
+        std::fs::create_dir_all(dir)?;
+        let index = Index::open_or_create(MmapDirectory::open(dir)?, schema)?;
+        index.tokenizers().register(
+            TOKENIZER,
+            TextAnalyzer::builder(WordTokenizer)
+                .filter(LowerCaser)
+                .filter(Stemmer::new(Language::English))
+                .build(),
+        );
+
+        let reader = index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?;
+        let writer = index.writer(WRITER_MEMORY)?;
+
+        Ok(Self { index, reader, writer: Mutex::new(writer), fields })
+    }
+
+    fn to_tantivy(&self, document: SearchDocument) -> TantivyDocument {
+        let f = &self.fields;
+        let mut tantivy_doc = doc!(
+            f.id => document.id.to_string(),
+            f.doc_type => document.doc_type,
+            f.title => document.title,
+            f.body => document.body,
+            f.url => document.url,
+            f.created_at => tantivy::DateTime::from_timestamp_micros(document.created_at.timestamp_micros()),
+        );
+        if let Some(topic_id) = document.topic_id {
+            tantivy_doc.add_text(f.topic_id, topic_id.to_string());
+        }
+        if let Some(category) = document.category {
+            tantivy_doc.add_facet(f.facets, Facet::from_path(["category", category.as_str()]));
+        }
+        for tag in document.tags {
+            tantivy_doc.add_facet(f.facets, Facet::from_path(["tag", tag.as_str()]));
+        }
+        tantivy_doc
+    }
+
+    fn commit(&self, writer: &mut IndexWriter) -> Result<()> {
+        writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    fn parse_query(&self, query: &str) -> Box<dyn Query> {
+        let mut parser = QueryParser::for_index(&self.index, vec![self.fields.title, self.fields.body]);
+        parser.set_conjunction_by_default();
+        parser.set_field_boost(self.fields.title, 3.0);
+
+        // Accept the same syntax as the Postgres backend, whose `or` is lowercase
+        let (segmented, _) = search::segment_query(query);
+        let query = segmented
+            .split_whitespace()
+            .map(|word| if word == "or" { "OR" } else { word })
+            .collect::<Vec<_>>()
+            .join(" ");
+        parser.parse_query_lenient(&query).0
+    }
+
+    fn to_result(&self, document: &TantivyDocument, score: f32, snippets: &SnippetGenerator) -> SearchResult {
+        let f = &self.fields;
+        let text = |field: Field| {
+            document
+                .get_first(field)
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let snippet = snippets.snippet_from_doc(document);
+        let mut headline = String::new();
+        if snippet.fragment().is_empty() {
+            headline = text(f.body);
+        } else {
+            let mut last = 0;
+            for range in snippet.highlighted() {
+                headline.push_str(&snippet.fragment()[last..range.start]);
+                headline.push(START_SEL);
+                headline.push_str(&snippet.fragment()[range.clone()]);
+                headline.push(STOP_SEL);
+                last = range.end;
+            }
+            headline.push_str(&snippet.fragment()[last..]);
+        }
+        let (snippet, highlight) = search::render_headline(&headline, &[]);
+
+        let created_at = document
+            .get_first(f.created_at)
+            .and_then(|value| value.as_datetime())
+            .and_then(|date| DateTime::<Utc>::from_timestamp_micros(date.into_timestamp_micros()))
+            .unwrap_or_default();
+
+        SearchResult {
+            result_type: text(f.doc_type),
+            id: text(f.id).parse().unwrap_or_default(),
+            title: text(f.title),
+            url: text(f.url),
+            snippet,
+            highlight,
+            rank: score,
+            created_at,
+        }
+    }
+}
+
+impl SearchIndex for TantivyIndex {
+    fn replace(&self, id: Uuid, documents: Vec<SearchDocument>) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_term(Term::from_field_text(self.fields.id, &id.to_string()));
+        writer.delete_term(Term::from_field_text(self.fields.topic_id, &id.to_string()));
+        for document in documents {
+            writer.add_document(self.to_tantivy(document))?;
+        }
+        self.commit(&mut writer)
+    }
+
+    fn rebuild(&self, documents: Vec<SearchDocument>) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.delete_all_documents()?;
+        for document in documents {
+            writer.add_document(self.to_tantivy(document))?;
+        }
+        self.commit(&mut writer)
+    }
+
+    fn search(&self, query: &IndexQuery) -> Result<IndexResults> {
+        let f = &self.fields;
+        let text_query = self.parse_query(&query.query);
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+            (Occur::Must, text_query.box_clone()),
+            (
+                Occur::Must,
+                Box::new(TermSetQuery::new(
+                    query.types.iter().map(|t| Term::from_field_text(f.doc_type, t)),
+                )),
+            ),
+        ];
+        for (kind, value) in [("category", query.category.as_deref()), ("tag", query.tag.as_deref())] {
+            if let Some(value) = value {
+                clauses.push((
+                    Occur::Must,
+                    Box::new(TermQuery::new(
+                        Term::from_facet(f.facets, &Facet::from_path([kind, value])),
+                        IndexRecordOption::Basic,
+                    )),
+                ));
+            }
+        }
+        let filtered = BooleanQuery::new(clauses);
+
+        let mut facet_collector = FacetCollector::for_field("facets");
+        facet_collector.add_facet("/category");
+        facet_collector.add_facet("/tag");
+
+        let searcher = self.reader.searcher();
+        let (top_docs, total, facet_counts) = searcher.search(
+            &filtered,
+            &(
+                TopDocs::with_limit(query.limit).and_offset(query.offset),
+                Count,
+                facet_collector,
+            ),
+        )?;
+
+        let mut snippets = SnippetGenerator::create(&searcher, &*text_query, f.body)?;
+        snippets.set_max_num_chars(240);
+
+        let mut results = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let document: TantivyDocument = searcher.doc(address)?;
+            results.push(self.to_result(&document, score, &snippets));
+        }
+
+        let counts = |root: &str| {
+            facet_counts
+                .top_k(root, MAX_FACETS)
+                .into_iter()
+                .map(|(facet, count)| FacetCount {
+                    value: facet.to_path().last().copied().unwrap_or_default().to_string(),
+                    count,
+                })
+                .collect()
+        };
+
+        Ok(IndexResults {
+            results,
+            total: total as i64,
+            facets: SearchFacets { categories: counts("/category"), tags: counts("/tag") },
+        })
+    }
+
+    fn num_docs(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+}
+
+/// Splits text into words: letters and digits outside CJK runs, and the
+/// words of CJK runs as cut by the configured [`search::Segmenter`].
+///
+/// Documents and queries go through the same tokenizer, so CJK runs are
+/// segmented with `query_words`, which yields contiguous words whose
+/// positions line up for phrase queries.
+#[derive(Clone)]
+struct WordTokenizer;
+
+struct WordTokenStream {
+    tokens: Vec<Token>,
+    next: usize,
+}
+
+impl Tokenizer for WordTokenizer {
+    type TokenStream<'a> = WordTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> WordTokenStream {
+        let mut tokens = Vec::new();
+        let mut push = |from: usize, to: usize| {
+            tokens.push(Token {
+                offset_from: from,
+                offset_to: to,
+                position: tokens.len(),
+                text: text[from..to].to_string(),
+                position_length: 1,
+            });
+        };
+
+        let mut offset = 0;
+        for (cjk, run) in search::split_runs(text) {
+            if cjk {
+                // Words may overlap (bigrams), so look for each one after the
+                // start of the previous
+                let mut cursor = 0;
+                for word in search::segmenter().query_words(run) {
+                    if let Some(found) = run[cursor..].find(word.as_str()) {
+                        let start = cursor + found;
+                        push(offset + start, offset + start + word.len());
+                        cursor = start + run[start..].chars().next().map_or(1, char::len_utf8);
+                    }
+                }
+            } else {
+                let mut start = None;
+                for (i, c) in run.char_indices() {
+                    match (c.is_alphanumeric(), start) {
+                        (true, None) => start = Some(i),
+                        (false, Some(s)) => {
+                            push(offset + s, offset + i);
+                            start = None;
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(s) = start {
+                    push(offset + s, offset + run.len());
+                }
+            }
+            offset += run.len();
+        }
+
+        WordTokenStream { tokens, next: 0 }
+    }
+}
+
+impl TokenStream for WordTokenStream {
+    fn advance(&mut self) -> bool {
+        self.next += 1;
+        self.next <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.next - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.next - 1]
+    }
+}
@@ -0,0 +1,127 @@
+//! Forum statistics and leaderboards. Computing them scans whole tables, so
+//! they are recomputed in the background every few minutes and served from
+//! memory.
+
+use crate::database::Database;
+use crate::models::{ForumStats, LeaderboardEntry};
+use anyhow::Result;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// How many days the posts per day series covers.
+pub const POSTS_PER_DAY_DAYS: i32 = 30;
+/// How many members each leaderboard keeps.
+pub const LEADERBOARD_SIZE: i64 = 100;
+const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// What members are ranked by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardMetric {
+    #[default]
+    Reputation,
+    Solutions,
+    Likes,
+}
+
+impl LeaderboardMetric {
+    pub const ALL: [LeaderboardMetric; 3] = [Self::Reputation, Self::Solutions, Self::Likes];
+
+    // Reputation sums every event's points; the others count one kind
+    fn event_type(self) -> Option<&'static str> {
+        match self {
+            Self::Reputation => None,
+            Self::Solutions => Some("solution_accepted"),
+            Self::Likes => Some("like_received"),
+        }
+    }
+}
+
+/// How far back a leaderboard looks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaderboardWindow {
+    Week,
+    #[default]
+    Month,
+    Year,
+    All,
+}
+
+impl LeaderboardWindow {
+    pub const ALL: [LeaderboardWindow; 4] = [Self::Week, Self::Month, Self::Year, Self::All];
+
+    fn since(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Week => Some(now - ChronoDuration::days(7)),
+            Self::Month => Some(now - ChronoDuration::days(30)),
+            Self::Year => Some(now - ChronoDuration::days(365)),
+            Self::All => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Snapshot {
+    forum: Option<ForumStats>,
+    leaderboards: HashMap<(LeaderboardMetric, LeaderboardWindow), Vec<LeaderboardEntry>>,
+}
+
+#[derive(Default)]
+pub struct StatsCache {
+    snapshot: RwLock<Arc<Snapshot>>,
+}
+
+impl StatsCache {
+    pub async fn refresh(&self, db: &Database) -> Result<()> {
+        let forum = db.forum_stats(POSTS_PER_DAY_DAYS).await?;
+
+        let now = Utc::now();
+        let mut leaderboards = HashMap::new();
+        for metric in LeaderboardMetric::ALL {
+            for window in LeaderboardWindow::ALL {
+                let entries = db.leaderboard(metric.event_type(), window.since(now), LEADERBOARD_SIZE).await?;
+                leaderboards.insert((metric, window), entries);
+            }
+        }
+
+        *self.snapshot.write().unwrap() = Arc::new(Snapshot {
+            forum: Some(forum),
+            leaderboards,
+        });
+        Ok(())
+    }
+
+    /// `None` until the first refresh has finished.
+    pub fn forum(&self) -> Option<ForumStats> {
+        self.snapshot.read().unwrap().forum.clone()
+    }
+
+    /// The top `limit` members, or `None` until the first refresh has
+    /// finished.
+    pub fn leaderboard(
+        &self,
+        metric: LeaderboardMetric,
+        window: LeaderboardWindow,
+        limit: usize,
+    ) -> Option<Vec<LeaderboardEntry>> {
+        let snapshot = self.snapshot.read().unwrap().clone();
+        let entries = snapshot.leaderboards.get(&(metric, window))?;
+        Some(entries.iter().take(limit).cloned().collect())
+    }
+}
+
+/// Computes the statistics now and then every five minutes.
+pub fn spawn_refresher(stats: Arc<StatsCache>, db: Arc<Database>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = stats.refresh(&db).await {
+                tracing::warn!("Failed to refresh forum statistics: {}", e);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
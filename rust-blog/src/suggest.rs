@@ -0,0 +1,369 @@
+//! Search-as-you-type suggestions and "did you mean" spelling corrections.
+//!
+//! Titles, tags and usernames are held in memory as an FST keyed by every
+//! word-aligned suffix of the lowercased text, so a prefix lookup also finds
+//! "overflow" in "Stack overflow attacks". A second FST maps the words used
+//! across the site to their frequency; misspelled query words are replaced
+//! by the most frequent word within a small edit distance.
+//!
+//! Titles are rebuilt in the background shortly after content changes;
+//! the vocabulary, which means reading all content, only every few minutes.
+
+use crate::database::Database;
+use crate::models::Suggestion;
+use crate::search::is_cjk;
+use anyhow::Result;
+use fst::automaton::{Levenshtein, Str};
+use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+pub const DEFAULT_LIMIT: usize = 8;
+pub const MAX_LIMIT: usize = 20;
+
+/// Keys are cut to this many characters; longer queries are cut to match.
+const MAX_KEY_CHARS: usize = 64;
+/// How many prefix matches are ranked before picking the best.
+const MAX_CANDIDATES: usize = 500;
+/// Writes usually come in bursts, so wait for them to settle before
+/// rebuilding.
+const REFRESH_DEBOUNCE: Duration = Duration::from_secs(2);
+/// Rebuild now and then anyway, so view counts and reputation stay fresh,
+/// and words from new content are picked up.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(600);
+
+#[derive(Default)]
+struct Snapshot {
+    /// Suffix key -> index into `entries`.
+    titles: Option<Map<Vec<u8>>>,
+    entries: Vec<Suggestion>,
+    /// Word -> number of occurrences, shared by the snapshots taken
+    /// between vocabulary rebuilds.
+    vocabulary: Option<Arc<Map<Vec<u8>>>>,
+}
+
+#[derive(Default)]
+pub struct Suggester {
+    snapshot: RwLock<Arc<Snapshot>>,
+}
+
+impl Suggester {
+    /// Rebuilds the titles, and the vocabulary too when `with_vocabulary`
+    /// is set; otherwise the current vocabulary is kept.
+    pub async fn refresh(&self, db: &Database, with_vocabulary: bool) -> Result<()> {
+        let mut entries = db.list_suggestions().await?;
+        for entry in &mut entries {
+            if entry.kind == "tag" {
+                entry.url = format!("/search?type=post&tag={0}&q={0}", percent_encode(&entry.text));
+            }
+        }
+
+        let titles = title_map(&entries)?;
+        let vocabulary = if with_vocabulary {
+            Some(Arc::new(build_vocabulary(db).await?))
+        } else {
+            self.snapshot.read().unwrap().vocabulary.clone()
+        };
+
+        let snapshot = Snapshot {
+            titles: Some(titles),
+            entries,
+            vocabulary,
+        };
+        *self.snapshot.write().unwrap() = Arc::new(snapshot);
+        Ok(())
+    }
+
+    /// Topics, posts, tags and users whose title or name contains a word
+    /// starting with `query`. Matches at the start of the text come first,
+    /// then the more popular ones.
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<Suggestion> {
+        let snapshot = self.snapshot.read().unwrap().clone();
+        let Some(titles) = &snapshot.titles else {
+            return Vec::new();
+        };
+
+        let query: String = normalize(query).chars().take(MAX_KEY_CHARS).collect();
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<(bool, usize)> = Vec::new();
+        let mut stream = titles.search(Str::new(&query).starts_with()).into_stream();
+        while let Some((_, index)) = stream.next() {
+            let index = index as usize;
+            let at_start = normalize(&snapshot.entries[index].text).starts_with(&query);
+            candidates.push((at_start, index));
+            if candidates.len() >= MAX_CANDIDATES {
+                break;
+            }
+        }
+
+        candidates.sort_by(|a, b| {
+            let (x, y) = (&snapshot.entries[a.1], &snapshot.entries[b.1]);
+            b.0.cmp(&a.0)
+                .then(y.weight.cmp(&x.weight))
+                .then(x.text.len().cmp(&y.text.len()))
+        });
+
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .map(|(_, index)| &snapshot.entries[index])
+            .filter(|entry| seen.insert((entry.kind.as_str(), entry.url.as_str())))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// The query with unknown words replaced by the most frequent known word
+    /// within one edit (two for words longer than five letters), or `None`
+    /// when every word is known or has no close match.
+    pub fn did_you_mean(&self, query: &str) -> Option<String> {
+        let snapshot = self.snapshot.read().unwrap().clone();
+        let vocabulary = snapshot.vocabulary.as_deref()?;
+
+        let mut changed = false;
+        let corrected: Vec<String> = query
+            .split_whitespace()
+            .map(|word| {
+                let lower = word.to_lowercase();
+                let checkable = lower.len() >= 3
+                    && lower.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit());
+                if !checkable || vocabulary.contains_key(&lower) {
+                    return word.to_string();
+                }
+                match closest_word(vocabulary, &lower) {
+                    Some(replacement) => {
+                        changed = true;
+                        replacement
+                    }
+                    None => word.to_string(),
+                }
+            })
+            .collect();
+
+        changed.then(|| corrected.join(" "))
+    }
+}
+
+/// Keeps `suggester` warm: builds it now, then rebuilds the titles after
+/// content changes and everything every few minutes.
+pub fn spawn_refresher(suggester: Arc<Suggester>, db: Arc<Database>) {
+    tokio::spawn(async move {
+        let changes = db.changes();
+        let mut next_full_refresh = Instant::now();
+        loop {
+            let full = Instant::now() >= next_full_refresh;
+            if full {
+                next_full_refresh = Instant::now() + REFRESH_INTERVAL;
+            }
+            if let Err(e) = suggester.refresh(&db, full).await {
+                tracing::warn!("Failed to refresh search suggestions: {}", e);
+            }
+            tokio::select! {
+                _ = changes.notified() => tokio::time::sleep(REFRESH_DEBOUNCE).await,
+                _ = tokio::time::sleep_until(next_full_refresh) => {}
+            }
+        }
+    });
+}
+
+// Every word-aligned suffix of each entry's text, mapped to the entry
+fn title_map(entries: &[Suggestion]) -> Result<Map<Vec<u8>>> {
+    let mut keys: Vec<(String, u64)> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        for suffix in suffixes(&normalize(&entry.text)) {
+            // The index makes keys of identical titles unique
+            keys.push((format!("{}\0{:08x}", suffix, i), i as u64));
+        }
+    }
+    keys.sort_unstable();
+    keys.dedup_by(|a, b| a.0 == b.0);
+    let mut titles = MapBuilder::memory();
+    for (key, value) in keys {
+        titles.insert(key, value)?;
+    }
+    Ok(titles.into_map())
+}
+
+async fn build_vocabulary(db: &Database) -> Result<Map<Vec<u8>>> {
+    let words = db
+        .list_vocabulary()
+        .await?
+        .into_iter()
+        .filter(|w| w.word.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()))
+        .map(|w| (w.word, w.count as u64))
+        .collect();
+    vocabulary_map(words)
+}
+
+fn vocabulary_map(mut words: Vec<(String, u64)>) -> Result<Map<Vec<u8>>> {
+    words.sort_unstable();
+    let mut vocabulary = MapBuilder::memory();
+    for (word, count) in words {
+        vocabulary.insert(word, count)?;
+    }
+    Ok(vocabulary.into_map())
+}
+
+fn closest_word(vocabulary: &Map<Vec<u8>>, word: &str) -> Option<String> {
+    let max_distance = if word.chars().count() > 5 { 2 } else { 1 };
+    let automaton = Levenshtein::new(word, max_distance).ok()?;
+
+    let mut best: Option<(usize, u64, String)> = None;
+    let mut stream = vocabulary.search(automaton).into_stream();
+    while let Some((candidate, count)) = stream.next() {
+        let candidate = String::from_utf8_lossy(candidate).into_owned();
+        let distance = edit_distance(word, &candidate);
+        let better = match &best {
+            Some((d, c, _)) => distance < *d || (distance == *d && count > *c),
+            None => true,
+        };
+        if better {
+            best = Some((distance, count, candidate));
+        }
+    }
+    best.map(|(_, _, word)| word)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .replace('\0', "")
+}
+
+/// The suffixes of `text` that start a word, or a character of a CJK run,
+/// cut to `MAX_KEY_CHARS`.
+fn suffixes(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len())
+        .filter(|&i| {
+            let c = chars[i];
+            let starts_word = c.is_alphanumeric() && (i == 0 || !chars[i - 1].is_alphanumeric());
+            starts_word || is_cjk(c)
+        })
+        .map(|i| chars[i..].iter().take(MAX_KEY_CHARS).collect())
+        .collect()
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kind: &str, text: &str, url: &str, weight: i64) -> Suggestion {
+        Suggestion { kind: kind.to_string(), text: text.to_string(), url: url.to_string(), weight }
+    }
+
+    fn suggester(entries: Vec<Suggestion>, words: &[(&str, u64)]) -> Suggester {
+        let words = words.iter().map(|(word, count)| (word.to_string(), *count)).collect();
+        let snapshot = Snapshot {
+            titles: Some(title_map(&entries).unwrap()),
+            entries,
+            vocabulary: Some(Arc::new(vocabulary_map(words).unwrap())),
+        };
+        Suggester { snapshot: RwLock::new(Arc::new(snapshot)) }
+    }
+
+    fn texts(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn suffixes_start_at_words() {
+        assert_eq!(suffixes("stack overflow-attacks"), ["stack overflow-attacks", "overflow-attacks", "attacks"]);
+        assert!(suffixes("").is_empty());
+    }
+
+    #[test]
+    fn suffixes_start_at_every_cjk_character() {
+        assert_eq!(suffixes("rop 栈溢出"), ["rop 栈溢出", "栈溢出", "溢出", "出"]);
+    }
+
+    #[test]
+    fn suffixes_are_cut_to_the_key_length() {
+        let text = format!("a {}", "b".repeat(MAX_KEY_CHARS * 2));
+        let keys = suffixes(&text);
+        assert_eq!(keys.len(), 2);
+        assert!(keys.iter().all(|key| key.chars().count() == MAX_KEY_CHARS));
+    }
+
+    #[test]
+    fn edit_distances_count_characters() {
+        assert_eq!(edit_distance("overflow", "overflow"), 0);
+        assert_eq!(edit_distance("overflw", "overflow"), 1);
+        assert_eq!(edit_distance("heap", "haep"), 2);
+        assert_eq!(edit_distance("栈溢", "栈溢出"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn closer_then_more_frequent_words_win() {
+        let suggester = suggester(Vec::new(), &[("heap", 3), ("help", 50), ("hello", 1), ("heal", 10)]);
+        // "hea" is one edit from heap and heal, and heal is used more
+        assert_eq!(suggester.did_you_mean("hea bug").as_deref(), Some("heal bug"));
+        assert_eq!(suggester.did_you_mean("heapp"), Some("heap".to_string()));
+        assert_eq!(suggester.did_you_mean("heap"), None);
+        assert_eq!(suggester.did_you_mean("zzzzz"), None);
+    }
+
+    #[test]
+    fn identical_titles_are_both_suggested() {
+        let suggester = suggester(
+            vec![entry("topic", "Heap basics", "/forum/t/1", 1), entry("topic", "Heap basics", "/forum/t/2", 1)],
+            &[],
+        );
+        let urls: Vec<String> = suggester.suggest("heap", 10).into_iter().map(|s| s.url).collect();
+        assert_eq!(urls.len(), 2);
+        assert!(urls.contains(&"/forum/t/1".to_string()) && urls.contains(&"/forum/t/2".to_string()));
+    }
+
+    #[test]
+    fn matches_at_the_start_come_first_then_popular_ones() {
+        let suggester = suggester(
+            vec![
+                entry("topic", "Advanced heap exploitation", "/forum/t/1", 100),
+                entry("topic", "Heap basics", "/forum/t/2", 1),
+                entry("post", "Heap internals", "/posts/3", 5),
+                entry("topic", "The heap", "/forum/t/4", 50),
+            ],
+            &[],
+        );
+        assert_eq!(
+            texts(&suggester.suggest("hea", 10)),
+            ["Heap internals", "Heap basics", "Advanced heap exploitation", "The heap"]
+        );
+        assert_eq!(texts(&suggester.suggest("HEAP b", 10)), ["Heap basics"]);
+        assert_eq!(suggester.suggest("hea", 1).len(), 1);
+        assert!(suggester.suggest("  ", 10).is_empty());
+    }
+}
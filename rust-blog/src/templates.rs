@@ -1,29 +1,97 @@
-use crate::models::{Post, Category, TopicWithDetails, ReplyWithDetails, CategoryWithStats};
+use crate::avatars;
+use crate::badges::{AwardedBadge, Badge, BadgeSummary};
+use crate::markdown::{self, ResponsiveImage, TocEntry};
+use crate::mentions;
+use crate::models::{
+    Post, Category, TopicWithDetails, ReplyWithDetails, CategoryWithStats, BadgeHolder,
+    DailyCount, ForumStats, LeaderboardEntry, UserProfile, UserStats, UserTopic, UserReply,
+    WikiPage, WikiRevision, WikiRevisionSummary, WikiBreadcrumb, TranslationLink, Discussion,
+    SearchResults, ChallengeFile
+};
+use crate::wiki::DiffLine;
 use askama::Template;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
     pub posts: Vec<Post>,
+    pub lang: String,
+    pub translations: Vec<TranslationLink>,
 }
 
 #[derive(Template)]
 #[template(path = "post.html")]
 pub struct PostTemplate {
     pub post: Post,
+    pub translations: Vec<TranslationLink>,
+    pub discussion: Option<Discussion>,
+    /// JSON identifying the post for the "Discuss this page" request.
+    pub discussion_target: String,
+    pub challenge_files: Vec<ChallengeFile>,
 }
 
 #[derive(Template)]
 #[template(path = "admin.html")]
 pub struct AdminTemplate {}
 
+#[derive(Template)]
+#[template(path = "translations.html")]
+pub struct TranslationReportTemplate {}
+
+#[derive(Template)]
+#[template(path = "trash.html")]
+pub struct TrashTemplate {}
+
+#[derive(Template)]
+#[template(path = "reports.html")]
+pub struct ReportsTemplate {}
+
+#[derive(Template)]
+#[template(path = "audit.html")]
+pub struct AuditLogTemplate {}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    /// Where the search form submits: `/search` or `/forum/search`.
+    pub action: String,
+    pub query: String,
+    /// The `type` filter as submitted, selected in the filter dropdown.
+    pub selected_types: String,
+    pub type_options: Vec<(String, String)>,
+    pub results: Option<SearchResults>,
+    pub error: Option<String>,
+}
+
 // Forum Templates
 
 #[derive(Template)]
 #[template(path = "forum/index.html")]
 pub struct ForumIndexTemplate {
     pub categories: Vec<CategoryWithStats>,
+    /// `None` until the statistics have first been computed.
+    pub stats: Option<ForumStats>,
+    /// This month's top members by reputation.
+    pub top_members: Vec<LeaderboardEntry>,
+}
+
+impl ForumIndexTemplate {
+    /// A day's bar in the posts per day chart, as a percentage of the
+    /// busiest day.
+    pub fn bar_height(&self, day: &DailyCount) -> i64 {
+        let busiest = self
+            .stats
+            .as_ref()
+            .and_then(|stats| stats.posts_per_day.iter().map(|day| day.count).max())
+            .unwrap_or(0);
+        if busiest == 0 {
+            0
+        } else {
+            day.count * 100 / busiest
+        }
+    }
 }
 
 #[derive(Template)]
@@ -31,8 +99,18 @@ pub struct ForumIndexTemplate {
 pub struct CategoryTemplate {
     pub category: Category,
     pub topics: Vec<TopicWithDetails>,
+    /// The solved/unsolved filter, if any.
+    pub solved: Option<bool>,
     pub current_page: u64,
     pub has_next: bool,
+    /// Avatars of the topic authors who have one.
+    pub avatars: HashMap<Uuid, String>,
+}
+
+impl CategoryTemplate {
+    pub fn avatar_for(&self, user_id: &Uuid) -> String {
+        avatars::sized_url(self.avatars.get(user_id).map(String::as_str), 64)
+    }
 }
 
 #[derive(Template)]
@@ -40,6 +118,65 @@ pub struct CategoryTemplate {
 pub struct TopicTemplate {
     pub topic: TopicWithDetails,
     pub replies: Vec<ReplyWithDetails>,
+    /// Shown under the opening post.
+    pub solution: Option<ReplyWithDetails>,
+    pub current_page: u64,
+    pub has_next: bool,
+    /// Badges of the members on this page, for their user cards.
+    pub user_badges: HashMap<Uuid, Vec<&'static Badge>>,
+    /// Avatars of the members on this page who have one.
+    pub avatars: HashMap<Uuid, String>,
+    /// The members mentioned on this page, by lowercase username.
+    pub mentioned: HashMap<String, String>,
+    /// The attached images shown on this page that have variants.
+    pub images: HashMap<Uuid, ResponsiveImage>,
+}
+
+impl TopicTemplate {
+    /// Reply content rendered from Markdown, with its mentions linked.
+    pub fn content_html(&self, content: &str) -> String {
+        mentions::link(&markdown::render_safe(content, &self.images), &self.mentioned)
+    }
+
+    pub fn badges_for(&self, user_id: &Uuid) -> &[&'static Badge] {
+        self.user_badges.get(user_id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn avatar_for(&self, user_id: &Uuid) -> String {
+        avatars::sized_url(self.avatars.get(user_id).map(String::as_str), 64)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "forum/profile.html")]
+pub struct ProfileTemplate {
+    pub username: String,
+    pub is_moderator: bool,
+    pub profile: UserProfile,
+    pub stats: UserStats,
+    pub trust_level: &'static str,
+    pub badges: Vec<AwardedBadge>,
+    pub topics: Vec<UserTopic>,
+    pub replies: Vec<UserReply>,
+}
+
+impl ProfileTemplate {
+    pub fn avatar(&self) -> String {
+        avatars::sized_url(self.profile.avatar_url.as_deref(), 256)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "forum/badges.html")]
+pub struct BadgesTemplate {
+    pub badges: Vec<BadgeSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "forum/badge.html")]
+pub struct BadgeTemplate {
+    pub badge: &'static Badge,
+    pub holders: Vec<BadgeHolder>,
     pub current_page: u64,
     pub has_next: bool,
 }
@@ -49,4 +186,61 @@ pub struct TopicTemplate {
 pub struct CreateTopicTemplate {
     pub categories: Vec<CategoryWithStats>,
     pub selected_category: Option<Uuid>,
-}
\ No newline at end of file
+}
+
+#[derive(Template)]
+#[template(path = "forum/notifications.html")]
+pub struct NotificationsTemplate {}
+
+// Wiki Templates
+
+#[derive(Template)]
+#[template(path = "wiki/page.html")]
+pub struct WikiPageTemplate {
+    pub lang: String,
+    pub page: WikiPage,
+    pub url: String,
+    pub content_html: String,
+    pub toc: Vec<TocEntry>,
+    pub breadcrumbs: Vec<WikiBreadcrumb>,
+    pub children: Vec<WikiPage>,
+    pub translations: Vec<TranslationLink>,
+    pub discussion: Option<Discussion>,
+    pub discussion_target: String,
+}
+
+#[derive(Template)]
+#[template(path = "wiki/edit.html")]
+pub struct WikiEditTemplate {
+    pub lang: String,
+    pub path: String,
+    pub url: String,
+    pub title: String,
+    pub page: Option<WikiPage>,
+    pub breadcrumbs: Vec<WikiBreadcrumb>,
+    pub translations: Vec<TranslationLink>,
+}
+
+#[derive(Template)]
+#[template(path = "wiki/history.html")]
+pub struct WikiHistoryTemplate {
+    pub lang: String,
+    pub page: WikiPage,
+    pub url: String,
+    pub revisions: Vec<WikiRevisionSummary>,
+    pub breadcrumbs: Vec<WikiBreadcrumb>,
+    pub translations: Vec<TranslationLink>,
+}
+
+#[derive(Template)]
+#[template(path = "wiki/diff.html")]
+pub struct WikiDiffTemplate {
+    pub lang: String,
+    pub page: WikiPage,
+    pub url: String,
+    pub from: WikiRevision,
+    pub to: WikiRevision,
+    pub lines: Vec<DiffLine>,
+    pub breadcrumbs: Vec<WikiBreadcrumb>,
+    pub translations: Vec<TranslationLink>,
+}
@@ -0,0 +1,33 @@
+//! Deleted posts, topics and replies stay in the trash, where moderators can
+//! restore them, for `TRASH_RETENTION_DAYS` (30 by default) before they are
+//! purged for good.
+
+use crate::database::Database;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_RETENTION_DAYS: i32 = 30;
+/// How often the trash is checked for items past their retention.
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+pub fn retention_days() -> i32 {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Purges the trash now and then every hour.
+pub fn spawn_purger(db: Arc<Database>) {
+    tokio::spawn(async move {
+        loop {
+            match db.purge_trash(retention_days()).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Purged {} items from the trash", count),
+                Err(e) => tracing::warn!("Failed to purge the trash: {}", e),
+            }
+            tokio::time::sleep(PURGE_INTERVAL).await;
+        }
+    });
+}
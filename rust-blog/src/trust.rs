@@ -0,0 +1,248 @@
+//! Trust levels. Members earn levels from their activity, recomputed by a
+//! background job, and each level unlocks more of the forum. Admins can pin
+//! a member at any level, and moderators are trusted with everything.
+
+use crate::{
+    auth::{is_moderator, optional_claims},
+    database::Database,
+    markdown,
+    models::Claims,
+    AppState,
+};
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{Event, Tag};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+pub const NEW: i16 = 0;
+pub const BASIC: i16 = 1;
+pub const MEMBER: i16 = 2;
+pub const REGULAR: i16 = 3;
+/// Only given by admins.
+pub const LEADER: i16 = 4;
+
+/// How often levels are recomputed.
+const RECOMPUTE_INTERVAL: Duration = Duration::from_secs(3600);
+/// How often a member's requests update when they were last seen.
+const VISIT_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+/// How many members' visits are remembered before the stale ones are dropped.
+const VISITS_PRUNED_AT: usize = 10_000;
+
+/// What a member needs to earn a level.
+pub struct TrustRequirement {
+    pub level: i16,
+    pub days_since_joined: i32,
+    pub days_visited: i32,
+    pub replies: i64,
+    pub likes_received: i64,
+}
+
+pub const REQUIREMENTS: [TrustRequirement; 3] = [
+    TrustRequirement { level: BASIC, days_since_joined: 1, days_visited: 2, replies: 1, likes_received: 0 },
+    TrustRequirement { level: MEMBER, days_since_joined: 7, days_visited: 15, replies: 10, likes_received: 5 },
+    TrustRequirement { level: REGULAR, days_since_joined: 30, days_visited: 50, replies: 50, likes_received: 30 },
+];
+
+/// Something a member has to earn.
+#[derive(Debug, Clone, Copy)]
+pub enum Capability {
+    PostLinks,
+    PostImages,
+    AttachFiles,
+    Flag,
+}
+
+impl Capability {
+    pub fn min_level(self) -> i16 {
+        match self {
+            Capability::PostLinks | Capability::PostImages | Capability::AttachFiles | Capability::Flag => BASIC,
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Capability::PostLinks => "Posting links",
+            Capability::PostImages => "Posting images",
+            Capability::AttachFiles => "Attaching files",
+            Capability::Flag => "Reporting content",
+        }
+    }
+}
+
+pub fn level_name(level: i16) -> &'static str {
+    match level {
+        NEW => "New",
+        BASIC => "Basic",
+        MEMBER => "Member",
+        REGULAR => "Regular",
+        _ => "Leader",
+    }
+}
+
+fn needs_level(what: &str, level: i16) -> Response {
+    let message = format!("{} needs trust level {} ({})", what, level, level_name(level));
+    (StatusCode::FORBIDDEN, message).into_response()
+}
+
+/// The level in force for the user; moderators have every level.
+pub async fn level_of(state: &AppState, claims: &Claims) -> Result<i16, Response> {
+    if is_moderator(&claims.role) {
+        return Ok(LEADER);
+    }
+    let user_id = claims
+        .sub
+        .parse::<Uuid>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid user ID").into_response())?;
+    state
+        .db
+        .get_trust_level(user_id)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response())
+}
+
+pub async fn require(state: &AppState, claims: &Claims, capability: Capability) -> Result<(), Response> {
+    let min_level = capability.min_level();
+    if level_of(state, claims).await? < min_level {
+        return Err(needs_level(capability.description(), min_level));
+    }
+    Ok(())
+}
+
+/// Refuses topic or reply content with links or images the user hasn't
+/// earned.
+pub async fn check_content(state: &AppState, claims: &Claims, content: &str) -> Result<(), Response> {
+    if contains_image(content) {
+        require(state, claims, Capability::PostImages).await?;
+    }
+    if contains_link(content) {
+        require(state, claims, Capability::PostLinks).await?;
+    }
+    Ok(())
+}
+
+/// Refuses starting a topic in a category above the user's level.
+pub async fn check_category(state: &AppState, claims: &Claims, min_level: i16) -> Result<(), Response> {
+    if level_of(state, claims).await? < min_level {
+        return Err(needs_level("Starting topics in this category", min_level));
+    }
+    Ok(())
+}
+
+// Markdown images in any form, and HTML images
+fn contains_image(content: &str) -> bool {
+    markdown::parse_safe(content).any(|event| match event {
+        Event::Start(Tag::Image { .. }) => true,
+        Event::Html(html) | Event::InlineHtml(html) => html.to_lowercase().contains("<img"),
+        _ => false,
+    })
+}
+
+// Markdown links in any form, HTML links, and bare URLs
+fn contains_link(content: &str) -> bool {
+    markdown::parse_safe(content).any(|event| match event {
+        Event::Start(Tag::Link { .. }) => true,
+        Event::Html(html) | Event::InlineHtml(html) => {
+            let html = html.to_lowercase();
+            html.contains("<a ") || has_url(&html)
+        }
+        Event::Text(text) | Event::Code(text) => has_url(&text.to_lowercase()),
+        _ => false,
+    })
+}
+
+fn has_url(text: &str) -> bool {
+    ["http://", "https://", "www."].iter().any(|pattern| text.contains(pattern))
+}
+
+/// Recomputes levels now and then every hour.
+pub fn spawn_recomputer(db: Arc<Database>) {
+    tokio::spawn(async move {
+        loop {
+            match db.recompute_trust_levels(&REQUIREMENTS).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Raised the trust level of {} members", count),
+                Err(e) => tracing::warn!("Failed to recompute trust levels: {}", e),
+            }
+            tokio::time::sleep(RECOMPUTE_INTERVAL).await;
+        }
+    });
+}
+
+/// Counts the days each signed-in member visits on, which trust levels are
+/// partly earned by, and when they were last seen. A member's requests are
+/// only recorded every few minutes, and on the first one of a day.
+pub async fn track_visits(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let user_id = optional_claims(request.headers()).and_then(|claims| claims.sub.parse::<Uuid>().ok());
+    if let Some(user_id) = user_id.filter(|user_id| visit_due(*user_id, Utc::now())) {
+        if let Err(e) = state.db.record_visit(user_id).await {
+            tracing::warn!("Failed to record a visit: {}", e);
+        }
+    }
+    next.run(request).await
+}
+
+// Remembers when each member's visit was last recorded
+fn visit_due(user_id: Uuid, now: DateTime<Utc>) -> bool {
+    static RECORDED: OnceLock<Mutex<HashMap<Uuid, DateTime<Utc>>>> = OnceLock::new();
+    let mut recorded = RECORDED.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+
+    let recent = |at: &DateTime<Utc>| at.date_naive() == now.date_naive() && now - *at < VISIT_INTERVAL;
+    if recorded.get(&user_id).is_some_and(recent) {
+        return false;
+    }
+    if recorded.len() >= VISITS_PRUNED_AT {
+        recorded.retain(|_, at| recent(at));
+    }
+    recorded.insert(user_id, now);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contains_image, contains_link};
+
+    #[test]
+    fn plain_text_has_no_links() {
+        assert!(!contains_link("Try a bigger [buffer] and see"));
+        assert!(!contains_image("No pictures here!"));
+    }
+
+    #[test]
+    fn inline_links_and_images_are_found() {
+        assert!(contains_link("[click](https://evil.example)"));
+        assert!(contains_link("[click](/forum)"));
+        assert!(contains_image("![x](/attachments/1)"));
+        assert!(!contains_link("![x](/attachments/1)"));
+    }
+
+    #[test]
+    fn reference_links_and_images_are_found() {
+        assert!(contains_link("[click][a]\n\n[a]: //evil.example"));
+        assert!(contains_link("[a]\n\n[a]: //evil.example"));
+        assert!(contains_image("![x][a]\n\n[a]: //evil.example/x.png"));
+    }
+
+    #[test]
+    fn autolinks_and_bare_urls_are_found() {
+        assert!(contains_link("<https://evil.example>"));
+        assert!(contains_link("<someone@evil.example>"));
+        assert!(contains_link("see www.evil.example"));
+        assert!(contains_link("`curl https://evil.example`"));
+    }
+
+    #[test]
+    fn html_links_and_images_are_found() {
+        assert!(contains_link("<a href=\"//evil.example\">click</a>"));
+        assert!(contains_link("text <A HREF=//evil.example>click</A>"));
+        assert!(contains_image("<img src=//evil.example/x.png>"));
+        assert!(contains_image("text <IMG SRC=x>"));
+    }
+}
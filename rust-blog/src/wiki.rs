@@ -0,0 +1,81 @@
+//! Path and revision helpers shared by the wiki handlers and the importer.
+
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+pub fn wiki_url(lang: &str, path: &str) -> String {
+    if path.is_empty() {
+        format!("/{}/wiki", lang)
+    } else {
+        format!("/{}/wiki/{}", lang, path)
+    }
+}
+
+pub fn parent_path(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Paths of every ancestor of `path`, from the wiki root down to its parent.
+pub fn ancestor_paths(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        return Vec::new();
+    }
+
+    let mut ancestors = vec![String::new()];
+    let segments: Vec<&str> = path.split('/').collect();
+    for end in 1..segments.len() {
+        ancestors.push(segments[..end].join("/"));
+    }
+    ancestors
+}
+
+/// Cleans up a user supplied page path, rejecting empty segments, `.`/`..`
+/// and whitespace so paths stay usable as URLs.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        return Some(String::new());
+    }
+
+    let segments: Vec<&str> = path.split('/').collect();
+    let valid = segments.iter().all(|segment| {
+        !segment.is_empty()
+            && *segment != "."
+            && *segment != ".."
+            && !segment.chars().any(|c| c.is_whitespace() || c == '?' || c == '#')
+    });
+    valid.then(|| segments.join("/"))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: &'static str,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// Line-by-line diff between two revisions of a page.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Delete => "delete",
+                ChangeTag::Insert => "insert",
+                ChangeTag::Equal => "equal",
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
+
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .to_string()
+}
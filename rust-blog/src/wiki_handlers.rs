@@ -0,0 +1,557 @@
+use crate::{
+    attachments,
+    audit::{self, AuditEvent, ClientIp},
+    auth::require_role,
+    forum_handlers::DISCUSSION_PREVIEW_REPLIES,
+    i18n::{self, DEFAULT_LANG},
+    markdown,
+    models::{
+        Claims, CreateWikiPage, ImportWikiRequest, RollbackWikiPage, TranslationLink,
+        UpdateWikiPage, UpsertWikiPage, UserRole, WikiBreadcrumb, WikiConflict, WikiPage, WikiRevision
+    },
+    templates::{WikiDiffTemplate, WikiEditTemplate, WikiHistoryTemplate, WikiPageTemplate},
+    wiki::{self, wiki_url},
+    wiki_import::{self, ImportOptions},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{Html, IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use askama::Template;
+use serde::Deserialize;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct WikiQuery {
+    pub action: Option<String>,
+    pub from: Option<i32>,
+    pub to: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub lang: Option<String>,
+    pub from: Option<i32>,
+    pub to: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct LangQuery {
+    pub lang: Option<String>,
+}
+
+// Unprefixed wiki URLs (/wiki, /wiki/*path) redirect to the language the
+// client prefers, out of the languages the page exists in
+pub async fn wiki_redirect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> impl IntoResponse {
+    let path = uri.path().trim_start_matches("/wiki").trim_matches('/');
+    let available = match state.db.list_wiki_pages_at_path(path).await {
+        Ok(pages) => pages.into_iter().map(|page| page.lang).collect::<Vec<String>>(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let available: Vec<&str> = available.iter().map(String::as_str).collect();
+
+    let lang = i18n::negotiate(&headers, &available)
+        .or_else(|| available.iter().find_map(|lang| i18n::normalize_lang(lang)))
+        .unwrap_or_else(|| i18n::preferred_lang(&headers));
+    let target = match uri.query() {
+        Some(query) => format!("/{}{}?{}", lang, uri.path(), query),
+        None => format!("/{}{}", lang, uri.path()),
+    };
+    ([(header::VARY, "Accept-Language")], Redirect::temporary(&target)).into_response()
+}
+
+// Wiki home page in one language, e.g. /zh/wiki
+pub async fn wiki_index(
+    State(state): State<AppState>,
+    Path(lang): Path<String>,
+    Query(params): Query<WikiQuery>,
+) -> impl IntoResponse {
+    match i18n::normalize_lang(&lang).filter(|normalized| *normalized == lang) {
+        Some(lang) => wiki_action(&state, lang, "", params).await,
+        None => (StatusCode::NOT_FOUND, "Unknown language").into_response(),
+    }
+}
+
+// Wiki page by language and path, e.g. /en/wiki/pwn/linux/user-mode
+// `?action=edit|history|diff` switches to the editor, the history or a diff
+pub async fn wiki_page(
+    State(state): State<AppState>,
+    Path((lang, path)): Path<(String, String)>,
+    Query(params): Query<WikiQuery>,
+) -> impl IntoResponse {
+    let Some(lang) = i18n::normalize_lang(&lang).filter(|normalized| *normalized == lang) else {
+        return (StatusCode::NOT_FOUND, "Unknown language").into_response();
+    };
+    match wiki::normalize_path(&path) {
+        Some(path) => wiki_action(&state, lang, &path, params).await,
+        None => (StatusCode::BAD_REQUEST, "Invalid wiki path").into_response(),
+    }
+}
+
+async fn wiki_action(state: &AppState, lang: &str, path: &str, params: WikiQuery) -> Response {
+    let page = match state.db.get_wiki_page_by_path(lang, path).await {
+        Ok(page) => page,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let breadcrumbs = match breadcrumbs(state, lang, path).await {
+        Ok(breadcrumbs) => breadcrumbs,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+    let translations = match &page {
+        Some(page) => match translations(state, page).await {
+            Ok(translations) => translations,
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        None => Vec::new(),
+    };
+
+    match (params.action.as_deref(), page) {
+        (Some("edit"), page) => {
+            let template = WikiEditTemplate {
+                lang: lang.to_string(),
+                path: path.to_string(),
+                url: wiki_url(lang, path),
+                title: page.as_ref().map(|p| p.title.clone()).unwrap_or_default(),
+                page,
+                breadcrumbs,
+                translations,
+            };
+            Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+        }
+        (_, None) => (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        (Some("history"), Some(page)) => match state.db.list_wiki_revisions(page.id).await {
+            Ok(revisions) => {
+                let template = WikiHistoryTemplate {
+                    lang: lang.to_string(),
+                    url: wiki_url(&page.lang, &page.path),
+                    page,
+                    revisions,
+                    breadcrumbs,
+                    translations,
+                };
+                Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+            }
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        (Some("diff"), Some(page)) => {
+            let to = params.to.unwrap_or(page.revision);
+            let from = params.from.unwrap_or(to - 1);
+            let revisions = (
+                diff_revision(state, &page, from).await,
+                diff_revision(state, &page, to).await,
+            );
+            match revisions {
+                (Ok(Some(from)), Ok(Some(to))) => {
+                    let template = WikiDiffTemplate {
+                        lang: lang.to_string(),
+                        url: wiki_url(&page.lang, &page.path),
+                        lines: wiki::diff_lines(&from.content, &to.content),
+                        page,
+                        from,
+                        to,
+                        breadcrumbs,
+                        translations,
+                    };
+                    Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+                }
+                (Ok(_), Ok(_)) => (StatusCode::NOT_FOUND, "Revision not found").into_response(),
+                _ => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+            }
+        }
+        (_, Some(page)) => match (
+            state.db.list_wiki_children(page.id).await,
+            state.db.get_discussion("wiki", page.id, DISCUSSION_PREVIEW_REPLIES).await,
+        ) {
+            (Ok(children), Ok(discussion)) => {
+                // Without the variants, images are rendered as written
+                let images = attachments::responsive_images(&state.db, &markdown::attachment_ids(&page.content))
+                    .await
+                    .unwrap_or_default();
+                let (content_html, toc) = markdown::render_with_toc(&page.content, &images);
+                let discussion_target = serde_json::json!({
+                    "content_type": "wiki",
+                    "lang": page.lang,
+                    "path": page.path,
+                })
+                .to_string();
+                let template = WikiPageTemplate {
+                    lang: lang.to_string(),
+                    url: wiki_url(&page.lang, &page.path),
+                    content_html,
+                    toc,
+                    page,
+                    breadcrumbs,
+                    children,
+                    translations,
+                    discussion,
+                    discussion_target,
+                };
+                Html(template.render().unwrap_or_else(|_| "Error rendering template".to_string())).into_response()
+            }
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+    }
+}
+
+async fn breadcrumbs(state: &AppState, lang: &str, path: &str) -> anyhow::Result<Vec<WikiBreadcrumb>> {
+    let ancestors = state.db.get_wiki_pages_by_paths(lang, &wiki::ancestor_paths(path)).await?;
+    Ok(ancestors
+        .into_iter()
+        .map(|page| WikiBreadcrumb {
+            url: wiki_url(&page.lang, &page.path),
+            title: page.title,
+        })
+        .collect())
+}
+
+// Every language version of a page, itself included, for hreflang links
+async fn translations(state: &AppState, page: &WikiPage) -> anyhow::Result<Vec<TranslationLink>> {
+    let pages = state.db.list_wiki_translations(page.translation_group).await?;
+    Ok(pages
+        .into_iter()
+        .map(|translation| TranslationLink {
+            url: wiki_url(&translation.lang, &translation.path),
+            lang: translation.lang,
+            title: translation.title,
+        })
+        .collect())
+}
+
+// Revision 0 is the empty page before the first revision, so the first
+// revision can be diffed too
+async fn diff_revision(state: &AppState, page: &WikiPage, revision: i32) -> anyhow::Result<Option<WikiRevision>> {
+    if revision != 0 {
+        return state.db.get_wiki_revision(page.id, revision).await;
+    }
+    Ok(Some(WikiRevision {
+        id: Uuid::nil(),
+        page_id: page.id,
+        revision: 0,
+        title: String::new(),
+        content: String::new(),
+        edited_by: None,
+        edit_summary: None,
+        created_at: page.created_at,
+    }))
+}
+
+fn api_lang(lang: Option<&str>) -> Result<&'static str, (StatusCode, &'static str)> {
+    match lang {
+        None => Ok(DEFAULT_LANG),
+        Some(lang) => i18n::normalize_lang(lang)
+            .ok_or((StatusCode::BAD_REQUEST, "Unsupported language")),
+    }
+}
+
+// API Endpoints
+
+pub async fn api_get_wiki_page(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<LangQuery>,
+) -> impl IntoResponse {
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => Json(page).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_create_wiki_page(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(page): Json<CreateWikiPage>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(status) = require_role(UserRole::Editor)(claims) {
+        return (status, "Editor access required").into_response();
+    }
+
+    let Some(path) = wiki::normalize_path(&page.path) else {
+        return (StatusCode::BAD_REQUEST, "Invalid wiki path").into_response();
+    };
+    let lang = match api_lang(page.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    match state.db.get_wiki_page_by_path(lang, &path).await {
+        Ok(Some(_)) => return (StatusCode::CONFLICT, "A page already exists at this path").into_response(),
+        Ok(None) => {}
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
+    // Every page except the wiki root hangs off an existing parent
+    let parent_id = if path.is_empty() {
+        None
+    } else {
+        match state.db.get_wiki_page_by_path(lang, wiki::parent_path(&path)).await {
+            Ok(Some(parent)) => Some(parent.id),
+            Ok(None) => return (StatusCode::BAD_REQUEST, "Parent page does not exist").into_response(),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        }
+    };
+
+    let upsert = UpsertWikiPage {
+        parent_id,
+        path,
+        lang: lang.to_string(),
+        title: page.title,
+        content: page.content,
+        source_path: None,
+        nav_order: i32::MAX,
+    };
+
+    match state.db.create_wiki_page(upsert, Some(user_id), page.edit_summary.as_deref()).await {
+        Ok(created_page) => (StatusCode::CREATED, Json(created_page)).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create wiki page").into_response(),
+    }
+}
+
+pub async fn api_update_wiki_page(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(path): Path<String>,
+    Query(params): Query<LangQuery>,
+    Json(update): Json<UpdateWikiPage>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(status) = require_role(UserRole::Editor)(claims) {
+        return (status, "Editor access required").into_response();
+    }
+
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    let page = match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => page,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    save_wiki_edit(&state, page, update, user_id).await
+}
+
+pub async fn api_rollback_wiki_page(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(path): Path<String>,
+    Query(params): Query<LangQuery>,
+    Json(rollback): Json<RollbackWikiPage>,
+) -> impl IntoResponse {
+    let user_id = match claims.sub.parse::<Uuid>() {
+        Ok(id) => id,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid user ID").into_response(),
+    };
+    if let Err(status) = require_role(UserRole::Editor)(claims.clone()) {
+        return (status, "Editor access required").into_response();
+    }
+
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    let page = match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => page,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let revision = match state.db.get_wiki_revision(page.id, rollback.revision).await {
+        Ok(Some(revision)) => revision,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Revision not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    // A rollback is saved as a new revision, so history is never rewritten
+    let update = UpdateWikiPage {
+        title: Some(revision.title),
+        content: Some(revision.content),
+        edit_summary: Some(format!("Rolled back to revision {}", revision.revision)),
+        base_revision: rollback.base_revision,
+    };
+
+    let event = AuditEvent::new("wiki.rollback", "wiki", page.id)
+        .before(&serde_json::json!({ "lang": page.lang, "path": page.path, "revision": page.revision }))
+        .after(&serde_json::json!({ "restored_revision": revision.revision }));
+    let response = save_wiki_edit(&state, page, update, user_id).await;
+    if response.status().is_success() {
+        audit::record(&state, &claims, &ip, event).await;
+    }
+    response
+}
+
+async fn save_wiki_edit(state: &AppState, page: WikiPage, update: UpdateWikiPage, user_id: Uuid) -> Response {
+    match state.db.update_wiki_page(page.id, update, user_id).await {
+        Ok(Some(page)) => Json(page).into_response(),
+        Ok(None) => {
+            // Someone else saved first; report the revision they produced
+            let current_revision = match state.db.get_wiki_page_by_path(&page.lang, &page.path).await {
+                Ok(Some(current)) => current.revision,
+                _ => page.revision,
+            };
+            let conflict = WikiConflict {
+                message: "This page was changed by someone else while you were editing.".to_string(),
+                current_revision,
+            };
+            (StatusCode::CONFLICT, Json(conflict)).into_response()
+        }
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to update wiki page").into_response(),
+    }
+}
+
+pub async fn api_delete_wiki_page(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Path(path): Path<String>,
+    Query(params): Query<LangQuery>,
+) -> impl IntoResponse {
+    if let Err(status) = require_role(UserRole::Editor)(claims.clone()) {
+        return (status, "Editor access required").into_response();
+    }
+
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    let page = match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => page,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    // Deleting a section would orphan its children
+    match state.db.list_wiki_children(page.id).await {
+        Ok(children) if !children.is_empty() => {
+            return (StatusCode::CONFLICT, "Move or delete the child pages first").into_response();
+        }
+        Ok(_) => {}
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
+    match state.db.delete_wiki_page(page.id).await {
+        Ok(true) => {
+            audit::record(&state, &claims, &ip, AuditEvent::new("wiki.delete", "wiki", page.id).before(&page)).await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete wiki page").into_response(),
+    }
+}
+
+pub async fn api_wiki_history(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<LangQuery>,
+) -> impl IntoResponse {
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => match state.db.list_wiki_revisions(page.id).await {
+            Ok(revisions) => Json(revisions).into_response(),
+            Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+        },
+        Ok(None) => (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_wiki_diff(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    Query(params): Query<DiffQuery>,
+) -> impl IntoResponse {
+    let lang = match api_lang(params.lang.as_deref()) {
+        Ok(lang) => lang,
+        Err(error) => return error.into_response(),
+    };
+
+    let page = match state.db.get_wiki_page_by_path(lang, path.trim_matches('/')).await {
+        Ok(Some(page)) => page,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Wiki page not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    };
+
+    let to = params.to.unwrap_or(page.revision);
+    let from = params.from.unwrap_or(to - 1);
+    match (
+        diff_revision(&state, &page, from).await,
+        diff_revision(&state, &page, to).await,
+    ) {
+        (Ok(Some(old)), Ok(Some(new))) => Json(serde_json::json!({
+            "from": old.revision,
+            "to": new.revision,
+            "diff": wiki::unified_diff(
+                &old.content,
+                &new.content,
+                &format!("revision {}", old.revision),
+                &format!("revision {}", new.revision),
+            ),
+        }))
+        .into_response(),
+        (Ok(_), Ok(_)) => (StatusCode::NOT_FOUND, "Revision not found").into_response(),
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+}
+
+pub async fn api_import_wiki(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Extension(ip): Extension<ClientIp>,
+    Json(request): Json<ImportWikiRequest>,
+) -> impl IntoResponse {
+    // Only admins can import documentation trees
+    if !matches!(claims.role, UserRole::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let options = ImportOptions {
+        mkdocs_path: PathBuf::from(request.mkdocs_path),
+        path_prefix: request.path_prefix.unwrap_or_default(),
+        asset_base_url: request
+            .asset_base_url
+            .unwrap_or_else(|| wiki_import::DEFAULT_ASSET_BASE_URL.to_string()),
+        lang: request.lang,
+        force: request.force,
+    };
+
+    match wiki_import::import_mkdocs(&state.db, &options).await {
+        Ok(report) => {
+            let event = AuditEvent::new("wiki.import", "wiki", options.mkdocs_path.display()).after(&report);
+            audit::record(&state, &claims, &ip, event).await;
+            Json(report).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Wiki import failed: {:#}", e);
+            (StatusCode::BAD_REQUEST, "Failed to import wiki").into_response()
+        }
+    }
+}
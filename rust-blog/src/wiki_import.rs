@@ -0,0 +1,557 @@
+//! Imports a mkdocs documentation tree (such as `docs/en` or `docs/zh`) into
+//! the `wiki_pages` table.
+//!
+//! The nav in `mkdocs.yml` decides which pages are imported and in what order.
+//! Page paths mirror the Markdown file layout, so
+//! `pwn/linux/user-mode/stackoverflow/x86/basic-rop.md` becomes the wiki page
+//! `pwn/linux/user-mode/stackoverflow/x86/basic-rop`. Directories without an
+//! `index.md` become section pages titled after the nav section they belong to.
+//! Re-importing the same tree is idempotent: pages are matched on their
+//! language and path and only rewritten when something changed. Pages edited
+//! in the app since they were last imported are left alone and reported as
+//! conflicts, unless the import is forced.
+//!
+//! Each tree is imported in one language, taken from the mkdocs `site_url`
+//! (`https://ctf-wiki.org/zh-tw/`) or `theme.language` unless given
+//! explicitly. Pages at the same path in different languages are linked as
+//! translations of each other.
+
+use crate::database::Database;
+use crate::i18n::{normalize_lang, DEFAULT_LANG};
+use crate::models::{UpsertWikiPage, WikiImportReport};
+use crate::wiki::{parent_path, wiki_url};
+use anyhow::{anyhow, Context, Result};
+use regex::{Captures, Regex};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+pub const DEFAULT_ASSET_BASE_URL: &str = "/wiki-assets";
+
+pub struct ImportOptions {
+    pub mkdocs_path: PathBuf,
+    pub path_prefix: String,
+    pub asset_base_url: String,
+    /// Language of the tree; detected from `mkdocs.yml` when `None`.
+    pub lang: Option<String>,
+    /// Overwrite pages edited in the app since they were last imported.
+    pub force: bool,
+}
+
+enum NavItem {
+    Page { title: Option<String>, file: String },
+    Section { title: String, children: Vec<NavItem> },
+}
+
+struct PendingPage {
+    path: String,
+    title: String,
+    content: String,
+    source_path: Option<String>,
+    nav_order: i32,
+}
+
+pub async fn import_mkdocs(db: &Database, options: &ImportOptions) -> Result<WikiImportReport> {
+    let config_text = std::fs::read_to_string(&options.mkdocs_path)
+        .with_context(|| format!("failed to read {}", options.mkdocs_path.display()))?;
+    let config: Value = serde_yaml::from_str(&config_text)?;
+
+    let base_dir = options.mkdocs_path.parent().unwrap_or(Path::new("."));
+    let docs_dir = base_dir.join(config.get("docs_dir").and_then(Value::as_str).unwrap_or("docs"));
+    let site_name = config.get("site_name").and_then(Value::as_str).unwrap_or("Wiki");
+
+    let nav = config
+        .get("nav")
+        .and_then(Value::as_sequence)
+        .ok_or_else(|| anyhow!("mkdocs.yml has no nav"))?;
+    let nav = parse_nav(nav);
+
+    let lang = match &options.lang {
+        Some(lang) => normalize_lang(lang).ok_or_else(|| anyhow!("unsupported language: {}", lang))?,
+        None => detect_lang(&config),
+    };
+
+    let prefix = options.path_prefix.trim_matches('/');
+    let mut report = WikiImportReport {
+        lang: lang.to_string(),
+        ..WikiImportReport::default()
+    };
+    let mut pages: BTreeMap<String, PendingPage> = BTreeMap::new();
+    let mut section_titles: HashMap<String, String> = HashMap::new();
+    let mut order = 0;
+
+    collect_pages(&nav, &mut order, &mut |file, title, nav_order| {
+        let source = docs_dir.join(file);
+        let Ok(raw) = std::fs::read_to_string(&source) else {
+            report.missing_files.push(file.to_string());
+            return;
+        };
+
+        let (front_matter_title, body) = split_front_matter(&raw);
+        let title = front_matter_title
+            .or_else(|| title.map(str::to_string))
+            .or_else(|| first_heading(body))
+            .unwrap_or_else(|| humanize(file_stem(file)));
+        let path = prefixed(prefix, &wiki_path_for_file(file));
+
+        pages.insert(path.clone(), PendingPage {
+            path,
+            title,
+            content: rewrite_links(body, file, lang, prefix, &options.asset_base_url),
+            source_path: Some(file.to_string()),
+            nav_order,
+        });
+    });
+    claim_section_titles(&nav, &mut section_titles);
+
+    // Fill in a section page for every directory that has no page of its own
+    let page_paths: Vec<(String, i32)> = pages.values().map(|p| (p.path.clone(), p.nav_order)).collect();
+    for (path, nav_order) in page_paths {
+        let mut relative = strip_prefix(prefix, &path).to_string();
+        while !relative.is_empty() {
+            relative = parent_path(&relative).to_string();
+            let section_path = prefixed(prefix, &relative);
+            let section = pages.entry(section_path.clone()).or_insert_with(|| PendingPage {
+                path: section_path,
+                title: if relative.is_empty() {
+                    site_name.to_string()
+                } else {
+                    section_titles
+                        .get(&relative)
+                        .cloned()
+                        .unwrap_or_else(|| humanize(last_segment(&relative)))
+                },
+                content: String::new(),
+                source_path: None,
+                nav_order,
+            });
+            if section.source_path.is_none() {
+                section.nav_order = section.nav_order.min(nav_order);
+            }
+        }
+    }
+
+    // Parents sort before their children, so every parent id is known in time
+    let mut pending: Vec<PendingPage> = pages.into_values().collect();
+    pending.sort_by_key(|page| (depth(&page.path), page.nav_order));
+
+    let mut ids: HashMap<String, uuid::Uuid> = HashMap::new();
+    for page in pending {
+        let parent_id = if page.path == prefix {
+            None
+        } else {
+            ids.get(&prefixed(prefix, parent_path(strip_prefix(prefix, &page.path)))).copied()
+        };
+        let upsert = UpsertWikiPage {
+            parent_id,
+            path: page.path.clone(),
+            lang: lang.to_string(),
+            title: page.title,
+            content: page.content,
+            source_path: page.source_path,
+            nav_order: page.nav_order,
+        };
+
+        let saved = match db.get_wiki_page_by_path(lang, &page.path).await? {
+            Some(existing)
+                if existing.parent_id == upsert.parent_id
+                    && existing.title == upsert.title
+                    && existing.content == upsert.content
+                    && existing.source_path == upsert.source_path
+                    && existing.nav_order == upsert.nav_order =>
+            {
+                report.unchanged += 1;
+                existing
+            }
+            Some(existing) => {
+                let edited = !options.force && db.wiki_page_edited_since_import(existing.id).await?;
+                let updated = if edited {
+                    None
+                } else {
+                    db.update_imported_wiki_page(existing.id, existing.revision, upsert).await?
+                };
+                match updated {
+                    Some(updated) => {
+                        report.updated += 1;
+                        updated
+                    }
+                    None => {
+                        report.conflicts.push(existing.path.clone());
+                        existing
+                    }
+                }
+            }
+            None => {
+                report.created += 1;
+                db.create_wiki_page(upsert, None, Some("Imported from mkdocs")).await?
+            }
+        };
+        ids.insert(saved.path, saved.id);
+    }
+
+    Ok(report)
+}
+
+/// The language a mkdocs tree is written in: the last segment of its
+/// `site_url` when that names a supported language, else `theme.language`.
+fn detect_lang(config: &Value) -> &'static str {
+    let from_site_url = config
+        .get("site_url")
+        .and_then(Value::as_str)
+        .and_then(|url| url.trim_end_matches('/').rsplit('/').next())
+        .and_then(normalize_lang);
+    let from_theme = config
+        .get("theme")
+        .and_then(|theme| theme.get("language"))
+        .and_then(Value::as_str)
+        .and_then(normalize_lang);
+    from_site_url.or(from_theme).unwrap_or(DEFAULT_LANG)
+}
+
+fn parse_nav(items: &[Value]) -> Vec<NavItem> {
+    let mut nav = Vec::new();
+    for item in items {
+        match item {
+            Value::String(file) => nav.push(NavItem::Page { title: None, file: file.clone() }),
+            Value::Mapping(mapping) => {
+                for (key, value) in mapping {
+                    let Some(title) = key.as_str() else { continue };
+                    match value {
+                        Value::String(file) => nav.push(NavItem::Page {
+                            title: Some(title.to_string()),
+                            file: file.clone(),
+                        }),
+                        Value::Sequence(children) => nav.push(NavItem::Section {
+                            title: title.to_string(),
+                            children: parse_nav(children),
+                        }),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    nav
+}
+
+fn collect_pages(nav: &[NavItem], order: &mut i32, visit: &mut impl FnMut(&str, Option<&str>, i32)) {
+    for item in nav {
+        match item {
+            NavItem::Page { title, file } => {
+                // External links in the nav are not part of the docs tree
+                if file.contains("://") || !file.ends_with(".md") {
+                    continue;
+                }
+                visit(file, title.as_deref(), *order);
+                *order += 1;
+            }
+            NavItem::Section { children, .. } => collect_pages(children, order, visit),
+        }
+    }
+}
+
+/// Titles directories after the outermost nav section whose pages all live
+/// under them, e.g. "User Mode" for `pwn/linux/user-mode`.
+fn claim_section_titles(nav: &[NavItem], titles: &mut HashMap<String, String>) {
+    for item in nav {
+        if let NavItem::Section { title, children } = item {
+            let mut files = Vec::new();
+            section_files(children, &mut files);
+            let dir = common_dir(&files);
+            if !dir.is_empty() {
+                titles.entry(dir).or_insert_with(|| title.clone());
+            }
+            claim_section_titles(children, titles);
+        }
+    }
+}
+
+fn section_files<'a>(nav: &'a [NavItem], files: &mut Vec<&'a str>) {
+    for item in nav {
+        match item {
+            NavItem::Page { file, .. } if file.ends_with(".md") => files.push(file),
+            NavItem::Page { .. } => {}
+            NavItem::Section { children, .. } => section_files(children, files),
+        }
+    }
+}
+
+fn common_dir(files: &[&str]) -> String {
+    let mut common: Option<Vec<&str>> = None;
+    for file in files {
+        let dirs: Vec<&str> = file.split('/').collect();
+        let dirs = &dirs[..dirs.len() - 1];
+        common = Some(match common {
+            None => dirs.to_vec(),
+            Some(prev) => prev.iter().zip(dirs).take_while(|(a, b)| a == b).map(|(a, _)| *a).collect(),
+        });
+    }
+    common.unwrap_or_default().join("/")
+}
+
+/// Splits a leading `---` YAML block off a page, with either line ending,
+/// returning its `title` and the rest of the page.
+fn split_front_matter(raw: &str) -> (Option<String>, &str) {
+    let Some(rest) = raw.strip_prefix("---\n").or_else(|| raw.strip_prefix("---\r\n")) else {
+        return (None, raw);
+    };
+
+    let mut end = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']) == "---" {
+            let title = serde_yaml::from_str::<Value>(&rest[..end])
+                .ok()
+                .and_then(|meta| meta.get("title").and_then(Value::as_str).map(str::to_string));
+            return (title, &rest[end + line.len()..]);
+        }
+        end += line.len();
+    }
+    (None, raw)
+}
+
+fn first_heading(content: &str) -> Option<String> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .map(|heading| heading.trim().to_string())
+        .filter(|heading| !heading.is_empty())
+}
+
+/// Rewrites relative Markdown links so they keep working once the page is
+/// served from `/<lang>/wiki/...`: links to other `.md` files point at their wiki
+/// page and links to images or other files point at `asset_base_url`.
+fn rewrite_links(content: &str, source_file: &str, lang: &str, prefix: &str, asset_base_url: &str) -> String {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    let link = LINK.get_or_init(|| Regex::new(r#"(!?\[[^\]]*\])\(([^)\s]+)(\s+"[^"]*")?\)"#).unwrap());
+
+    let source_dir = parent_path(source_file);
+    let mut in_code_block = false;
+    let mut output = String::with_capacity(content.len());
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+        }
+        if in_code_block {
+            output.push_str(line);
+            continue;
+        }
+
+        let rewritten = link.replace_all(line, |caps: &Captures| {
+            let target = &caps[2];
+            let (file, anchor) = match target.split_once('#') {
+                Some((file, anchor)) => (file, format!("#{}", anchor)),
+                None => (target, String::new()),
+            };
+            let is_relative_file = !file.is_empty()
+                && !file.contains("://")
+                && !file.starts_with('/')
+                && !file.starts_with("mailto:")
+                && last_segment(file).contains('.');
+
+            let Some(resolved) = is_relative_file.then(|| resolve_relative(source_dir, file)).flatten() else {
+                return caps[0].to_string();
+            };
+            let url = if resolved.ends_with(".md") {
+                wiki_url(lang, &prefixed(prefix, &wiki_path_for_file(&resolved)))
+            } else {
+                format!("{}/{}", asset_base_url.trim_end_matches('/'), resolved)
+            };
+            format!("{}({}{}{})", &caps[1], url, anchor, caps.get(3).map_or("", |m| m.as_str()))
+        });
+        output.push_str(&rewritten);
+    }
+
+    output
+}
+
+fn resolve_relative(base_dir: &str, target: &str) -> Option<String> {
+    let mut segments: Vec<&str> = base_dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+fn wiki_path_for_file(file: &str) -> String {
+    let path = file.trim_end_matches(".md");
+    if path == "index" {
+        String::new()
+    } else {
+        path.strip_suffix("/index").unwrap_or(path).to_string()
+    }
+}
+
+fn prefixed(prefix: &str, path: &str) -> String {
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => path.to_string(),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
+
+fn strip_prefix<'a>(prefix: &str, path: &'a str) -> &'a str {
+    if prefix.is_empty() {
+        return path;
+    }
+    path.strip_prefix(prefix)
+        .map(|rest| rest.trim_start_matches('/'))
+        .unwrap_or(path)
+}
+
+fn last_segment(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn file_stem(file: &str) -> &str {
+    last_segment(file).trim_end_matches(".md")
+}
+
+fn depth(path: &str) -> usize {
+    if path.is_empty() {
+        0
+    } else {
+        path.split('/').count()
+    }
+}
+
+fn humanize(name: &str) -> String {
+    name.split(['-', '_'])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(source: &str) -> Value {
+        serde_yaml::from_str(source).unwrap()
+    }
+
+    fn outline(nav: &[NavItem]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for item in nav {
+            match item {
+                NavItem::Page { title, file } => {
+                    lines.push(format!("{} = {}", title.as_deref().unwrap_or("-"), file))
+                }
+                NavItem::Section { title, children } => {
+                    lines.push(format!("{}:", title));
+                    lines.extend(outline(children).into_iter().map(|line| format!("  {}", line)));
+                }
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn nav_keeps_nesting_and_order() {
+        let config = yaml(
+            "nav:\n\
+             - index.md\n\
+             - Pwn:\n  \
+               - pwn/index.md\n  \
+               - Linux:\n    \
+                 - Stack: pwn/linux/stack.md\n    \
+                 - Heap: pwn/linux/heap.md\n  \
+               - Forum: https://example.com/forum\n\
+             - About: about.md\n",
+        );
+        let nav = parse_nav(config["nav"].as_sequence().unwrap());
+        assert_eq!(
+            outline(&nav),
+            [
+                "- = index.md",
+                "Pwn:",
+                "  - = pwn/index.md",
+                "  Linux:",
+                "    Stack = pwn/linux/stack.md",
+                "    Heap = pwn/linux/heap.md",
+                "  Forum = https://example.com/forum",
+                "About = about.md",
+            ]
+        );
+
+        let mut pages = Vec::new();
+        collect_pages(&nav, &mut 0, &mut |file, _, order| pages.push((file.to_string(), order)));
+        let files: Vec<&str> = pages.iter().map(|(file, _)| file.as_str()).collect();
+        assert_eq!(files, ["index.md", "pwn/index.md", "pwn/linux/stack.md", "pwn/linux/heap.md", "about.md"]);
+        assert_eq!(pages.last().unwrap().1, 4);
+
+        let mut titles = HashMap::new();
+        claim_section_titles(&nav, &mut titles);
+        assert_eq!(titles.get("pwn").map(String::as_str), Some("Pwn"));
+        assert_eq!(titles.get("pwn/linux").map(String::as_str), Some("Linux"));
+    }
+
+    #[test]
+    fn language_comes_from_site_url_then_theme() {
+        assert_eq!(detect_lang(&yaml("site_url: https://ctf-wiki.org/zh-tw/\ntheme: {language: en}")), "zh-tw");
+        assert_eq!(detect_lang(&yaml("site_url: https://ctf-wiki.org/\ntheme: {language: zh}")), "zh");
+        assert_eq!(detect_lang(&yaml("theme: {name: material}")), DEFAULT_LANG);
+    }
+
+    #[test]
+    fn front_matter_is_split_with_either_line_ending() {
+        let (title, body) = split_front_matter("---\ntitle: Basic ROP\n---\n# Heading\n");
+        assert_eq!(title.as_deref(), Some("Basic ROP"));
+        assert_eq!(body, "# Heading\n");
+
+        let (title, body) = split_front_matter("---\r\ntitle: Basic ROP\r\ntags: [pwn]\r\n---\r\n# Heading\r\n");
+        assert_eq!(title.as_deref(), Some("Basic ROP"));
+        assert_eq!(body, "# Heading\r\n");
+
+        let (title, body) = split_front_matter("---\n---\nbody");
+        assert_eq!(title, None);
+        assert_eq!(body, "body");
+
+        let unterminated = "---\ntitle: x\nbody\n";
+        assert_eq!(split_front_matter(unterminated), (None, unterminated));
+        assert_eq!(split_front_matter("text\n---\n"), (None, "text\n---\n"));
+    }
+
+    #[test]
+    fn relative_page_links_point_at_the_wiki() {
+        let content = "See [ROP](../rop.md#gadgets \"ROP\"), [index](./index.md) and [root](../../index.md).\n";
+        assert_eq!(
+            rewrite_links(content, "pwn/linux/stack.md", "zh", "ctf", "https://cdn.example.com/docs/"),
+            "See [ROP](/zh/wiki/ctf/pwn/rop#gadgets \"ROP\"), [index](/zh/wiki/ctf/pwn/linux) \
+             and [root](/zh/wiki/ctf).\n"
+        );
+    }
+
+    #[test]
+    fn assets_point_at_the_asset_base_url() {
+        let content = "![stack](figure/stack.png)\n[exploit](../files/exp.py)\n";
+        assert_eq!(
+            rewrite_links(content, "pwn/linux/stack.md", "en", "", "https://cdn.example.com/docs/"),
+            "![stack](https://cdn.example.com/docs/pwn/linux/figure/stack.png)\n\
+             [exploit](https://cdn.example.com/docs/pwn/files/exp.py)\n"
+        );
+    }
+
+    #[test]
+    fn other_links_and_code_blocks_are_left_alone() {
+        let content = "[site](https://example.com/a.md) [top](/x.md) [anchor](#top) [dir](pwn/)\n\
+                       ```\n[code](a.md)\n```\n[escape](../../../a.md)\n";
+        assert_eq!(rewrite_links(content, "pwn/stack.md", "en", "", "https://cdn"), content);
+    }
+}
@@ -0,0 +1,100 @@
+// "Discuss this page" widget for the static mkdocs site.
+//
+// Add the script to mkdocs.yml:
+//
+//   extra_javascript:
+//     - https://blog.example.com/static/discussion-widget.js
+//
+// The blog URL is taken from the script's own URL. The page's language and wiki
+// path come from the site URL (/en/pwn/... or /zh-tw/pwn/..., with zh at the
+// root) and can be overridden by setting window.discussionWidgetConfig to
+// { api, lang, path } before the script runs.
+(function() {
+    const SUPPORTED_LANGS = ['en', 'zh', 'zh-tw'];
+    const script = document.currentScript;
+    const config = window.discussionWidgetConfig || {};
+    const api = (config.api || new URL(script.src).origin).replace(/\/$/, '');
+
+    function currentPage() {
+        let segments = window.location.pathname
+            .replace(/index\.html$/, '')
+            .split('/')
+            .filter(Boolean);
+        let lang = 'zh';
+        if (SUPPORTED_LANGS.includes(segments[0])) {
+            lang = segments[0];
+            segments = segments.slice(1);
+        }
+        return {
+            lang: config.lang || lang,
+            path: config.path !== undefined ? config.path : segments.join('/'),
+        };
+    }
+
+    function escapeHtml(text) {
+        const div = document.createElement('div');
+        div.textContent = text;
+        return div.innerHTML;
+    }
+
+    function truncate(text, length) {
+        return text.length > length ? text.slice(0, length) + '...' : text;
+    }
+
+    async function render() {
+        const page = currentPage();
+        const container = document.querySelector('article') || document.body;
+        let widget = document.getElementById('discussion');
+        if (!widget) {
+            widget = document.createElement('section');
+            widget.id = 'discussion';
+            container.appendChild(widget);
+        }
+
+        const params = new URLSearchParams({ content_type: 'wiki', lang: page.lang, path: page.path });
+        const pageUrl = `${api}/${page.lang}/wiki${page.path ? '/' + page.path : ''}#discussion`;
+
+        try {
+            const response = await fetch(`${api}/api/discussions?${params}`);
+            if (response.status === 404) {
+                widget.innerHTML = `
+                    <h2>Discussion</h2>
+                    <p>No one has asked about this page yet. <a href="${pageUrl}">Discuss this page</a></p>
+                `;
+                return;
+            }
+            if (!response.ok) {
+                widget.innerHTML = '';
+                return;
+            }
+
+            const discussion = await response.json();
+            const topicUrl = api + discussion.url;
+            widget.innerHTML = `
+                <h2>Discussion</h2>
+                <p><a href="${topicUrl}">${discussion.replies_count} ${discussion.replies_count === 1 ? 'reply' : 'replies'}</a> in the forum</p>
+                <ul>
+                    ${discussion.latest_replies.map(reply => `
+                        <li>
+                            <strong>${escapeHtml(reply.user.username)}</strong>
+                            <span>${new Date(reply.created_at).toLocaleDateString()}</span>
+                            <p>${escapeHtml(truncate(reply.content, 200))}</p>
+                        </li>
+                    `).join('')}
+                </ul>
+                <p><a href="${topicUrl}">Join the discussion</a></p>
+            `;
+        } catch (error) {
+            console.error('Error loading discussion:', error);
+        }
+    }
+
+    // Material for MkDocs swaps pages without reloading when instant loading is on
+    if (window.document$) {
+        window.document$.subscribe(render);
+    } else if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', render);
+    } else {
+        render();
+    }
+})();
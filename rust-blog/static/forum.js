@@ -61,7 +61,7 @@ function initializeSearch() {
             if (query.length >= 2) {
                 searchTimeout = setTimeout(() => {
                     performSearch(query);
-                }, 300);
+                }, 100);
             } else if (searchResults) {
                 searchResults.style.display = 'none';
             }
@@ -78,7 +78,7 @@ function initializeSearch() {
 
 async function performSearch(query) {
     try {
-        const response = await fetch(`/api/forum/search?q=${encodeURIComponent(query)}`);
+        const response = await fetch(`/api/search/suggest?q=${encodeURIComponent(query)}`);
         const results = await response.json();
 
         displaySearchResults(results);
@@ -91,20 +91,27 @@ function displaySearchResults(results) {
     const searchResults = document.querySelector('.search-results');
     if (!searchResults) return;
 
-    if (results.length === 0) {
-        searchResults.innerHTML = '<div class="search-empty">No results found</div>';
+    const searchUrl = query => `/forum/search?q=${encodeURIComponent(query)}`;
+
+    if (results.suggestions.length === 0) {
+        searchResults.innerHTML = results.did_you_mean
+            ? `<div class="search-empty">Did you mean <a href="${searchUrl(results.did_you_mean)}">${escapeHtml(results.did_you_mean)}</a>?</div>`
+            : '<div class="search-empty">No results found</div>';
     } else {
-        const resultsHtml = results.map(result => `
+        const resultsHtml = results.suggestions.map(suggestion => `
             <div class="search-result-item">
-                <a href="${result.url}" class="search-result-link">
-                    <h4 class="search-result-title">${escapeHtml(result.title)}</h4>
-                    <p class="search-result-snippet">${escapeHtml(result.snippet)}</p>
-                    <span class="search-result-type">${result.type}</span>
+                <a href="${suggestion.url}" class="search-result-link">
+                    <h4 class="search-result-title">${escapeHtml(suggestion.text)}</h4>
+                    <span class="search-result-type">${suggestion.type}</span>
                 </a>
             </div>
         `).join('');
 
-        searchResults.innerHTML = resultsHtml;
+        searchResults.innerHTML = resultsHtml + `
+            <a href="${searchUrl(results.query)}" class="search-result-link search-all">
+                Search for "${escapeHtml(results.query)}"
+            </a>
+        `;
     }
 
     searchResults.style.display = 'block';
@@ -120,6 +127,10 @@ function initializeRealTime() {
     // Update last seen timestamp
     updateLastSeen();
     setInterval(updateLastSeen, 60000); // Every minute
+
+    // Keep the unread notification count current
+    refreshNotificationBadge();
+    setInterval(refreshNotificationBadge, 60000);
 }
 
 async function checkForNewReplies() {
@@ -181,6 +192,32 @@ async function updateLastSeen() {
     }
 }
 
+async function refreshNotificationBadge() {
+    const token = localStorage.getItem('token');
+    if (!token) return;
+
+    try {
+        const response = await fetch('/api/notifications/unread-count', {
+            headers: {
+                'Authorization': `Bearer ${token}`
+            }
+        });
+        if (response.ok) {
+            updateNotificationBadge((await response.json()).unread);
+        }
+    } catch (error) {
+        console.error('Error checking notifications:', error);
+    }
+}
+
+function updateNotificationBadge(unread) {
+    const badge = document.querySelector('.notification-count');
+    if (!badge) return;
+
+    badge.textContent = unread > 99 ? '99+' : unread;
+    badge.hidden = unread === 0;
+}
+
 // Keyboard shortcuts
 function initializeKeyboardShortcuts() {
     document.addEventListener('keydown', function(e) {
@@ -325,43 +362,28 @@ function updateTimestamps() {
 setInterval(updateTimestamps, 60000);
 
 // Topic and reply interactions
+// POST likes and DELETE unlikes, chosen from the button's current state
 async function likeTopic(topicId) {
-    const token = localStorage.getItem('token');
-    if (!token) {
-        showLoginPrompt();
-        return;
-    }
-
-    try {
-        const response = await fetch(`/api/forum/topics/${topicId}/like`, {
-            method: 'POST',
-            headers: {
-                'Authorization': `Bearer ${token}`
-            }
-        });
-
-        if (response.ok) {
-            const result = await response.json();
-            updateLikeButton(topicId, result.liked, result.count);
-        } else {
-            throw new Error('Failed to like topic');
-        }
-    } catch (error) {
-        console.error('Error liking topic:', error);
-        showNotification('Failed to like topic', 'error');
-    }
+    await toggleLike(`/api/forum/topics/${topicId}/like`, topicId, 'topic');
 }
 
 async function likeReply(replyId) {
+    await toggleLike(`/api/forum/replies/${replyId}/like`, replyId, 'reply');
+}
+
+async function toggleLike(url, id, kind) {
     const token = localStorage.getItem('token');
     if (!token) {
         showLoginPrompt();
         return;
     }
 
+    const button = document.querySelector(`[onclick*="${id}"]`);
+    const liked = button && button.classList.contains('liked');
+
     try {
-        const response = await fetch(`/api/forum/replies/${replyId}/like`, {
-            method: 'POST',
+        const response = await fetch(url, {
+            method: liked ? 'DELETE' : 'POST',
             headers: {
                 'Authorization': `Bearer ${token}`
             }
@@ -369,13 +391,13 @@ async function likeReply(replyId) {
 
         if (response.ok) {
             const result = await response.json();
-            updateLikeButton(replyId, result.liked, result.count);
+            updateLikeButton(id, result.liked, result.count);
         } else {
-            throw new Error('Failed to like reply');
+            throw new Error(await response.text() || `Failed to like ${kind}`);
         }
     } catch (error) {
-        console.error('Error liking reply:', error);
-        showNotification('Failed to like reply', 'error');
+        console.error(`Error liking ${kind}:`, error);
+        showNotification(error.message, 'error');
     }
 }
 
@@ -392,6 +414,43 @@ function updateLikeButton(id, liked, count) {
         } else {
             button.classList.remove('liked');
         }
+
+        // The list of likers changed
+        delete button.dataset.likersLoaded;
+    }
+}
+
+// Name the people who liked a reply when hovering its like button
+document.addEventListener('mouseover', async function(e) {
+    const button = e.target.closest('[data-likers]');
+    if (!button || button.dataset.likersLoaded) return;
+
+    const count = button.querySelector('.count');
+    if (!count || count.textContent.trim() === '0') return;
+
+    button.dataset.likersLoaded = 'true';
+    try {
+        const response = await fetch(button.dataset.likers);
+        if (!response.ok) return;
+
+        const likers = await response.json();
+        button.title = 'Liked by ' + likers.map(liker => liker.display_name || liker.username).join(', ');
+    } catch (error) {
+        delete button.dataset.likersLoaded;
+    }
+});
+
+// The signed-in user from the stored token, or null
+function currentUser() {
+    const token = localStorage.getItem('token');
+    if (!token) return null;
+
+    try {
+        const payload = token.split('.')[1].replace(/-/g, '+').replace(/_/g, '/');
+        const claims = JSON.parse(atob(payload));
+        return claims.exp * 1000 > Date.now() ? claims : null;
+    } catch (error) {
+        return null;
     }
 }
 
@@ -532,6 +591,7 @@ console.log('Forum JavaScript initialized');
 // Export functions for global use
 window.forumJS = {
     toggleUserMenu,
+    currentUser,
     performSearch,
     likeTopic,
     likeReply,